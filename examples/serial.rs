@@ -4,7 +4,7 @@ fn main() {
         println!("{}", p.port_name);
     }
 
-    let port = serialport::new("/dev/ttyUSB0", 115_200)
+    let _port = serialport::new("/dev/ttyUSB0", 115_200)
         .timeout(std::time::Duration::from_millis(10))
         .open()
         .expect("Failed to open port");
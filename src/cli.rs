@@ -0,0 +1,423 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{self, history, ConfigFile},
+    namespace,
+    plugins::{manager, mqtt, state_file},
+    schema,
+};
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Run the controller, the default without a command
+    Run,
+    /// Print the JSON Schemas of every config file and MQTT message
+    Schema {
+        /// write `<dir>/<name>.json` files instead
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Inspect and change config files
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Inspect and reset the state saved between runs
+    #[command(subcommand)]
+    State(StateCommand),
+    /// Inspect the cache of messages published while the broker was unreachable
+    #[command(subcommand)]
+    Cache(CacheCommand),
+}
+
+#[derive(clap::Subcommand)]
+pub enum ConfigCommand {
+    /// Print a config as the app would load it, all configs without NAME
+    Show { name: Option<String> },
+    /// Check configs for errors, exits with an error if any is invalid
+    Validate { name: Option<String> },
+    /// Write default configs, keeps existing files unless forced
+    Init {
+        name: Option<String>,
+        /// replace existing files
+        #[arg(long)]
+        force: bool,
+    },
+    /// Change a single field of a config, e.g. `set mqtt_client create_options.server_uri
+    /// 10.42.0.2:1883`
+    Set {
+        name: String,
+        /// path of an existing field, e.g. `zones[1].spray_duration` or `zones.1.spray_duration`
+        key: String,
+        /// JSON value, taken as a string if it isn't valid JSON
+        value: String,
+    },
+}
+
+// state files are rewritten by the app whenever its state changes, stop it first
+#[derive(clap::Subcommand)]
+pub enum StateCommand {
+    /// Print saved states, all of them without NAME
+    Show { name: Option<String> },
+    /// Delete saved states, the app starts from its defaults again
+    Clear {
+        #[arg(required_unless_present = "all")]
+        name: Option<String>,
+        /// delete every saved state
+        #[arg(long, conflicts_with = "name")]
+        all: bool,
+    },
+}
+
+#[derive(clap::Subcommand)]
+pub enum CacheCommand {
+    /// Print the number, age and size of cached messages
+    Stats,
+    /// Discard every cached message
+    Flush,
+    /// Print cached messages as JSON lines, oldest first
+    Export {
+        /// write to FILE instead
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+/// Runs a maintenance command, anything but [`Command::Run`].
+pub fn execute(command: Command) -> anyhow::Result<()> {
+    match command {
+        Command::Run => unreachable!("the app runs from main"),
+        Command::Schema { out } => schema::export(out.as_deref()),
+        Command::Config(command) => local::config(command),
+        Command::State(command) => local::state(command),
+        Command::Cache(command) => local::cache(command),
+    }
+}
+
+mod local {
+    use super::*;
+
+    /// config serialized as JSON, with the version its file was written in
+    type Versioned = (serde_json::Value, u32);
+
+    /// Operations on one config file, instantiated for each [`ConfigFile`].
+    struct ConfigFileCmd {
+        name: &'static str,
+        filepath: fn() -> PathBuf,
+        version: fn() -> u32,
+        default: fn() -> serde_json::Value,
+        read: fn() -> Result<Option<Versioned>, config::Error>,
+        /// parses and validates a whole config before saving it
+        save: fn(serde_json::Value, history::Source) -> anyhow::Result<()>,
+        /// a running app applies saved changes right away, see [`config::HotReload`]
+        live: bool,
+    }
+    impl ConfigFileCmd {
+        fn of<T: ConfigFile>() -> Self {
+            Self {
+                name: T::FILENAME,
+                filepath: T::config_filepath,
+                version: T::config_version,
+                default: || serde_json::to_value(T::Config::default()).unwrap(),
+                read: || {
+                    T::read_config().map(|config| {
+                        config.map(|(config, version)| {
+                            (serde_json::to_value(config).unwrap(), version)
+                        })
+                    })
+                },
+                save: |value, source| {
+                    let (config, _) =
+                        T::parse_config(&serde_json::to_vec(&value)?, T::config_version())
+                            .map_err(|errors| config::Error::Invalid {
+                                path: T::config_filepath(),
+                                errors,
+                            })?;
+                    Ok(T::save_config(config, source)?)
+                },
+                live: false,
+            }
+        }
+
+        fn live<T: config::HotReload>() -> Self {
+            Self {
+                live: true,
+                ..Self::of::<T>()
+            }
+        }
+
+        /// when a saved change takes effect
+        fn applied(&self) -> &'static str {
+            if self.live {
+                "applied right away if the app is running, otherwise on the next start"
+            } else {
+                "applied on the next start"
+            }
+        }
+
+        /// Config the app would load, the default if the file doesn't exist yet.
+        fn effective(&self) -> anyhow::Result<serde_json::Value> {
+            Ok((self.read)()?.map_or_else(self.default, |(config, _)| config))
+        }
+    }
+
+    fn config_files() -> [ConfigFileCmd; 6] {
+        [
+            ConfigFileCmd::of::<namespace::Namespace>(),
+            ConfigFileCmd::of::<mqtt::Plugin>(),
+            ConfigFileCmd::live::<manager::AeroponicSprayManager>(),
+            ConfigFileCmd::live::<manager::PhDosingManager>(),
+            ConfigFileCmd::live::<manager::GrowlightManager>(),
+            ConfigFileCmd::live::<manager::NutrientDosingManager>(),
+        ]
+    }
+
+    /// Config files named `name`, all of them without one.
+    fn select(name: Option<&str>) -> anyhow::Result<Vec<ConfigFileCmd>> {
+        let files = config_files();
+        match name {
+            None => Ok(files.into()),
+            Some(name) => {
+                let names = files.iter().map(|file| file.name).collect::<Vec<_>>();
+                let file = files
+                    .into_iter()
+                    .find(|file| file.name == name)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "unknown config '{name}', expected one of {}",
+                            names.join(", ")
+                        )
+                    })?;
+                Ok(vec![file])
+            }
+        }
+    }
+
+    pub fn config(command: ConfigCommand) -> anyhow::Result<()> {
+        match command {
+            ConfigCommand::Show { name } => {
+                let value = match name {
+                    Some(name) => select(Some(&name))?.remove(0).effective()?,
+                    None => serde_json::Value::Object(
+                        config_files()
+                            .iter()
+                            .map(|file| Ok((file.name.to_string(), file.effective()?)))
+                            .collect::<anyhow::Result<_>>()?,
+                    ),
+                };
+                println!("{}", serde_json::to_string_pretty(&value)?);
+            }
+            ConfigCommand::Validate { name } => {
+                let mut invalid = 0;
+                for file in select(name.as_deref())? {
+                    let filepath = (file.filepath)();
+                    match (file.read)() {
+                        Ok(None) => println!(
+                            "{}: missing, defaults are written when the app loads it",
+                            filepath.display()
+                        ),
+                        Ok(Some((_, version))) if version < (file.version)() => println!(
+                            "{}: valid, upgraded from version {version} to {} when the app loads it",
+                            filepath.display(),
+                            (file.version)()
+                        ),
+                        Ok(Some(_)) => println!("{}: valid", filepath.display()),
+                        Err(e) => {
+                            invalid += 1;
+                            println!("{e}");
+                        }
+                    }
+                }
+                anyhow::ensure!(invalid == 0, "{invalid} invalid config file(s)");
+            }
+            ConfigCommand::Init { name, force } => {
+                for file in select(name.as_deref())? {
+                    let filepath = (file.filepath)();
+                    let source = if !filepath.exists() {
+                        history::Source::Default
+                    } else if force {
+                        history::Source::Cli
+                    } else {
+                        println!("{}: exists, kept", filepath.display());
+                        continue;
+                    };
+
+                    (file.save)((file.default)(), source)?;
+                    println!("{}: defaults written", filepath.display());
+                }
+            }
+            ConfigCommand::Set { name, key, value } => {
+                let file = select(Some(&name))?.remove(0);
+                let mut config = file.effective()?;
+
+                let field = field_mut(&mut config, &key)?;
+                *field = serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value));
+                let field = field.clone();
+
+                (file.save)(config, history::Source::Cli)?;
+                println!(
+                    "{}: {key} = {field}, {}",
+                    (file.filepath)().display(),
+                    file.applied()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Existing field of `value` at `key`, dot separated with `[i]` or `.i` indices.
+    fn field_mut<'a>(
+        mut value: &'a mut serde_json::Value,
+        key: &str,
+    ) -> anyhow::Result<&'a mut serde_json::Value> {
+        let normalized = key.replace('[', ".").replace(']', "");
+        for segment in normalized.split('.').filter(|segment| !segment.is_empty()) {
+            value = match value {
+                serde_json::Value::Object(fields) => fields.get_mut(segment),
+                serde_json::Value::Array(items) => segment
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| items.get_mut(index)),
+                _ => None,
+            }
+            .ok_or_else(|| anyhow::anyhow!("no field '{segment}' in '{key}'"))?;
+        }
+
+        Ok(value)
+    }
+
+    fn state_files(dir: &Path) -> anyhow::Result<Vec<(String, PathBuf)>> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut files = std::fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<std::io::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .map(|path| {
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                (name, path)
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+
+        Ok(files)
+    }
+
+    pub fn state(command: StateCommand) -> anyhow::Result<()> {
+        let dir = state_file::Plugin::default().directory();
+        let files = state_files(&dir)?;
+
+        let select = |name: Option<&str>| -> anyhow::Result<Vec<(String, PathBuf)>> {
+            match name {
+                None => Ok(files.clone()),
+                Some(name) => {
+                    let file = files.iter().find(|(file, _)| file == name).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "no state '{name}' in \"{}\", saved states: {}",
+                            dir.display(),
+                            files
+                                .iter()
+                                .map(|(name, _)| name.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })?;
+                    Ok(vec![file.clone()])
+                }
+            }
+        };
+
+        match command {
+            StateCommand::Show { name } => {
+                let states = select(name.as_deref())?
+                    .into_iter()
+                    .map(|(name, path)| {
+                        // the app leaves an empty file before its first save
+                        let data = std::fs::read(&path)?;
+                        let state = serde_json::from_slice(&data).unwrap_or_default();
+                        Ok((name, state))
+                    })
+                    .collect::<anyhow::Result<serde_json::Map<_, _>>>()?;
+
+                let out = match name {
+                    Some(_) => states.into_iter().next().unwrap().1,
+                    None => serde_json::Value::Object(states),
+                };
+                println!("{}", serde_json::to_string_pretty(&out)?);
+            }
+            StateCommand::Clear { name, all } => {
+                let name = if all { None } else { name };
+                for (_, path) in select(name.as_deref())? {
+                    std::fs::remove_file(&path)?;
+                    println!("{}: removed", path.display());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn cache(command: CacheCommand) -> anyhow::Result<()> {
+        let (config, _) = mqtt::Plugin::read_config()?.unwrap_or_default();
+        let filepath = mqtt::cache::filepath(&config.create_options);
+
+        match command {
+            CacheCommand::Stats => {
+                let stats = mqtt::cache::stats(&filepath)?;
+                println!("file:     {}", filepath.display());
+                println!("messages: {}", stats.messages);
+                println!("bytes:    {}", stats.bytes);
+                if let (Some(oldest), Some(newest)) = (stats.oldest, stats.newest) {
+                    println!("oldest:   {}", timestamp(oldest)?);
+                    println!("newest:   {}", timestamp(newest)?);
+                }
+            }
+            CacheCommand::Flush => {
+                let count = mqtt::cache::flush(&filepath)?;
+                println!("{}: {count} messages discarded", filepath.display());
+            }
+            CacheCommand::Export { out } => {
+                let mut writer: Box<dyn Write> = match &out {
+                    Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+                    None => Box::new(std::io::stdout().lock()),
+                };
+
+                for (time, msg) in mqtt::cache::read_all(&filepath)? {
+                    // most payloads are JSON, keep them readable
+                    let payload =
+                        serde_json::from_slice(msg.payload.as_ref()).unwrap_or_else(|_| {
+                            serde_json::Value::String(
+                                String::from_utf8_lossy(msg.payload.as_ref()).into_owned(),
+                            )
+                        });
+
+                    serde_json::to_writer(
+                        &mut writer,
+                        &serde_json::json!({
+                            "time": timestamp(time)?,
+                            "topic": msg.topic,
+                            "qos": msg.qos as i32,
+                            "retained": msg.retained,
+                            "payload": payload,
+                        }),
+                    )?;
+                    writeln!(writer)?;
+                }
+                writer.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn timestamp(unix: i64) -> anyhow::Result<String> {
+        Ok(time::OffsetDateTime::from_unix_timestamp(unix)?
+            .to_offset(*crate::timezone_offset())
+            .format(&time::format_description::well_known::Rfc3339)?)
+    }
+}
@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use crate::log;
+
+/// Saved configs kept per config file, the oldest are dropped first.
+const MAX_ENTRIES: usize = 20;
+
+/// What changed a config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Source {
+    /// default config written on the first start
+    Default,
+    /// file upgraded from an older config version
+    Migration { from: u32 },
+    /// saved over MQTT
+    Mqtt,
+    /// restored from the history entry `id`
+    Rollback { id: u32 },
+    /// updated by the app itself, e.g. after a pump calibration
+    App,
+    /// changed with the command line tool
+    Cli,
+    /// edited outside the app, recorded at the next save
+    External,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub id: u32,
+    /// unix timestamp of the save
+    pub timestamp: i64,
+    pub source: Source,
+    /// file content, including its version
+    pub config: serde_json::Value,
+}
+
+/// Entries of the config file `name`, oldest first.
+pub fn load(name: &str) -> Vec<Entry> {
+    let filepath = local::filepath(name);
+
+    match std::fs::read(&filepath) {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            log::warn!(
+                "[config] history \"{}\" discarded, reason: {e}",
+                filepath.to_str().unwrap()
+            );
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn find(name: &str, id: u32) -> Option<Entry> {
+    load(name).into_iter().find(|entry| entry.id == id)
+}
+
+/// Records `config` about to replace the file at `current`, along with the current
+/// content if it was edited since the last record.
+pub fn record(
+    name: &str,
+    current: &Path,
+    config: &serde_json::Value,
+    source: Source,
+) -> std::io::Result<()> {
+    fn push(entries: &mut Vec<Entry>, timestamp: i64, source: Source, config: serde_json::Value) {
+        if entries.last().is_some_and(|last| last.config == config) {
+            return;
+        }
+
+        entries.push(Entry {
+            id: entries.last().map_or(1, |last| last.id + 1),
+            timestamp,
+            source,
+            config,
+        });
+    }
+
+    let mut entries = load(name);
+
+    let edited = std::fs::read(current)
+        .ok()
+        .and_then(|data| serde_json::from_slice::<serde_json::Value>(&data).ok());
+    if let Some(edited) = edited {
+        let modified = std::fs::metadata(current)
+            .and_then(|metadata| metadata.modified())
+            .map_or(time::OffsetDateTime::now_utc(), time::OffsetDateTime::from);
+        push(
+            &mut entries,
+            modified.unix_timestamp(),
+            Source::External,
+            edited,
+        );
+    }
+
+    push(
+        &mut entries,
+        time::OffsetDateTime::now_utc().unix_timestamp(),
+        source,
+        config.clone(),
+    );
+
+    let excess = entries.len().saturating_sub(MAX_ENTRIES);
+    entries.drain(..excess);
+
+    super::local::write_atomic(
+        &local::filepath(name),
+        serde_json::to_string_pretty(&entries).unwrap().as_bytes(),
+    )
+}
+
+mod local {
+    use super::*;
+
+    pub fn filepath(name: &str) -> PathBuf {
+        let mut dir = crate::data_directory().to_path_buf();
+        dir.push("config");
+        dir.push("history");
+
+        dir.push(format!("{name}.json"));
+        dir
+    }
+}
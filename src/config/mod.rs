@@ -1,37 +1,50 @@
 use crate::{log, AtomicFixedString};
-use std::{
-    fs::OpenOptions,
-    io::{BufReader, Write},
-    path::PathBuf,
-};
+use std::path::PathBuf;
+
+use bevy_ecs::world::World;
+use bevy_tokio_tasks::TokioTasksRuntime;
+
+pub mod history;
+
+/// Upgrades a config one version, see [`ConfigFile::MIGRATIONS`].
+pub type Migration = fn(&mut serde_json::Value) -> Result<(), AtomicFixedString>;
 
 pub trait ConfigFile {
     const FILENAME: &'static str;
-    type Config: serde::Serialize + serde::de::DeserializeOwned + Default + std::fmt::Debug;
+    /// `MIGRATIONS[n]` upgrades a config of version `n` to `n + 1`, the current version
+    /// is the number of migrations
+    const MIGRATIONS: &'static [Migration] = &[];
+    type Config: serde::Serialize
+        + serde::de::DeserializeOwned
+        + Default
+        + std::fmt::Debug
+        + schemars::JsonSchema
+        + Validate;
 
     fn config_filepath() -> PathBuf {
         local::filepath(Self::FILENAME)
     }
 
-    fn save_config(config: Self::Config) -> std::io::Result<()> {
-        let filepath = Self::config_filepath();
+    fn config_version() -> u32 {
+        Self::MIGRATIONS.len() as u32
+    }
 
-        let mut file = {
-            if !filepath.exists() {
-                OpenOptions::new()
-                    .create_new(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(&filepath)
-                    .unwrap()
-            } else {
-                OpenOptions::new()
-                    .write(true)
-                    .truncate(true)
-                    .open(&filepath)
-                    .unwrap()
-            }
-        };
+    /// JSON Schema of the config file, including its version.
+    fn config_schema() -> schemars::schema::RootSchema {
+        let mut gen = schemars::gen::SchemaGenerator::default();
+        let mut schema = gen.root_schema_for::<Self::Config>();
+        schema
+            .schema
+            .object()
+            .properties
+            .insert(local::VERSION_KEY.into(), gen.subschema_for::<u32>());
+
+        schema
+    }
+
+    /// Replaces the config file, keeping the previous content in its history.
+    fn save_config(config: Self::Config, source: history::Source) -> std::io::Result<()> {
+        let filepath = Self::config_filepath();
 
         log::debug!(
             "new config file: \"{}\"\n{:?}",
@@ -39,53 +52,478 @@ pub trait ConfigFile {
             config
         );
 
-        file.write_all(
-            format!(
-                "{}\n",
-                serde_json::to_string_pretty(&serde_json::to_value(config).unwrap(),).unwrap()
-            )
-            .as_bytes(),
-        )
+        let value = local::versioned(
+            serde_json::to_value(config).unwrap(),
+            Self::config_version(),
+        );
+        local::save(Self::FILENAME, &filepath, &value, source)
     }
 
-    fn load_config() -> Result<Self::Config, AtomicFixedString> {
+    /// Parses the config file without writing anything, `None` if it doesn't exist yet.
+    /// Returns the config with the version the file was written in.
+    fn read_config() -> Result<Option<(Self::Config, u32)>, Error> {
         let filepath = Self::config_filepath();
-
         if !filepath.exists() {
-            Self::save_config(Self::Config::default()).unwrap();
-            Ok(Self::Config::default())
-        } else {
-            let file = OpenOptions::new().read(true).open(&filepath).unwrap();
-            let reader = BufReader::new(file);
+            return Ok(None);
+        }
+
+        let data = std::fs::read(&filepath).map_err(|source| Error::Io {
+            path: filepath.clone(),
+            source,
+        })?;
+
+        // files written before versioning count as version 0
+        Self::parse_config(&data, 0)
+            .map(Some)
+            .map_err(|errors| Error::Invalid {
+                path: filepath,
+                errors,
+            })
+    }
+
+    fn load_config() -> Result<Self::Config, Error> {
+        let filepath = Self::config_filepath();
+        let io_error = |source| Error::Io {
+            path: filepath.clone(),
+            source,
+        };
+
+        match Self::read_config()? {
+            None => {
+                Self::save_config(Self::Config::default(), history::Source::Default)
+                    .map_err(io_error)?;
+                Ok(Self::Config::default())
+            }
+            Some((out, version)) => {
+                if version < Self::config_version() {
+                    let mut backup = filepath.clone().into_os_string();
+                    backup.push(format!(".v{version}.bak"));
+                    std::fs::copy(&filepath, &backup).map_err(io_error)?;
+
+                    local::save(
+                        Self::FILENAME,
+                        &filepath,
+                        &local::versioned(
+                            serde_json::to_value(&out).unwrap(),
+                            Self::config_version(),
+                        ),
+                        history::Source::Migration { from: version },
+                    )
+                    .map_err(io_error)?;
+
+                    log::info!(
+                    "[config] upgraded \"{}\" from version {version} to {}, original kept as \"{}\"",
+                    filepath.to_str().unwrap(),
+                    Self::config_version(),
+                    backup.to_str().unwrap()
+                );
+                }
+
+                log::debug!(
+                    "existing config file: \"{}\"\n{:?}",
+                    filepath.as_path().to_str().unwrap(),
+                    out
+                );
+
+                Ok(out)
+            }
+        }
+    }
+
+    /// Upgrades JSON `data` from its `version` field, or from `unversioned` without one,
+    /// fills in missing fields from the default config and validates it. Returns the
+    /// config with the version it was written in.
+    fn parse_config(
+        data: &[u8],
+        unversioned: u32,
+    ) -> Result<(Self::Config, u32), Vec<ValidationError>> {
+        let mut value: serde_json::Value =
+            serde_json::from_slice(data).map_err(|e| vec![ValidationError::new(".", e)])?;
 
-            let out = serde_json::from_reader(reader).map_err(|e| -> AtomicFixedString {
+        let version = match value
+            .as_object_mut()
+            .and_then(|config| config.remove(local::VERSION_KEY))
+        {
+            Some(version) => version
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| {
+                    vec![ValidationError::new(
+                        local::VERSION_KEY,
+                        "must be a non-negative integer",
+                    )]
+                })?,
+            None => unversioned,
+        };
+
+        if version > Self::config_version() {
+            return Err(vec![ValidationError::new(
+                local::VERSION_KEY,
                 format!(
-                    "error in config file '{}', reason {e}",
-                    filepath.to_str().unwrap()
-                )
-                .into()
+                    "version {version} is newer than the supported version {}",
+                    Self::config_version()
+                ),
+            )]);
+        }
+
+        for (from, migrate) in Self::MIGRATIONS.iter().enumerate().skip(version as usize) {
+            migrate(&mut value).map_err(|e| {
+                vec![ValidationError::new(
+                    ".",
+                    format!("failed to upgrade to version {}, reason: {e}", from + 1),
+                )]
             })?;
+        }
+
+        local::fill_missing::<Self::Config>(
+            &mut value,
+            &serde_json::to_value(Self::Config::default()).unwrap(),
+        );
+
+        Ok((parse(value)?, version))
+    }
+}
 
-            log::debug!(
-                "existing config file: \"{}\"\n{:?}",
-                filepath.as_path().to_str().unwrap(),
-                out
-            );
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to access config file '{}', reason: {source}", .path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("error in config file '{}':\n{}", .path.display(), local::list(.errors))]
+    Invalid {
+        path: PathBuf,
+        errors: Vec<ValidationError>,
+    },
+}
 
-            Ok(out)
+/// Invalid field of a config, `path` addresses it from the root (e.g.
+/// `zones[1].spray_duration`, `.` for the config itself).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ValidationError {
+    pub path: AtomicFixedString,
+    pub message: AtomicFixedString,
+}
+impl ValidationError {
+    pub fn new(path: impl std::fmt::Display, message: impl std::fmt::Display) -> Self {
+        Self {
+            path: path.to_string().into(),
+            message: message.to_string().into(),
         }
     }
 }
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+impl From<serde_path_to_error::Error<serde_json::Error>> for ValidationError {
+    fn from(e: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        Self::new(e.path(), e.inner())
+    }
+}
+
+/// Errors collected while validating a config.
+#[derive(Debug, Default)]
+pub struct Errors(Vec<ValidationError>);
+impl Errors {
+    /// Records `message` for the field at `path` unless `valid`.
+    pub fn check(
+        &mut self,
+        valid: bool,
+        path: impl std::fmt::Display,
+        message: impl std::fmt::Display,
+    ) {
+        if !valid {
+            self.0.push(ValidationError::new(path, message));
+        }
+    }
+
+    /// Records an error for a duration above `max`, which keeps timestamps offset by it
+    /// far from overflowing.
+    pub fn check_duration(
+        &mut self,
+        path: impl std::fmt::Display,
+        duration: std::time::Duration,
+        max: std::time::Duration,
+    ) {
+        self.check(
+            duration <= max,
+            path,
+            format_args!(
+                "must not exceed {}",
+                crate::helper::serde_time::format_duration(max)
+            ),
+        );
+    }
+}
+
+/// Semantic checks of a config beyond what deserializing it enforces.
+pub trait Validate {
+    fn validate(&self, errors: &mut Errors);
+}
+
+/// Deserializes and validates a JSON config, returning every error with its field path.
+fn parse<C>(value: serde_json::Value) -> Result<C, Vec<ValidationError>>
+where
+    C: serde::de::DeserializeOwned + Validate,
+{
+    let config: C =
+        serde_path_to_error::deserialize(value).map_err(|e| vec![ValidationError::from(e)])?;
+
+    validate(&config).map(|()| config)
+}
+
+/// Runs the [`Validate`] checks of a config that was built or changed in code.
+pub fn validate<C: Validate>(config: &C) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Errors::default();
+    config.validate(&mut errors);
+
+    if errors.0.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.0)
+    }
+}
+
+/// Config taking effect on the running app, without a restart, when it is saved over
+/// MQTT or its file is edited.
+pub trait HotReload: ConfigFile {
+    /// Applies `config` to the live resources, an error keeps the running config.
+    fn apply_config(config: &Self::Config, world: &mut World) -> Result<(), AtomicFixedString>;
+}
+
+/// Signals every completed write or move onto the config file of `T`.
+pub fn watch<T: ConfigFile>(
+    rt: &TokioTasksRuntime,
+) -> Result<crossbeam_channel::Receiver<()>, AtomicFixedString> {
+    use futures::StreamExt;
+    use inotify::{Inotify, WatchMask};
+
+    let filepath = T::config_filepath();
+    let (dir, filename) = (
+        filepath.parent().unwrap().to_path_buf(),
+        filepath.file_name().unwrap().to_os_string(),
+    );
+
+    let inotify = Inotify::init().map_err(|e| format!("failed to init inotify, reason: {e}"))?;
+    // editors often replace the file, so the directory is watched
+    inotify
+        .watches()
+        .add(&dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+        .map_err(|e| format!("failed to watch {}, reason: {e}", dir.to_str().unwrap()))?;
+
+    let (tx, rx) = crossbeam_channel::unbounded();
+
+    rt.spawn_background_task(move |_| async move {
+        // registering with the reactor needs the runtime context
+        let mut events = match inotify.into_event_stream([0; 1024]) {
+            Ok(events) => events,
+            Err(e) => {
+                log::warn!("[config] failed to read inotify events, reason: {e}");
+                return;
+            }
+        };
+
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(event) if event.name.as_ref() == Some(&filename) => {
+                    if tx.send(()).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    log::warn!("[config] inotify failed, reason: {e}");
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(rx)
+}
 
 mod local {
-    use std::path::PathBuf;
+    use std::{
+        fs::OpenOptions,
+        io::Write,
+        path::{Path, PathBuf},
+    };
+
+    use super::ValidationError;
+    use crate::log;
+
+    pub const VERSION_KEY: &str = "version";
 
     pub fn filepath(name: &str) -> PathBuf {
         let mut dir = crate::data_directory().to_path_buf();
         dir.push("config");
-        std::fs::create_dir_all(&dir).unwrap();
 
         dir.push(format!("{}.json", name));
         dir
     }
+
+    pub fn versioned(mut config: serde_json::Value, version: u32) -> serde_json::Value {
+        if let Some(config) = config.as_object_mut() {
+            config.insert(VERSION_KEY.into(), version.into());
+        }
+        config
+    }
+
+    pub fn save(
+        name: &str,
+        filepath: &Path,
+        config: &serde_json::Value,
+        source: super::history::Source,
+    ) -> std::io::Result<()> {
+        if let Err(e) = super::history::record(name, filepath, config, source) {
+            log::warn!("[config] failed to record {name} history, reason: {e}");
+        }
+
+        write_atomic(
+            filepath,
+            format!("{}\n", serde_json::to_string_pretty(config).unwrap()).as_bytes(),
+        )
+    }
+
+    /// Writes a temporary file next to `filepath` and renames it over, so readers never
+    /// see a partial file.
+    pub fn write_atomic(filepath: &Path, data: &[u8]) -> std::io::Result<()> {
+        std::fs::create_dir_all(filepath.parent().unwrap())?;
+
+        let mut tmp_path = filepath.to_path_buf().into_os_string();
+        tmp_path.push(".tmp");
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+
+        std::fs::rename(&tmp_path, filepath)
+    }
+
+    /// Tag of internally tagged enums, see `#[serde(tag = "type")]`.
+    const TAG_KEY: &str = "type";
+
+    /// Adds the fields serde reports missing in `config` from `default`, one at a time,
+    /// so a field with a serde default keeps it. Only objects of the same variant as in
+    /// `default` are filled, anything else stays an error for [`super::parse`].
+    pub fn fill_missing<C>(config: &mut serde_json::Value, default: &serde_json::Value)
+    where
+        C: serde::de::DeserializeOwned,
+    {
+        use serde_path_to_error::Segment;
+
+        while let Err(e) = serde_path_to_error::deserialize::<_, C>(config.clone()) {
+            let message = e.inner().to_string();
+            let Some(field) = message
+                .strip_prefix("missing field `")
+                .and_then(|field| field.strip_suffix('`'))
+            else {
+                return;
+            };
+
+            let (mut target, mut source) = (&mut *config, default);
+            for segment in e.path().iter() {
+                if target.get(TAG_KEY) != source.get(TAG_KEY) {
+                    return;
+                }
+                let Segment::Map { key } = segment else {
+                    return;
+                };
+                let (Some(value), Some(default)) = (target.get_mut(key), source.get(key)) else {
+                    return;
+                };
+                (target, source) = (value, default);
+            }
+
+            if target.get(TAG_KEY) != source.get(TAG_KEY) {
+                return;
+            }
+            let (Some(target), Some(default)) = (target.as_object_mut(), source.get(field)) else {
+                return;
+            };
+            target.insert(field.to_string(), default.clone());
+        }
+    }
+
+    pub fn list(errors: &[ValidationError]) -> String {
+        errors
+            .iter()
+            .map(|e| format!("  {e}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::local;
+    use serde_json::json;
+
+    #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+    struct Config {
+        name: String,
+        #[serde(default)]
+        list: Vec<u32>,
+        nested: Nested,
+        sensor: Sensor,
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct Nested {
+        a: u32,
+        b: u32,
+    }
+    impl Default for Nested {
+        fn default() -> Self {
+            Self { a: 1, b: 2 }
+        }
+    }
+
+    #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Sensor {
+        #[default]
+        Analog,
+        Digital {
+            pin: u8,
+        },
+    }
+
+    fn default() -> serde_json::Value {
+        serde_json::to_value(Config {
+            list: vec![7],
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn fills_missing_required_fields_only() {
+        let mut config = json!({ "nested": { "b": 5 } });
+        local::fill_missing::<Config>(&mut config, &default());
+
+        assert_eq!(
+            config,
+            json!({ "name": "", "nested": { "a": 1, "b": 5 }, "sensor": { "type": "analog" } })
+        );
+    }
+
+    #[test]
+    fn keeps_objects_of_another_variant() {
+        let mut config = json!({ "name": "x", "nested": {}, "sensor": { "type": "digital" } });
+        local::fill_missing::<Config>(&mut config, &default());
+
+        assert_eq!(
+            config,
+            json!({ "name": "x", "nested": { "a": 1, "b": 2 }, "sensor": { "type": "digital" } })
+        );
+        assert!(serde_json::from_value::<Config>(config).is_err());
+    }
 }
@@ -1,14 +1,30 @@
 #![allow(dead_code)]
 
+/// Defaults of [`crate::namespace::Config`].
 pub mod project {
     pub const NAME: &str = "triponics";
     pub const DEVICE: &str = "0";
 }
 
-pub mod mqtt_prefix {
-    pub const STATUS: &str = "status";
-    pub const DATABASE: &str = "data";
-    pub const REQUEST: &str = "request";
-    pub const RESPONSE: &str = "response";
-    pub const CONFIG: &str = "configs";
+/// First segment of a message topic, mapped to its configured prefix by
+/// [`crate::namespace::Prefixes::get`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MqttPrefix {
+    Status,
+    Database,
+    Request,
+    Response,
+    Config,
+}
+impl MqttPrefix {
+    /// Default prefix, also names the kind of message in schemas and CLI args.
+    pub const fn name(self) -> &'static str {
+        match self {
+            MqttPrefix::Status => "status",
+            MqttPrefix::Database => "data",
+            MqttPrefix::Request => "request",
+            MqttPrefix::Response => "response",
+            MqttPrefix::Config => "configs",
+        }
+    }
 }
@@ -1,12 +1,32 @@
-use std::sync::LazyLock;
+use std::sync::{LazyLock, OnceLock};
 
+static DATA_DIRECTORY: OnceLock<std::path::PathBuf> = OnceLock::new();
+static NAMESPACE: OnceLock<crate::namespace::Config> = OnceLock::new();
+
+/// `data/` in the working directory unless set by [`init_data_directory`].
 pub fn data_directory() -> &'static std::path::Path {
-    static DATA_DIRECTORY: LazyLock<std::path::PathBuf> = LazyLock::new(|| {
+    DATA_DIRECTORY.get_or_init(|| {
         let mut cwd = std::env::current_dir().unwrap();
         cwd.push("data");
         cwd
-    });
-    DATA_DIRECTORY.as_path()
+    })
+}
+
+/// Must run before the data directory is first used.
+pub fn init_data_directory(path: std::path::PathBuf) {
+    DATA_DIRECTORY
+        .set(path)
+        .expect("data directory is already in use");
+}
+
+/// The default namespace unless set by [`init_namespace`].
+pub fn namespace() -> &'static crate::namespace::Config {
+    NAMESPACE.get_or_init(Default::default)
+}
+
+/// Must run before the first topic is built.
+pub fn init_namespace(config: crate::namespace::Config) {
+    NAMESPACE.set(config).expect("namespace is already in use");
 }
 
 pub fn timezone_offset() -> &'static time::UtcOffset {
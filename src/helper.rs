@@ -9,7 +9,7 @@ use bevy_ecs::system::Resource;
 use bevy_ecs::{event::Event, system::Res};
 use bevy_internal::prelude::{Deref, DerefMut};
 
-use crate::{log, AtomicFixedBytes, AtomicFixedString};
+use crate::{AtomicFixedBytes, AtomicFixedString};
 
 #[derive(Resource, Deref, DerefMut)]
 struct ChannelReceiver<T>(Mutex<Receiver<T>>);
@@ -46,8 +46,6 @@ fn channel_to_event<T: 'static + Send + Sync + Event>(
 pub mod serde_time {
     use serde::Deserialize;
 
-    use super::{log, ToDuration};
-
     pub fn serialize_offset_datetime_as_local<S>(
         offset_datetime: &time::OffsetDateTime,
         serializer: S,
@@ -70,13 +68,11 @@ pub mod serde_time {
         D: serde::Deserializer<'de>,
     {
         let data = String::deserialize(deserializer)?;
-        Ok(time::OffsetDateTime::parse(&data, &crate::time_log_fmt())
-            .map_err(|e| {
-                log::error!(
-                    "error deserializing datetime, reason: {e}; expected format \"hh:mm:ss.sss\""
-                )
-            })
-            .unwrap())
+        time::OffsetDateTime::parse(&data, &crate::time_log_fmt()).map_err(|e| {
+            serde::de::Error::custom(format!(
+                "error deserializing datetime '{data}', reason: {e}; expected an RFC 2822 datetime"
+            ))
+        })
     }
 
     const TIME_FORMAT: &[time::format_description::BorrowedFormatItem<'_>] =
@@ -94,13 +90,86 @@ pub mod serde_time {
         D: serde::Deserializer<'de>,
     {
         let data = String::deserialize(deserializer)?;
-        Ok(time::Time::parse(&data, TIME_FORMAT)
-            .map_err(|e| {
-                log::error!(
-                    "error deserializing time, reason: {e}; expected format \"hh:mm:ss.sss\""
-                )
+        time::Time::parse(&data, TIME_FORMAT).map_err(|e| {
+            serde::de::Error::custom(format!(
+                "error deserializing time '{data}', reason: {e}; expected format \"hh:mm:ss.sss\""
+            ))
+        })
+    }
+
+    const DATE_FORMAT: &[time::format_description::BorrowedFormatItem<'_>] =
+        time::macros::format_description!("[year]-[month]-[day]");
+
+    pub fn serialize_optional_date<S>(
+        date: &Option<time::Date>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match date {
+            Some(date) => serializer.serialize_some(&date.format(DATE_FORMAT).unwrap()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize_optional_date<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<time::Date>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|data| {
+                time::Date::parse(&data, DATE_FORMAT).map_err(|e| {
+                    serde::de::Error::custom(format!(
+                        "error deserializing date, reason: {e}; expected format \"yyyy-mm-dd\""
+                    ))
+                })
             })
-            .unwrap())
+            .transpose()
+    }
+
+    /// Formats a duration as its non-zero units, e.g. `"1d12h"`, `"1h30m"` or `"1s500ms"`,
+    /// readable by [`parse_duration`] without loss.
+    pub fn format_duration(duration: std::time::Duration) -> String {
+        let secs = duration.as_secs();
+        let nanos = duration.subsec_nanos();
+        let units = [
+            (secs / 86_400, "d"),
+            (secs % 86_400 / 3_600, "h"),
+            (secs % 3_600 / 60, "m"),
+            (secs % 60, "s"),
+            (u64::from(nanos / 1_000_000), "ms"),
+            (u64::from(nanos / 1_000 % 1_000), "us"),
+            (u64::from(nanos % 1_000), "ns"),
+        ];
+
+        let out = units
+            .iter()
+            .filter(|(value, _)| *value > 0)
+            .map(|(value, unit)| format!("{value}{unit}"))
+            .collect::<String>();
+
+        if out.is_empty() {
+            "0s".into()
+        } else {
+            out
+        }
+    }
+
+    /// Parses `"90s"`, `"5m"`, `"1h30m"`, `"2d"` and the like, ISO 8601 durations such as
+    /// `"PT1H30M"` or `"P1W"`, and `"hh:mm:ss.sss"` with any number of hours.
+    pub fn parse_duration(data: &str) -> Result<std::time::Duration, String> {
+        let data = data.trim();
+
+        if data.contains(':') {
+            local::parse_hms(data)
+        } else if let Some(iso) = data.strip_prefix('P') {
+            local::parse_iso8601(iso)
+        } else {
+            humantime::parse_duration(data).map_err(|e| e.to_string())
+        }
     }
 
     pub fn serialize_duration_formatted<S>(
@@ -110,9 +179,7 @@ pub mod serde_time {
     where
         S: serde::Serializer,
     {
-        let start = time::macros::time!(00:00:00.000);
-        let dur = start + *duration;
-        serializer.serialize_str(&dur.format(TIME_FORMAT).unwrap())
+        serializer.serialize_str(&format_duration(*duration))
     }
 
     pub fn deserialize_duration_formatted<'de, D>(
@@ -122,14 +189,280 @@ pub mod serde_time {
         D: serde::Deserializer<'de>,
     {
         let data = String::deserialize(deserializer)?;
-        Ok(time::Time::parse(&data, TIME_FORMAT)
-            .map_err(|e| {
-                log::error!(
-                    "error deserializing duration, reason: {e}; expected format \"hh:mm:ss.sss\""
-                )
+        local::deserialize_duration(&data)
+    }
+
+    pub fn serialize_optional_duration_formatted<S>(
+        duration: &Option<std::time::Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match duration {
+            Some(duration) => serialize_duration_formatted(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize_optional_duration_formatted<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<std::time::Duration>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|data| local::deserialize_duration(&data))
+            .transpose()
+    }
+
+    mod local {
+        const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+        pub fn deserialize_duration<E: serde::de::Error>(
+            data: &str,
+        ) -> Result<std::time::Duration, E> {
+            super::parse_duration(data).map_err(|e| {
+                E::custom(format!(
+                    "error deserializing duration '{data}', reason: {e}; expected e.g. \"90s\", \"1h30m\", \"2d\", \"PT1H30M\" or \"hh:mm:ss.sss\""
+                ))
             })
-            .unwrap()
-            .to_duration())
+        }
+
+        /// `hh:mm:ss` with optional fractional seconds, hours may exceed a day.
+        pub fn parse_hms(data: &str) -> Result<std::time::Duration, String> {
+            let [hours, minutes, seconds] = data.split(':').collect::<Vec<_>>()[..] else {
+                return Err("expected hours, minutes and seconds".into());
+            };
+
+            let hours = decimal(hours, 3_600 * NANOS_PER_SEC, false)
+                .ok_or_else(|| format!("invalid hours '{hours}'"))?;
+            let minutes = decimal(minutes, 60 * NANOS_PER_SEC, false)
+                .filter(|minutes| *minutes < 3_600 * NANOS_PER_SEC)
+                .ok_or_else(|| format!("invalid minutes '{minutes}'"))?;
+            let seconds = decimal(seconds, NANOS_PER_SEC, true)
+                .filter(|seconds| *seconds < 60 * NANOS_PER_SEC)
+                .ok_or_else(|| format!("invalid seconds '{seconds}'"))?;
+
+            from_nanos(hours + minutes + seconds)
+        }
+
+        /// ISO 8601 duration after its `P`, e.g. `1DT12H`, `T1H30M`, `T0.5S` or `2W`. Years
+        /// and months are rejected since their length varies.
+        pub fn parse_iso8601(data: &str) -> Result<std::time::Duration, String> {
+            let (date, time) = data.split_once('T').unwrap_or((data, ""));
+            if data.is_empty() || data.ends_with('T') {
+                return Err("ISO 8601 duration without any value".into());
+            }
+
+            let mut nanos = 0;
+            for (is_date, part, units) in [
+                (
+                    true,
+                    date,
+                    &[
+                        ('W', 7 * 86_400 * NANOS_PER_SEC),
+                        ('D', 86_400 * NANOS_PER_SEC),
+                    ][..],
+                ),
+                (
+                    false,
+                    time,
+                    &[
+                        ('H', 3_600 * NANOS_PER_SEC),
+                        ('M', 60 * NANOS_PER_SEC),
+                        ('S', NANOS_PER_SEC),
+                    ][..],
+                ),
+            ] {
+                let mut rest = part;
+                let mut units = units.iter();
+                while !rest.is_empty() {
+                    let end = rest
+                        .find(|c: char| c.is_ascii_alphabetic())
+                        .ok_or_else(|| format!("missing unit after '{rest}'"))?;
+                    let (value, unit) = (&rest[..end], rest[end..].chars().next().unwrap());
+
+                    if is_date && matches!(unit, 'Y' | 'M') {
+                        return Err("years and months have no fixed length, use days".into());
+                    }
+
+                    // units must follow in order, each at most once
+                    let (_, unit_nanos) = units
+                        .find(|(name, _)| *name == unit)
+                        .ok_or_else(|| format!("unexpected unit '{unit}'"))?;
+                    nanos += decimal(&value.replace(',', "."), *unit_nanos, true)
+                        .ok_or_else(|| format!("invalid number '{value}'"))?;
+
+                    rest = &rest[end + 1..];
+                }
+            }
+
+            from_nanos(nanos)
+        }
+
+        /// `data` in `unit_nanos`, kept exact to the nanosecond.
+        fn decimal(data: &str, unit_nanos: u128, fraction: bool) -> Option<u128> {
+            let (int, frac) = match data.split_once('.') {
+                Some((int, frac)) if fraction => (int, frac),
+                Some(_) => return None,
+                None => (data, ""),
+            };
+            let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+            if int.is_empty() || !digits(int) || !digits(frac) || int.len() > 20 {
+                return None;
+            }
+
+            // digits past nanoseconds can't change the result
+            let frac = &frac[..frac.len().min(18)];
+            let scale = 10u128.pow(frac.len() as u32);
+            let frac_nanos = if frac.is_empty() {
+                0
+            } else {
+                frac.parse::<u128>().ok()? * unit_nanos / scale
+            };
+
+            int.parse::<u128>()
+                .ok()?
+                .checked_mul(unit_nanos)?
+                .checked_add(frac_nanos)
+        }
+
+        fn from_nanos(nanos: u128) -> Result<std::time::Duration, String> {
+            let secs = u64::try_from(nanos / NANOS_PER_SEC).map_err(|_| "duration too long")?;
+            Ok(std::time::Duration::new(
+                secs,
+                (nanos % NANOS_PER_SEC) as u32,
+            ))
+        }
+    }
+
+    /// Stand-ins describing the formatted fields above, for `#[schemars(with = "...")]`.
+    pub mod schema {
+        use schemars::{
+            gen::SchemaGenerator,
+            schema::{InstanceType, Metadata, Schema, SchemaObject, StringValidation},
+            JsonSchema,
+        };
+
+        fn formatted(pattern: &str, description: &str) -> Schema {
+            SchemaObject {
+                instance_type: Some(InstanceType::String.into()),
+                string: Some(Box::new(StringValidation {
+                    pattern: Some(pattern.into()),
+                    ..Default::default()
+                })),
+                metadata: Some(Box::new(Metadata {
+                    description: Some(description.into()),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }
+            .into()
+        }
+
+        /// `hh:mm:ss.sss`
+        pub struct Time;
+        impl JsonSchema for Time {
+            fn schema_name() -> String {
+                "Time".into()
+            }
+
+            fn json_schema(_: &mut SchemaGenerator) -> Schema {
+                formatted(r"^\d{2}:\d{2}:\d{2}\.\d{3}$", "time of day, hh:mm:ss.sss")
+            }
+        }
+
+        /// `1h30m`, `PT1H30M` or `hh:mm:ss.sss`
+        pub struct Duration;
+        impl JsonSchema for Duration {
+            fn schema_name() -> String {
+                "Duration".into()
+            }
+
+            fn json_schema(_: &mut SchemaGenerator) -> Schema {
+                formatted(
+                    r"^\s*(\d+:\d{2}:\d{2}(\.\d+)?|P.+|(\d+\s*[a-zµ]+\s*)+)$",
+                    "duration, e.g. \"90s\", \"1h30m\", \"2d\", ISO 8601 \"PT1H30M\" or hh:mm:ss.sss",
+                )
+            }
+        }
+
+        /// `yyyy-mm-dd`
+        pub struct Date;
+        impl JsonSchema for Date {
+            fn schema_name() -> String {
+                "Date".into()
+            }
+
+            fn json_schema(_: &mut SchemaGenerator) -> Schema {
+                formatted(r"^\d{4}-\d{2}-\d{2}$", "date, yyyy-mm-dd")
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use super::{format_duration, parse_duration};
+
+        #[test]
+        fn parses_legacy_hms() {
+            assert_eq!(parse_duration("00:00:30"), Ok(Duration::from_secs(30)));
+            assert_eq!(parse_duration("01:30:00"), Ok(Duration::from_secs(5_400)));
+            assert_eq!(
+                parse_duration("00:00:01.250"),
+                Ok(Duration::from_millis(1_250))
+            );
+            assert!(parse_duration("00:60:00").is_err());
+        }
+
+        #[test]
+        fn parses_a_day_and_more() {
+            assert_eq!(parse_duration("24:00:00"), Ok(Duration::from_secs(86_400)));
+            assert_eq!(parse_duration("36:00:00"), Ok(Duration::from_secs(129_600)));
+            assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(172_800)));
+            assert_eq!(parse_duration("1d12h"), Ok(Duration::from_secs(129_600)));
+        }
+
+        #[test]
+        fn parses_iso8601_in_order() {
+            assert_eq!(parse_duration("PT1H30M"), Ok(Duration::from_secs(5_400)));
+            assert_eq!(
+                parse_duration("P1W2DT3H4M5.5S"),
+                Ok(Duration::from_millis(
+                    ((9 * 24 + 3) * 3_600 + 4 * 60 + 5) * 1_000 + 500
+                ))
+            );
+            assert!(parse_duration("PT1S2M").is_err());
+            assert!(parse_duration("P1DT").is_err());
+            assert!(parse_duration("P1H").is_err());
+        }
+
+        #[test]
+        fn rejects_iso8601_years_and_months() {
+            assert!(parse_duration("P1Y").is_err());
+            assert!(parse_duration("P1M").is_err());
+            assert!(parse_duration("P1Y2M3D").is_err());
+        }
+
+        #[test]
+        fn round_trips_formatted_durations() {
+            assert_eq!(format_duration(Duration::ZERO), "0s");
+            assert_eq!(format_duration(Duration::from_secs(5_400)), "1h30m");
+            assert_eq!(format_duration(Duration::from_secs(129_600)), "1d12h");
+
+            for duration in [
+                Duration::ZERO,
+                Duration::from_nanos(1),
+                Duration::from_millis(1_500),
+                Duration::from_secs(86_400),
+                Duration::new(3 * 86_400 + 3_661, 123_456_789),
+            ] {
+                assert_eq!(parse_duration(&format_duration(duration)), Ok(duration));
+            }
+        }
     }
 }
 
@@ -152,15 +485,3 @@ impl ToBytes for serde_json::Value {
         bytes.into()
     }
 }
-
-pub trait ToDuration {
-    fn to_duration(&self) -> std::time::Duration;
-}
-impl ToDuration for time::Time {
-    fn to_duration(&self) -> std::time::Duration {
-        let (h, m, s, ms) = self.as_hms_milli();
-        std::time::Duration::from_secs_f32(
-            (h as f32 * 60.0 * 60.0) + (m as f32 * 60.0) + (s as f32 + (ms as f32 / 1000.0)),
-        )
-    }
-}
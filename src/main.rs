@@ -12,6 +12,10 @@ use newtype::*;
 mod globals;
 use globals::*;
 
+mod cli;
+mod namespace;
+mod schema;
+
 use std::time::Duration;
 
 use bevy_app::{prelude::*, ScheduleRunnerPlugin};
@@ -30,15 +34,55 @@ pub struct Args {
     /// log to stdout
     #[arg(long)]
     pub stdout: bool,
+
+    #[command(flatten)]
+    pub namespace: namespace::Overrides,
+
+    #[command(subcommand)]
+    pub command: Option<cli::Command>,
 }
 
 fn main() -> anyhow::Result<()> {
-    local::try_init();
+    let args = Args::parse();
+    if let Some(data_dir) = args.namespace.data_dir.clone() {
+        init_data_directory(data_dir);
+    }
 
-    let mqtt_config = mqtt::Plugin::load_config().unwrap();
-    let aeroponic_config = manager::AeroponicSprayManager::load_config().unwrap();
-    let ph_dosing_config = manager::PhDosingManager::load_config().unwrap();
-    let growlight_config = manager::GrowlightManager::load_config().unwrap();
+    match args.command {
+        None | Some(cli::Command::Run) => run(args),
+        Some(command) => cli::execute(command),
+    }
+}
+
+fn run(args: Args) -> anyhow::Result<()> {
+    local::init_logging(args.stdout);
+
+    let namespace = args
+        .namespace
+        .apply(local::load_config::<namespace::Namespace>()?)
+        .inspect_err(|e| log::error!("{e}"))?;
+    log::info!(
+        "data directory: \"{}\", topics: {}",
+        data_directory().to_str().unwrap(),
+        namespace.topic(constants::MqttPrefix::Status, "<group>")
+    );
+    init_namespace(namespace);
+
+    let mqtt_config = local::load_config::<mqtt::Plugin>()?;
+    let aeroponic_config = local::load_config::<manager::AeroponicSprayManager>()?;
+    let ph_dosing_config = local::load_config::<manager::PhDosingManager>()?;
+    let growlight_config = local::load_config::<manager::GrowlightManager>()?;
+    let nutrient_dosing_config = local::load_config::<manager::NutrientDosingManager>()?;
+
+    nutrient_dosing_config
+        .check_sprayer_relays(aeroponic_config.relays())
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "error in config file '{}':\n{e}",
+                manager::NutrientDosingManager::config_filepath().display()
+            )
+        })
+        .inspect_err(|e| log::error!("{e}"))?;
 
     let configs = std::collections::HashMap::from([
         (
@@ -57,6 +101,10 @@ fn main() -> anyhow::Result<()> {
             manager::GrowlightManager::config_filepath(),
             serde_json::to_string_pretty(&growlight_config).unwrap(),
         ),
+        (
+            manager::NutrientDosingManager::config_filepath(),
+            serde_json::to_string_pretty(&nutrient_dosing_config).unwrap(),
+        ),
     ]);
 
     configs.into_iter().for_each(|(path, config)| {
@@ -76,6 +124,7 @@ fn main() -> anyhow::Result<()> {
         .add_plugins((
             ExitHandler,
             state_file::Plugin::default(),
+            schema::Plugin,
             mqtt::Plugin {
                 config: mqtt_config,
             },
@@ -89,6 +138,9 @@ fn main() -> anyhow::Result<()> {
             manager::ph_dosing::Plugin {
                 config: ph_dosing_config,
             },
+            manager::nutrient_dosing::Plugin {
+                config: nutrient_dosing_config,
+            },
             manager::aeroponic_spray::Plugin {
                 config: aeroponic_config,
             },
@@ -112,7 +164,7 @@ mod local {
     #[derive(Resource)]
     struct ExitRx(tokio::sync::oneshot::Receiver<()>);
 
-    #[derive(Debug, serde::Serialize)]
+    #[derive(Debug, serde::Serialize, schemars::JsonSchema)]
     pub struct ExitMsg;
     impl<'de> serde::Deserialize<'de> for ExitMsg {
         fn deserialize<D>(_: D) -> Result<Self, D::Error>
@@ -123,10 +175,8 @@ mod local {
         }
     }
     impl mqtt::add_on::action_message::MessageImpl for ExitMsg {
-        const PREFIX: &'static str = crate::constants::mqtt_prefix::REQUEST;
-        const PROJECT: &'static str = crate::constants::project::NAME;
+        const PREFIX: crate::constants::MqttPrefix = crate::constants::MqttPrefix::Request;
         const GROUP: &'static str = "exit";
-        const DEVICE: &'static str = crate::constants::project::DEVICE;
         const QOS: mqtt::Qos = mqtt::Qos::_1;
     }
     impl ExitMsg {
@@ -162,9 +212,9 @@ mod local {
         }
     }
 
-    pub fn try_init() {
-        let args = Args::parse();
-        init_logging(args.stdout);
+    /// Loads the config of `T`, logging every error before giving up.
+    pub fn load_config<T: ConfigFile>() -> Result<T::Config, config::Error> {
+        T::load_config().inspect_err(|e| log::error!("{e}"))
     }
 
     pub fn init_logging(to_stdout: bool) {
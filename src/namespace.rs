@@ -0,0 +1,216 @@
+use std::path::PathBuf;
+
+use crate::{
+    config::{self, ConfigFile, Errors, Validate},
+    constants::{self, MqttPrefix},
+    AtomicFixedString,
+};
+
+/// Topic namespace of the app, lets several towers share one broker.
+///
+/// Read from its config file, then overridden by [`Overrides`]. Changes take effect on the
+/// next start.
+pub struct Namespace;
+impl ConfigFile for Namespace {
+    const FILENAME: &'static str = "namespace";
+    type Config = Config;
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+#[serde(default)]
+pub struct Config {
+    /// second topic segment, `<prefix>/<project>/<group>/<device>`
+    pub project: AtomicFixedString,
+    /// last topic segment, tells towers sharing a broker apart
+    pub device: AtomicFixedString,
+    pub prefixes: Prefixes,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            project: constants::project::NAME.into(),
+            device: constants::project::DEVICE.into(),
+            prefixes: Prefixes::default(),
+        }
+    }
+}
+impl Validate for Config {
+    fn validate(&self, errors: &mut Errors) {
+        // both end up in Home Assistant node ids, which allow no more than this
+        for (path, segment) in [("project", &self.project), ("device", &self.device)] {
+            errors.check(
+                !segment.as_ref().is_empty()
+                    && segment
+                        .as_ref()
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+                path,
+                "must be letters, digits, '_' or '-'",
+            );
+        }
+
+        for (kind, prefix) in self.prefixes.iter() {
+            let prefix = prefix.as_ref();
+            errors.check(
+                !prefix.is_empty()
+                    && !prefix.starts_with('/')
+                    && !prefix.ends_with('/')
+                    && !prefix.contains(['+', '#']),
+                format!("prefixes.{kind}"),
+                "must be a topic without wildcards or surrounding '/'",
+            );
+        }
+    }
+}
+impl Config {
+    /// `<prefix>/<project>/<group>/<device>`
+    pub fn topic(&self, prefix: MqttPrefix, group: &str) -> AtomicFixedString {
+        format!(
+            "{}/{}/{group}/{}",
+            self.prefixes.get(prefix),
+            self.project,
+            self.device
+        )
+        .into()
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+#[serde(default)]
+pub struct Prefixes {
+    pub status: AtomicFixedString,
+    pub database: AtomicFixedString,
+    pub request: AtomicFixedString,
+    pub response: AtomicFixedString,
+    pub config: AtomicFixedString,
+    /// Home Assistant discovery prefix
+    pub home_assistant: AtomicFixedString,
+}
+impl Default for Prefixes {
+    fn default() -> Self {
+        Self {
+            status: MqttPrefix::Status.name().into(),
+            database: MqttPrefix::Database.name().into(),
+            request: MqttPrefix::Request.name().into(),
+            response: MqttPrefix::Response.name().into(),
+            config: MqttPrefix::Config.name().into(),
+            home_assistant: "homeassistant".into(),
+        }
+    }
+}
+impl Prefixes {
+    const KINDS: [&'static str; 6] = [
+        "status",
+        "database",
+        "request",
+        "response",
+        "config",
+        "home_assistant",
+    ];
+
+    pub fn get(&self, prefix: MqttPrefix) -> &AtomicFixedString {
+        match prefix {
+            MqttPrefix::Status => &self.status,
+            MqttPrefix::Database => &self.database,
+            MqttPrefix::Request => &self.request,
+            MqttPrefix::Response => &self.response,
+            MqttPrefix::Config => &self.config,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&'static str, &AtomicFixedString)> {
+        Self::KINDS.into_iter().zip([
+            &self.status,
+            &self.database,
+            &self.request,
+            &self.response,
+            &self.config,
+            &self.home_assistant,
+        ])
+    }
+
+    fn get_mut(&mut self, kind: &str) -> Option<&mut AtomicFixedString> {
+        match kind {
+            "status" => Some(&mut self.status),
+            "database" => Some(&mut self.database),
+            "request" => Some(&mut self.request),
+            "response" => Some(&mut self.response),
+            "config" => Some(&mut self.config),
+            "home_assistant" => Some(&mut self.home_assistant),
+            _ => None,
+        }
+    }
+}
+
+// Overrides of the data directory and the namespace config file, args take precedence
+// over environment variables.
+#[derive(Debug, clap::Args)]
+pub struct Overrides {
+    /// directory of configs, state, caches and logs [default: ./data]
+    #[arg(long, env = "TRIPONICS_DATA_DIR", global = true)]
+    pub data_dir: Option<PathBuf>,
+
+    /// project topic segment
+    #[arg(long, env = "TRIPONICS_PROJECT", global = true)]
+    pub project: Option<String>,
+
+    /// device topic segment
+    #[arg(long, env = "TRIPONICS_DEVICE", global = true)]
+    pub device: Option<String>,
+
+    /// topic prefix of a kind of message, e.g. `status=tower-1/status`; kinds are status,
+    /// database, request, response, config and home_assistant
+    #[arg(
+        long = "topic-prefix",
+        value_name = "KIND=PREFIX",
+        env = "TRIPONICS_TOPIC_PREFIXES",
+        value_delimiter = ',',
+        value_parser = Overrides::parse_prefix,
+        global = true
+    )]
+    pub topic_prefixes: Vec<(String, String)>,
+}
+impl Overrides {
+    fn parse_prefix(arg: &str) -> Result<(String, String), String> {
+        let (kind, prefix) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("expected KIND=PREFIX, got '{arg}'"))?;
+
+        if !Prefixes::KINDS.contains(&kind) {
+            return Err(format!(
+                "unknown kind '{kind}', expected one of {}",
+                Prefixes::KINDS.join(", ")
+            ));
+        }
+
+        Ok((kind.into(), prefix.into()))
+    }
+
+    /// Applies the overrides on top of the loaded `config`.
+    pub fn apply(&self, mut config: Config) -> anyhow::Result<Config> {
+        if let Some(project) = &self.project {
+            config.project = project.clone().into();
+        }
+
+        if let Some(device) = &self.device {
+            config.device = device.clone().into();
+        }
+
+        for (kind, prefix) in &self.topic_prefixes {
+            *config.prefixes.get_mut(kind).unwrap() = prefix.clone().into();
+        }
+
+        config::validate(&config).map_err(|errors| {
+            anyhow::anyhow!(
+                "invalid namespace:\n{}",
+                errors
+                    .iter()
+                    .map(|e| format!("  {e}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        })?;
+
+        Ok(config)
+    }
+}
@@ -103,3 +103,16 @@ impl std::fmt::Debug for AtomicFixedString {
         std::fmt::Debug::fmt(&self.0, f)
     }
 }
+impl schemars::JsonSchema for AtomicFixedString {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        String::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
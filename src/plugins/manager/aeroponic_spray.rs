@@ -1,19 +1,35 @@
+use std::collections::HashMap;
+
 use bevy_app::{Startup, Update};
-use bevy_ecs::system::{Commands, IntoSystem, Local, Res, ResMut, Resource};
-use bevy_internal::{prelude::DetectChanges, time::common_conditions::on_timer};
+use bevy_ecs::{
+    change_detection::Mut,
+    schedule::IntoSystemConfigs,
+    system::{Commands, IntoSystem, Res, ResMut, Resource},
+    world::World,
+};
+use bevy_internal::{
+    prelude::{DetectChanges, DetectChangesMut},
+    time::{common_conditions::on_timer, Time},
+};
+use bevy_tokio_tasks::TokioTasksRuntime;
 
 use super::relay_module;
 use crate::{
-    config::ConfigFile,
+    config::{ConfigFile, Errors, HotReload, Validate},
     constants,
     helper::ErrorLogFormat,
     log,
     mqtt::add_on::action_message::ConfigMessage,
     plugins::{
         manager,
-        mqtt::{self, add_on::action_message::StatusMessage},
+        mqtt::{
+            self,
+            add_on::action_message::{RequestMessage, StatusMessage},
+            message::MessageInfo,
+        },
         state_file,
     },
+    AtomicFixedString,
 };
 
 pub struct Plugin {
@@ -22,99 +38,488 @@ pub struct Plugin {
 impl bevy_app::Plugin for Plugin {
     fn build(&self, app: &mut bevy_app::App) {
         app.init_resource::<manager::RelayManager>()
-            .insert_resource(Manager::new(self.config))
+            .insert_resource(Manager::new(&self.config))
+            .insert_resource(Schedule::new(&self.config))
+            .insert_resource(Pressure::new(&self.config))
             .add_plugins((
                 StatusMessage::<Manager, action::AeroponicSprayerStatus>::publish_condition(
                     on_timer(std::time::Duration::from_secs(1)),
                 ),
                 ConfigMessage::<Manager, Config>::new(),
+                RequestMessage::<Manager>::new(),
                 state_file::StateFile::<Manager>::new(),
             ))
-            .add_systems(Startup, (Manager::setup,))
-            .add_systems(Update, (Manager::watcher, Manager::update));
+            .add_systems(
+                Startup,
+                (
+                    Manager::setup,
+                    Manager::setup_climate_sensor,
+                    Manager::setup_pressure_sensor,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    Manager::follow_schedule,
+                    Manager::watcher,
+                    Manager::regulate_pressure,
+                    Manager::update,
+                )
+                    .chain(),
+            );
     }
 }
 
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 pub struct Config {
     #[serde(
         serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
         deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
     )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
     pub spray_duration: std::time::Duration,
     #[serde(
         serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
         deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
     )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
     pub spray_interval: std::time::Duration,
+    /// time-of-day profiles, each runs from its `start_time` until the next one starts,
+    /// `spray_duration` and `spray_interval` apply without profiles
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    /// scales the spray interval by the air temperature or VPD
+    #[serde(default)]
+    pub adaptive: Option<Adaptive>,
+    /// shared pump, runs while any zone sprays
+    #[serde(default = "Config::default_pump")]
+    pub pump: relay_module::Channel,
+    /// solenoid zones sharing the pump, without zones the pump sprays on its own
+    #[serde(default)]
+    pub zones: Vec<ZoneConfig>,
+    /// zones spraying at the same time, due zones wait for a free slot
+    #[serde(default = "Config::default_max_concurrent_zones")]
+    pub max_concurrent_zones: usize,
+    /// runs the pump to keep an accumulator in a pressure band instead of with the zones
+    #[serde(default)]
+    pub pressure: Option<PressureConfig>,
+}
+impl Config {
+    fn default_pump() -> relay_module::Channel {
+        relay_module::Channel::Relay2
+    }
+
+    fn default_max_concurrent_zones() -> usize {
+        1
+    }
+
+    /// pump and zone valve relays
+    pub fn relays(&self) -> impl Iterator<Item = relay_module::Channel> + '_ {
+        std::iter::once(self.pump).chain(self.zones.iter().map(|zone| zone.valve))
+    }
 }
 impl Default for Config {
     fn default() -> Self {
         Self {
             spray_duration: std::time::Duration::from_secs(3),
             spray_interval: std::time::Duration::from_secs(5 * 60),
+            profiles: Vec::new(),
+            adaptive: None,
+            pump: Config::default_pump(),
+            zones: Vec::new(),
+            max_concurrent_zones: Config::default_max_concurrent_zones(),
+            pressure: None,
         }
     }
 }
+impl Validate for Config {
+    fn validate(&self, errors: &mut Errors) {
+        // the adaptive factor shortens every interval down to its lowest valid point
+        let min_factor = self.adaptive.as_ref().map_or(1.0, |adaptive| {
+            adaptive
+                .curve
+                .iter()
+                .map(|p| p.factor)
+                .filter(|factor| ScalePoint::FACTORS.contains(factor))
+                .fold(1.0, f32::min)
+        });
+
+        let check_spray = |errors: &mut Errors,
+                           path: &str,
+                           spray_duration: std::time::Duration,
+                           spray_interval: std::time::Duration| {
+            errors.check_duration(
+                format_args!("{path}spray_interval"),
+                spray_interval,
+                std::time::Duration::from_secs(24 * 60 * 60),
+            );
+            errors.check(
+                !spray_duration.is_zero(),
+                format_args!("{path}spray_duration"),
+                "must be positive",
+            );
+            errors.check(
+                spray_duration < spray_interval.mul_f32(min_factor),
+                format_args!("{path}spray_duration"),
+                if min_factor < 1.0 {
+                    "must be shorter than spray_interval at the lowest adaptive factor"
+                } else {
+                    "must be shorter than spray_interval"
+                },
+            );
+        };
+
+        check_spray(errors, "", self.spray_duration, self.spray_interval);
+
+        for (i, profile) in self.profiles.iter().enumerate() {
+            check_spray(
+                errors,
+                &format!("profiles[{i}]."),
+                profile.spray_duration,
+                profile.spray_interval,
+            );
+            errors.check(
+                !self.profiles[..i].iter().any(|p| p.name == profile.name),
+                format_args!("profiles[{i}].name"),
+                format_args!("duplicate profile '{}'", profile.name),
+            );
+        }
+
+        if let Some(adaptive) = self.adaptive.as_ref() {
+            errors.check(
+                !adaptive.curve.is_empty(),
+                "adaptive.curve",
+                "needs at least one point",
+            );
+            for (i, point) in adaptive.curve.iter().enumerate() {
+                errors.check(
+                    ScalePoint::FACTORS.contains(&point.factor),
+                    format_args!("adaptive.curve[{i}].factor"),
+                    format_args!(
+                        "must be between {} and {}",
+                        ScalePoint::FACTORS.start(),
+                        ScalePoint::FACTORS.end()
+                    ),
+                );
+            }
+        }
+
+        for (i, zone) in self.zones.iter().enumerate() {
+            if zone.spray_duration.is_some() || zone.spray_interval.is_some() {
+                check_spray(
+                    errors,
+                    &format!("zones[{i}]."),
+                    zone.spray_duration.unwrap_or(self.spray_duration),
+                    zone.spray_interval.unwrap_or(self.spray_interval),
+                );
+            }
+            errors.check(
+                !self.zones[..i].iter().any(|z| z.name == zone.name),
+                format_args!("zones[{i}].name"),
+                format_args!("duplicate zone '{}'", zone.name),
+            );
+            errors.check(
+                zone.valve != self.pump,
+                format_args!("zones[{i}].valve"),
+                "is the pump relay",
+            );
+            errors.check(
+                !self.zones[..i].iter().any(|z| z.valve == zone.valve),
+                format_args!("zones[{i}].valve"),
+                format_args!("{:?} is used by another zone", zone.valve),
+            );
+        }
+
+        errors.check(
+            self.max_concurrent_zones > 0,
+            "max_concurrent_zones",
+            "must be at least 1",
+        );
+
+        if let Some(pressure) = self.pressure.as_ref() {
+            errors.check(
+                pressure.low >= 0.0 && pressure.low < pressure.high,
+                "pressure.low",
+                "must be between 0 and pressure.high",
+            );
+            errors.check(
+                pressure.min_rise > 0.0,
+                "pressure.min_rise",
+                "must be positive",
+            );
+            errors.check_duration(
+                "pressure.rise_timeout",
+                pressure.rise_timeout,
+                std::time::Duration::from_secs(60 * 60),
+            );
+            errors.check(
+                pressure.max_drop_rate > 0.0,
+                "pressure.max_drop_rate",
+                "must be positive",
+            );
+            if let manager::pressure_sensor::Config::Modbus {
+                raw_min, raw_max, ..
+            } = pressure.sensor
+            {
+                errors.check(
+                    raw_min < raw_max,
+                    "pressure.sensor.raw_max",
+                    "must be above raw_min",
+                );
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct Profile {
+    pub name: AtomicFixedString,
+    #[serde(
+        serialize_with = "crate::helper::serde_time::serialize_time",
+        deserialize_with = "crate::helper::serde_time::deserialize_time"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Time")]
+    pub start_time: time::Time,
+    #[serde(
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    pub spray_duration: std::time::Duration,
+    #[serde(
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    pub spray_interval: std::time::Duration,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct ZoneConfig {
+    pub name: AtomicFixedString,
+    pub valve: relay_module::Channel,
+    /// replaces the profile spray duration for this zone
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::helper::serde_time::serialize_optional_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_optional_duration_formatted"
+    )]
+    #[schemars(with = "Option<crate::helper::serde_time::schema::Duration>")]
+    pub spray_duration: Option<std::time::Duration>,
+    /// replaces the profile spray interval for this zone, still scaled by `adaptive`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::helper::serde_time::serialize_optional_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_optional_duration_formatted"
+    )]
+    #[schemars(with = "Option<crate::helper::serde_time::schema::Duration>")]
+    pub spray_interval: Option<std::time::Duration>,
+}
+
+/// Accumulator pressure band and fault limits, pressures in bar.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct PressureConfig {
+    pub sensor: manager::pressure_sensor::Config,
+    /// the pump starts at or below
+    pub low: f32,
+    /// the pump stops at or above
+    pub high: f32,
+    /// rise expected within `rise_timeout` of pumping, less means a dry run or a leak
+    #[serde(default = "PressureConfig::default_min_rise")]
+    pub min_rise: f32,
+    #[serde(
+        default = "PressureConfig::default_rise_timeout",
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    pub rise_timeout: std::time::Duration,
+    /// drop in bar/s while spraying that means a burst line
+    #[serde(default = "PressureConfig::default_max_drop_rate")]
+    pub max_drop_rate: f32,
+}
+impl PressureConfig {
+    fn default_min_rise() -> f32 {
+        0.5
+    }
+
+    fn default_rise_timeout() -> std::time::Duration {
+        std::time::Duration::from_secs(30)
+    }
+
+    fn default_max_drop_rate() -> f32 {
+        2.0
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct Adaptive {
+    pub source: Source,
+    pub sensor: manager::climate_sensor::Config,
+    /// interval factor by reading, linearly interpolated and held beyond the ends
+    pub curve: Vec<ScalePoint>,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// air temperature in °C
+    Temperature,
+    /// vapour pressure deficit in kPa
+    Vpd,
+}
+
+/// Spray interval multiplied by `factor` at `value` of the adaptive source.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct ScalePoint {
+    pub value: f32,
+    pub factor: f32,
+}
+impl ScalePoint {
+    pub const FACTORS: std::ops::RangeInclusive<f32> = 0.1..=10.0;
+}
 impl mqtt::add_on::action_message::MessageImpl for Config {
-    const PREFIX: &'static str = constants::mqtt_prefix::CONFIG;
-    const PROJECT: &'static str = constants::project::NAME;
-    const GROUP: &'static str = "aeroponics";
-    const DEVICE: &'static str = constants::project::DEVICE;
+    const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Config;
+    const GROUP: &'static str = action::GROUP;
     const QOS: mqtt::Qos = mqtt::Qos::_1;
 }
 
-#[derive(Debug, Resource, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Resource)]
 pub struct Manager {
+    /// shared pump, on while any zone sprays
     sprayer_state: bool,
-    #[serde(
-        serialize_with = "crate::helper::serde_time::serialize_offset_datetime_as_local",
-        deserialize_with = "crate::helper::serde_time::deserialize_offset_datetime_as_local"
-    )]
-    next_spray_time: time::OffsetDateTime,
-    #[serde(skip)]
+    pump: relay_module::Channel,
+    zones: Vec<Zone>,
+    max_concurrent_zones: usize,
+    /// scheduled sprays are held, a manual spray still runs
+    paused: bool,
+    /// unix timestamp resuming the schedule, `None` pauses until resumed
+    paused_until: Option<i64>,
+    skip_next: bool,
+    /// effective values of the active profile
     spray_duration: std::time::Duration,
-    #[serde(skip)]
     spray_interval: std::time::Duration,
+    interval_factor: f32,
+    /// the pump follows the accumulator pressure instead of the zones
+    accumulator: bool,
+    /// latched until cleared by request, holds every spray
+    fault: Option<action::Fault>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    name: AtomicFixedString,
+    /// `None` for the implicit zone of a sprayer without valves
+    valve: Option<relay_module::Channel>,
+    spray_duration: Option<std::time::Duration>,
+    spray_interval: Option<std::time::Duration>,
+    state: bool,
+    next_spray_time: time::OffsetDateTime,
+    spray_end_time: Option<time::OffsetDateTime>,
+    /// duration of a requested spray, runs while paused
+    manual: Option<std::time::Duration>,
 }
 impl Manager {
-    pub fn turn_on(&mut self) {
-        self.sprayer_state = true;
-    }
+    const DEFAULT_ZONE: &'static str = "main";
 
-    pub fn turn_off(&mut self) {
-        self.sprayer_state = false;
+    /// relays of the applied config, see [`Config::relays`]
+    pub fn relays(&self) -> impl Iterator<Item = relay_module::Channel> + '_ {
+        std::iter::once(self.pump).chain(self.zones.iter().filter_map(|zone| zone.valve))
     }
 
-    fn new(config: Config) -> Self {
+    fn new(config: &Config) -> Self {
+        let now = time::OffsetDateTime::now_utc().to_offset(*crate::timezone_offset());
+        let zone = |name: AtomicFixedString, valve, spray_duration, spray_interval| Zone {
+            name,
+            valve,
+            spray_duration,
+            spray_interval,
+            state: false,
+            next_spray_time: now,
+            spray_end_time: None,
+            manual: None,
+        };
+
+        let zones = if config.zones.is_empty() {
+            vec![zone(Self::DEFAULT_ZONE.into(), None, None, None)]
+        } else {
+            config
+                .zones
+                .iter()
+                .map(|z| {
+                    zone(
+                        z.name.clone(),
+                        Some(z.valve),
+                        z.spray_duration,
+                        z.spray_interval,
+                    )
+                })
+                .collect()
+        };
+
         Self {
             sprayer_state: false,
-            next_spray_time: time::OffsetDateTime::now_utc().to_offset(*crate::timezone_offset()),
+            pump: config.pump,
+            zones,
+            max_concurrent_zones: config.max_concurrent_zones.max(1),
+            paused: false,
+            paused_until: None,
+            skip_next: false,
             spray_duration: config.spray_duration,
             spray_interval: config.spray_interval,
+            interval_factor: 1.0,
+            accumulator: config.pressure.is_some(),
+            fault: None,
         }
     }
 
+    fn zone_duration(&self, zone: &Zone) -> std::time::Duration {
+        zone.spray_duration.unwrap_or(self.spray_duration)
+    }
+
+    fn zone_interval(&self, zone: &Zone) -> std::time::Duration {
+        zone.spray_interval
+            .map_or(self.spray_interval, |i| i.mul_f32(self.interval_factor))
+    }
+
+    fn next_spray_time(&self) -> time::OffsetDateTime {
+        self.zones
+            .iter()
+            .filter(|z| !z.state)
+            .map(|z| z.next_spray_time)
+            .min()
+            .or(self.zones.iter().map(|z| z.next_spray_time).min())
+            .unwrap()
+    }
+
+    fn spray_end_time(&self) -> Option<time::OffsetDateTime> {
+        self.zones.iter().filter_map(|z| z.spray_end_time).max()
+    }
+
     fn setup(mut cmd: Commands) {
         use crate::helper::ToBytes;
-        use mqtt::add_on::home_assistant::Device;
+        use mqtt::add_on::home_assistant::{self, Device};
 
         #[derive(serde::Serialize)]
         struct Config {
             name: &'static str,
             icon: &'static str,
-            state_topic: &'static str,
+            state_topic: AtomicFixedString,
             value_template: &'static str,
             device: Device,
         }
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/state/aeroponic_spray/config".into(),
+            topic: home_assistant::topic("sensor", "aeroponic_spray", "state"),
             payload: {
                 serde_json::to_value(Config {
                     name: "Sprayer Controller Command",
                     icon: "mdi:car-cruise-control",
-                    state_topic: "status/triponics/aeroponics/0",
+                    state_topic: action::AeroponicSprayerStatus::topic(),
                     value_template: "{{ \"ON\" if value_json.sprayer_state else \"OFF\" }}",
                     device: Device {
                         identifiers: &["aeroponics"],
@@ -129,12 +534,12 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/next_spray_time/aeroponic_spray/config".into(),
+            topic: home_assistant::topic("sensor", "aeroponic_spray", "next_spray_time"),
             payload: {
                 serde_json::to_value(Config {
                     name: "Next Scheduled Spray",
                     icon: "mdi:clock",
-                    state_topic: "status/triponics/aeroponics/0",
+                    state_topic: action::AeroponicSprayerStatus::topic(),
                     value_template:
                         "{{ (as_datetime(value_json.next_spray_time) | as_local | string )[:19] }}",
                     device: Device {
@@ -148,109 +553,876 @@ impl Manager {
             qos: mqtt::Qos::_1,
             retained: true,
         });
+
+        #[derive(serde::Serialize)]
+        struct Button {
+            name: &'static str,
+            icon: &'static str,
+            command_topic: AtomicFixedString,
+            command_template: &'static str,
+            payload_press: bool,
+            device: Device,
+        }
+
+        #[derive(serde::Serialize)]
+        struct Switch {
+            name: &'static str,
+            icon: &'static str,
+            command_topic: AtomicFixedString,
+            command_template: &'static str,
+            payload_on: bool,
+            payload_off: bool,
+            state_topic: AtomicFixedString,
+            value_template: &'static str,
+            state_on: bool,
+            state_off: bool,
+            device: Device,
+        }
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "aeroponic_spray", "spray_now"),
+            payload: {
+                serde_json::to_value(Button {
+                    name: "Spray Now",
+                    icon: "mdi:sprinkler",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"spray_now\" : {{value | lower}} }",
+                    payload_press: true,
+                    device: Device {
+                        identifiers: &["aeroponics"],
+                        name: "Aeroponics",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "aeroponic_spray", "skip_next"),
+            payload: {
+                serde_json::to_value(Button {
+                    name: "Skip Next Spray",
+                    icon: "mdi:skip-next",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"skip_next\" : {{value | lower}} }",
+                    payload_press: true,
+                    device: Device {
+                        identifiers: &["aeroponics"],
+                        name: "Aeroponics",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "aeroponic_spray", "clear_fault"),
+            payload: {
+                serde_json::to_value(Button {
+                    name: "Clear Spray Fault",
+                    icon: "mdi:alert-remove",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"clear_fault\" : {{value | lower}} }",
+                    payload_press: true,
+                    device: Device {
+                        identifiers: &["aeroponics"],
+                        name: "Aeroponics",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "aeroponic_spray", "fault"),
+            payload: {
+                serde_json::to_value(Config {
+                    name: "Spray Fault",
+                    icon: "mdi:alert",
+                    state_topic: action::AeroponicSprayerStatus::topic(),
+                    value_template: "{{ value_json.fault or \"none\" }}",
+                    device: Device {
+                        identifiers: &["aeroponics"],
+                        name: "Aeroponics",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        #[derive(serde::Serialize)]
+        struct Measurement {
+            name: &'static str,
+            device_class: &'static str,
+            state_topic: AtomicFixedString,
+            value_template: &'static str,
+            unit_of_measurement: &'static str,
+            device: Device,
+        }
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "aeroponic_spray", "pressure"),
+            payload: {
+                serde_json::to_value(Measurement {
+                    name: "Spray Pressure",
+                    device_class: "pressure",
+                    state_topic: action::AeroponicSprayerStatus::topic(),
+                    value_template: "{{ value_json.pressure | round(2) if value_json.pressure is not none else None }}",
+                    unit_of_measurement: "bar",
+                    device: Device {
+                        identifiers: &["aeroponics"],
+                        name: "Aeroponics",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("switch", "aeroponic_spray", "paused"),
+            payload: {
+                serde_json::to_value(Switch {
+                    name: "Pause Spraying",
+                    icon: "mdi:pause-circle",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"pause\" : {{value | lower}} }",
+                    payload_on: true,
+                    payload_off: false,
+                    state_topic: action::AeroponicSprayerStatus::topic(),
+                    value_template: "{{ value_json.paused }}",
+                    state_on: true,
+                    state_off: false,
+                    device: Device {
+                        identifiers: &["aeroponics"],
+                        name: "Aeroponics",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+    }
+
+    fn setup_climate_sensor(rt: Res<TokioTasksRuntime>, mut schedule: ResMut<Schedule>) {
+        let Some(adaptive) = schedule.adaptive.as_ref() else {
+            return;
+        };
+
+        match manager::climate_sensor::Sensor::new(&adaptive.sensor, &rt) {
+            Ok(sensor) => schedule.sensor = Some(sensor),
+            Err(e) => log::error!("[aeroponic_spray] climate sensor disabled, reason: {e}"),
+        }
+    }
+
+    /// Applies the time-of-day profile and the adaptive interval factor, a shorter
+    /// interval pulls the next spray forward.
+    fn follow_schedule(mut this: ResMut<Self>, mut schedule: ResMut<Schedule>) {
+        let now = time::OffsetDateTime::now_utc();
+        let local_now = now.to_offset(*crate::timezone_offset());
+
+        let (name, spray_duration, spray_interval) = match schedule.profile_at(local_now.time()) {
+            Some(profile) => (
+                Some(profile.name.clone()),
+                profile.spray_duration,
+                profile.spray_interval,
+            ),
+            None => (None, schedule.spray_duration, schedule.spray_interval),
+        };
+
+        if schedule.profile != name {
+            log::info!(
+                "[aeroponic_spray] <APP> profile -> {} (every {:.0}s for {:.1}s)",
+                name.as_ref().map_or("default", |n| n.as_ref()),
+                spray_interval.as_secs_f32(),
+                spray_duration.as_secs_f32()
+            );
+            schedule.profile = name;
+        }
+
+        schedule.reading = schedule.sensor.as_ref().and_then(|sensor| {
+            let reading = sensor.reading()?;
+            match schedule.adaptive.as_ref()?.source {
+                Source::Temperature => Some(reading.temperature),
+                Source::Vpd => Some(reading.vpd()),
+            }
+        });
+        // 5 % steps, so the interval is not rewritten on every sensor update
+        let factor = schedule.reading.map_or(1.0, |value| {
+            (schedule.factor_at(value) * 20.0).round() / 20.0
+        });
+        if schedule.factor != factor {
+            log::debug!(
+                "[aeroponic_spray] interval factor -> {factor:.2} (reading: {:?})",
+                schedule.reading
+            );
+            schedule.factor = factor;
+        }
+
+        let spray_interval = spray_interval.mul_f32(factor);
+        if this.spray_duration == spray_duration
+            && this.spray_interval == spray_interval
+            && this.interval_factor == factor
+        {
+            return;
+        }
+
+        // effective values are not persisted
+        let manager = this.bypass_change_detection();
+        manager.spray_duration = spray_duration;
+        manager.spray_interval = spray_interval;
+        manager.interval_factor = factor;
+
+        let shortened = manager
+            .zones
+            .iter()
+            .map(|z| !z.state && z.next_spray_time > now + manager.zone_interval(z))
+            .collect::<Vec<_>>();
+        if !shortened.contains(&true) {
+            return;
+        }
+
+        let this = &mut *this;
+        for (i, shortened) in shortened.into_iter().enumerate() {
+            if shortened {
+                let next_spray_time = now + this.zone_interval(&this.zones[i]);
+                let zone = &mut this.zones[i];
+                zone.next_spray_time = next_spray_time;
+
+                log::info!(
+                    "[aeroponic_spray] <APP> {} interval shortened (next spray time: {})",
+                    zone.name,
+                    local_time(zone.next_spray_time)
+                );
+            }
+        }
+    }
+
+    fn setup_pressure_sensor(rt: Res<TokioTasksRuntime>, mut pressure: ResMut<Pressure>) {
+        let Some(config) = pressure.config.as_ref() else {
+            return;
+        };
+
+        match manager::pressure_sensor::Sensor::new(&config.sensor, &rt) {
+            Ok(sensor) => pressure.sensor = Some(sensor),
+            Err(e) => log::error!("[aeroponic_spray] pressure sensor disabled, reason: {e}"),
+        }
+    }
+
+    /// Keeps the accumulator within the pressure band and latches a fault when the
+    /// pressure does not rise while pumping or drops too fast while spraying.
+    fn regulate_pressure(mut this: ResMut<Self>, mut pressure: ResMut<Pressure>, time: Res<Time>) {
+        let Some(config) = pressure.config.clone() else {
+            return;
+        };
+
+        // the pump only follows the pressure, without a sensor the zones would spray dry
+        if pressure.sensor.is_none() {
+            if this.fault.is_none() {
+                this.latch_fault(action::Fault::SensorFailure, None);
+            }
+            return;
+        }
+
+        let now = time::OffsetDateTime::now_utc();
+        let open_zones = this.zones.iter().filter(|z| z.state).count();
+
+        if let Some(sensor) = pressure.sensor.as_mut() {
+            sensor.simulate(this.sprayer_state, open_zones, time.delta_seconds());
+        }
+        pressure.pressure = pressure.sensor.as_ref().and_then(|s| s.pressure());
+
+        let Some(current) = pressure.pressure else {
+            if this.sprayer_state {
+                log::warn!("[aeroponic_spray] <APP> pressure unknown, pump -> OFF");
+                this.sprayer_state = false;
+            }
+            pressure.reset_tracking();
+            return;
+        };
+
+        if this.fault.is_some() {
+            // a cleared fault starts over instead of judging the pressure from before it
+            pressure.reset_tracking();
+            return;
+        }
+
+        let pump = if current <= config.low {
+            true
+        } else if current >= config.high {
+            false
+        } else {
+            this.sprayer_state
+        };
+
+        if this.sprayer_state != pump {
+            this.sprayer_state = pump;
+            pressure.pumping_since = pump.then_some((now, current));
+
+            log::info!(
+                "[aeroponic_spray] <APP> pressure {current:.2} bar, pump -> {}",
+                if pump { "ON" } else { "OFF" }
+            );
+        }
+
+        if let Some((since, start)) = pressure.pumping_since {
+            if now - since >= config.rise_timeout {
+                if current - start < config.min_rise {
+                    this.latch_fault(action::Fault::DryRun, Some(current));
+                    pressure.reset_tracking();
+                    return;
+                }
+                pressure.pumping_since = Some((now, current));
+            }
+        }
+
+        match pressure.sample {
+            Some((at, last)) if now - at >= time::Duration::SECOND => {
+                let drop_rate = (last - current) / (now - at).as_seconds_f32();
+                pressure.sample = Some((now, current));
+
+                if open_zones > 0 && drop_rate > config.max_drop_rate {
+                    this.latch_fault(action::Fault::BurstLine, Some(current));
+                    pressure.reset_tracking();
+                }
+            }
+            Some(_) => {}
+            None => pressure.sample = Some((now, current)),
+        }
+    }
+
+    fn latch_fault(&mut self, fault: action::Fault, pressure: Option<f32>) {
+        let now = time::OffsetDateTime::now_utc();
+        match pressure {
+            Some(pressure) => log::error!(
+                "[aeroponic_spray] <APP> fault: {fault} at {pressure:.2} bar, pump and zones -> OFF"
+            ),
+            None => log::error!("[aeroponic_spray] <APP> fault: {fault}, pump and zones -> OFF"),
+        }
+
+        for i in 0..self.zones.len() {
+            if self.zones[i].state {
+                let next_spray_time = now + self.zone_interval(&self.zones[i]);
+                let zone = &mut self.zones[i];
+                zone.state = false;
+                zone.spray_end_time = None;
+                zone.next_spray_time = next_spray_time;
+            }
+            self.zones[i].manual = None;
+        }
+        self.sprayer_state = false;
+        self.fault = Some(fault);
     }
 
     fn update(mut relay_manager: ResMut<relay_module::Manager>, this: Res<Self>) {
         if this.is_changed() {
-            if let Err(e) = relay_manager.update_state(
-                relay_module::action::Update {
-                    relay_2: Some(this.sprayer_state),
-                    ..relay_module::action::Update::empty()
-                }, //
-            ) {
-                log::warn!(
-                    "[aeroponic_spray] failed to update relay manager, reason:\n{}",
-                    e.fmt_error()
-                )
+            // valves open before the pump starts and close after it stops
+            let valves = this
+                .zones
+                .iter()
+                .filter_map(|z| z.valve.map(|valve| valve.update(z.state)));
+            let pump = std::iter::once(this.pump.update(this.sprayer_state));
+
+            let updates: Vec<_> = if this.sprayer_state {
+                valves.chain(pump).collect()
+            } else {
+                pump.chain(valves).collect()
+            };
+
+            for update in updates {
+                if let Err(e) = relay_manager.update_state(update) {
+                    log::warn!(
+                        "[aeroponic_spray] failed to update relay manager, reason:\n{}",
+                        e.fmt_error()
+                    )
+                }
             }
         }
     }
 
-    fn watcher(mut this: ResMut<Self>, mut maybe_end_time: Local<Option<time::OffsetDateTime>>) {
+    fn watcher(mut this: ResMut<Self>) {
         let now = time::OffsetDateTime::now_utc();
+        // only real transitions mark the manager changed, it is persisted on change
+        let mut changed = false;
+        let manager = &mut this;
+        let this = manager.bypass_change_detection();
 
-        if let Some(end_time) = *maybe_end_time {
-            if end_time <= now {
-                this.turn_off();
-                *maybe_end_time = None;
-                this.next_spray_time = now + this.spray_interval;
-
-                let next_spray_local_time = {
-                    let o = this.next_spray_time;
-                    o.to_offset(*crate::timezone_offset())
-                        .format(&crate::time_log_fmt())
-                        .unwrap()
-                };
+        for i in 0..this.zones.len() {
+            if this.zones[i]
+                .spray_end_time
+                .is_some_and(|end_time| end_time <= now)
+            {
+                let next_spray_time = now + this.zone_interval(&this.zones[i]);
+                let zone = &mut this.zones[i];
+                zone.state = false;
+                zone.spray_end_time = None;
+                zone.next_spray_time = next_spray_time;
+                changed = true;
 
                 log::info!(
-                    "[aeroponic_spray] <APP> set -> OFF (next spray time: {})",
-                    next_spray_local_time
+                    "[aeroponic_spray] <APP> {} set -> OFF (next spray time: {})",
+                    zone.name,
+                    local_time(zone.next_spray_time)
                 )
             }
-            return;
         }
 
-        if this.next_spray_time <= now && maybe_end_time.is_none() {
-            let end_time = now + this.spray_duration;
+        if this.paused {
+            if let Some(until) = this
+                .paused_until
+                .filter(|until| *until <= now.unix_timestamp())
+            {
+                this.paused = false;
+                this.paused_until = None;
+                changed = true;
+                log::info!(
+                    "[aeroponic_spray] <APP> pause ended at {} -> resumed",
+                    local_time(time::OffsetDateTime::from_unix_timestamp(until).unwrap_or(now))
+                );
+            }
+        }
+
+        // due zones in order of their spray time, manual sprays first
+        let mut due = this
+            .zones
+            .iter()
+            .enumerate()
+            .filter(|(_, z)| {
+                this.fault.is_none()
+                    && !z.state
+                    && (z.manual.is_some() || (!this.paused && z.next_spray_time <= now))
+            })
+            .map(|(i, z)| (z.manual.is_none(), z.next_spray_time, i))
+            .collect::<Vec<_>>();
+        due.sort();
+
+        for (scheduled, _, i) in due {
+            if scheduled && this.skip_next {
+                this.skip_next = false;
+                let next_spray_time = now + this.zone_interval(&this.zones[i]);
+                let zone = &mut this.zones[i];
+                zone.next_spray_time = next_spray_time;
+                changed = true;
+
+                log::info!(
+                    "[aeroponic_spray] <APP> {} spray skipped (next spray time: {})",
+                    zone.name,
+                    local_time(zone.next_spray_time)
+                );
+                continue;
+            }
+
+            if this.zones.iter().filter(|z| z.state).count() >= this.max_concurrent_zones {
+                break;
+            }
 
-            this.turn_on();
-            *maybe_end_time = Some(end_time);
+            let duration = this.zones[i]
+                .manual
+                .take()
+                .unwrap_or(this.zone_duration(&this.zones[i]));
+            let zone = &mut this.zones[i];
+            zone.state = true;
+            zone.spray_end_time = Some(now + duration);
+            changed = true;
 
             log::info!(
-                "[aeroponic_spray] <APP> set -> ON (spray until: {})",
-                end_time
-                    .to_offset(*crate::timezone_offset())
-                    .format(&crate::time_log_fmt())
-                    .unwrap()
+                "[aeroponic_spray] <APP> {} set -> ON (spray until: {})",
+                zone.name,
+                local_time(now + duration)
             );
         }
+
+        if !this.accumulator {
+            this.sprayer_state = this.zones.iter().any(|z| z.state);
+        }
+
+        if changed {
+            manager.set_changed();
+        }
+    }
+
+    fn update_state(
+        &mut self,
+        request: action::Update,
+    ) -> Result<AtomicFixedString, AtomicFixedString> {
+        let action::Update {
+            spray_now,
+            spray_duration,
+            zone,
+            pause,
+            pause_until,
+            resume,
+            skip_next,
+            clear_fault,
+            status,
+        } = request;
+        let now = time::OffsetDateTime::now_utc();
+        let mut out = Vec::new();
+
+        if let Some(until) = pause_until {
+            if until <= now.unix_timestamp() {
+                return Err(format!("pause end is in the past: {until}").into());
+            }
+        }
+
+        if let Some(zone) = &zone {
+            if !self.zones.iter().any(|z| z.name == *zone) {
+                return Err(format!("unknown zone '{zone}'").into());
+            }
+        }
+
+        // everything is checked before the first change
+        let spraying = spray_now == Some(true) || spray_duration.is_some();
+
+        let spray_duration = match spray_duration {
+            Some(duration) if duration > 0.0 => Some(
+                std::time::Duration::try_from_secs_f32(duration)
+                    .map_err(|_| format!("invalid spray duration: {duration}"))?,
+            ),
+            Some(duration) => return Err(format!("invalid spray duration: {duration}").into()),
+            None => None,
+        };
+
+        if let Some(duration) = spray_duration {
+            // a spray has to end before the zone is due again
+            for z in self.zones.iter() {
+                if zone.as_ref().is_some_and(|zone| z.name != *zone) {
+                    continue;
+                }
+
+                let interval = self.zone_interval(z);
+                if duration >= interval {
+                    return Err(format!(
+                        "spray duration of {}s must be shorter than the {} spray interval of {}s",
+                        duration.as_secs_f32(),
+                        z.name,
+                        interval.as_secs_f32()
+                    )
+                    .into());
+                }
+            }
+        }
+
+        if spraying && clear_fault != Some(true) {
+            if let Some(fault) = self.fault {
+                return Err(format!("spraying held by a {fault} fault, clear it first").into());
+            }
+        }
+
+        if clear_fault == Some(true) {
+            match self.fault.take() {
+                Some(fault) => out.push(format!("{fault} fault cleared")),
+                None => out.push("no fault to clear".to_string()),
+            }
+        }
+
+        if resume == Some(true) || pause == Some(false) {
+            self.paused = false;
+            self.paused_until = None;
+            out.push("spraying resumed".to_string());
+        }
+
+        if pause == Some(true) || pause_until.is_some() {
+            self.paused = true;
+            self.paused_until = pause_until;
+
+            for i in 0..self.zones.len() {
+                if self.zones[i].state {
+                    let next_spray_time = now + self.zone_interval(&self.zones[i]);
+                    let zone = &mut self.zones[i];
+                    zone.state = false;
+                    zone.spray_end_time = None;
+                    zone.next_spray_time = next_spray_time;
+                }
+                self.zones[i].manual = None;
+            }
+            self.sprayer_state = false;
+
+            match pause_until {
+                Some(until) => out.push(format!(
+                    "spraying paused until {}",
+                    local_time(time::OffsetDateTime::from_unix_timestamp(until).unwrap_or(now))
+                )),
+                None => out.push("spraying paused".to_string()),
+            }
+        }
+
+        if skip_next == Some(true) {
+            self.skip_next = true;
+            out.push(format!(
+                "skipping the spray at {}",
+                local_time(self.next_spray_time())
+            ));
+        }
+
+        if spraying {
+            let default_duration = self.spray_duration;
+            let mut names = Vec::new();
+
+            for z in self.zones.iter_mut() {
+                if zone.as_ref().is_some_and(|zone| z.name != *zone) {
+                    continue;
+                }
+
+                let duration = spray_duration
+                    .or(z.spray_duration)
+                    .unwrap_or(default_duration);
+                z.manual = Some(duration);
+                names.push(z.name.to_string());
+            }
+
+            out.push(format!("spraying {} now", names.join(", ")));
+        }
+
+        if status == Some(true) {
+            out.push(self.summary());
+        }
+
+        if out.is_empty() {
+            Ok("nothing to do".into())
+        } else {
+            Ok(out.join("; ").into())
+        }
+    }
+
+    fn summary(&self) -> String {
+        let zones = self
+            .zones
+            .iter()
+            .map(|z| match z.spray_end_time {
+                Some(end_time) => format!("{} spraying until {}", z.name, local_time(end_time)),
+                None => format!("{} next at {}", z.name, local_time(z.next_spray_time)),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let schedule = if let Some(fault) = self.fault {
+            format!("{fault} fault")
+        } else {
+            match (self.paused, self.paused_until) {
+                (true, Some(until)) => format!(
+                    "paused until {}",
+                    local_time(
+                        time::OffsetDateTime::from_unix_timestamp(until)
+                            .unwrap_or(self.next_spray_time())
+                    )
+                ),
+                (true, None) => "paused".to_string(),
+                (false, _) if self.skip_next => "skipping the next spray".to_string(),
+                (false, _) => "running".to_string(),
+            }
+        };
+
+        format!(
+            "{schedule}: {zones} (every {}s for {}s)",
+            self.spray_interval.as_secs_f32(),
+            self.spray_duration.as_secs_f32()
+        )
+    }
+}
+impl mqtt::add_on::action_message::RequestHandler for Manager {
+    type Request = action::Update;
+    type Response = action::Response;
+
+    fn update_state(request: Self::Request, state: &mut Self) -> Option<Self::Response> {
+        log::info!("[aeroponic_spray] <USER> set -> {request:?}");
+
+        Some(action::Response(state.update_state(request).map_err(|e| {
+            log::warn!("[aeroponic_spray] request rejected, reason: {e}");
+            e
+        })))
     }
 }
 impl ConfigFile for Manager {
     const FILENAME: &'static str = "aeroponic_spray";
     type Config = Config;
 }
+impl HotReload for Manager {
+    fn apply_config(config: &Config, world: &mut World) -> Result<(), AtomicFixedString> {
+        fn unchanged<T: serde::Serialize>(a: &T, b: &T) -> bool {
+            serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+        }
+
+        if let Some(nutrient_dosing) = world.get_resource::<manager::NutrientDosingManager>() {
+            nutrient_dosing.check_sprayer_relays(config.relays())?;
+        }
+
+        world.resource_scope(|world, rt: Mut<TokioTasksRuntime>| {
+            // replaced sensors stop polling when dropped, the serial bus stays open
+            let mut schedule = world.resource_mut::<Schedule>();
+            let keep = matches!(
+                (&schedule.adaptive, &config.adaptive),
+                (Some(a), Some(b)) if unchanged(&a.sensor, &b.sensor)
+            );
+            let sensor = schedule.sensor.take().filter(|_| keep);
+            *schedule = Schedule::new(config);
+            schedule.sensor = match (sensor, config.adaptive.as_ref()) {
+                (Some(sensor), _) => Some(sensor),
+                (None, Some(adaptive)) => {
+                    manager::climate_sensor::Sensor::new(&adaptive.sensor, &rt)
+                        .map_err(|e| {
+                            log::error!("[aeroponic_spray] climate sensor disabled, reason: {e}")
+                        })
+                        .ok()
+                }
+                (None, None) => None,
+            };
+
+            let mut pressure = world.resource_mut::<Pressure>();
+            let keep = matches!(
+                (&pressure.config, &config.pressure),
+                (Some(a), Some(b)) if unchanged(&a.sensor, &b.sensor)
+            );
+            let sensor = pressure.sensor.take().filter(|_| keep);
+            *pressure = Pressure::new(config);
+            pressure.sensor = match (sensor, config.pressure.as_ref()) {
+                (Some(sensor), _) => Some(sensor),
+                (None, Some(pressure_config)) => {
+                    manager::pressure_sensor::Sensor::new(&pressure_config.sensor, &rt)
+                        .map_err(|e| {
+                            log::error!("[aeroponic_spray] pressure sensor disabled, reason: {e}")
+                        })
+                        .ok()
+                }
+                (None, None) => None,
+            };
+        });
+
+        let now = time::OffsetDateTime::now_utc();
+        let mut this = world.resource_mut::<Manager>();
+        let mut next = Manager::new(config);
+
+        // zones keep their schedule and running spray by name
+        for zone in next.zones.iter_mut() {
+            if let Some(old) = this.zones.iter_mut().find(|z| z.name == zone.name) {
+                zone.state = old.state;
+                zone.next_spray_time = old.next_spray_time;
+                zone.spray_end_time = old.spray_end_time;
+                zone.manual = old.manual.take();
+            }
+        }
+        for i in 0..next.zones.len() {
+            let interval = this.zone_interval(&next.zones[i]);
+            let zone = &mut next.zones[i];
+            if !zone.state && zone.next_spray_time > now + interval {
+                zone.next_spray_time = now + interval;
+            }
+        }
+
+        let released = this
+            .zones
+            .iter()
+            .filter_map(|z| z.valve)
+            .chain(std::iter::once(this.pump))
+            .filter(|channel| {
+                *channel != next.pump && !next.zones.iter().any(|z| z.valve == Some(*channel))
+            })
+            .collect::<Vec<_>>();
+
+        this.pump = next.pump;
+        this.zones = next.zones;
+        this.max_concurrent_zones = next.max_concurrent_zones;
+        this.accumulator = next.accumulator;
+
+        let mut relay_manager = world.resource_mut::<relay_module::Manager>();
+        for channel in released {
+            log::info!("[aeroponic_spray] <APP> released {channel:?}");
+            if let Err(e) = relay_manager.update_state(channel.update(false)) {
+                log::warn!(
+                    "[aeroponic_spray] failed to update relay manager, reason:\n{}",
+                    e.fmt_error()
+                )
+            }
+        }
+
+        Ok(())
+    }
+}
 impl state_file::SaveState for Manager {
-    type State<'de> = Self;
+    type State<'de> = State;
 
     const FILENAME: &str = "aeroponic_spray_manager";
 
     fn build(state: Self::State<'_>, this: Option<Self>) -> Self {
-        if let Some(this) = this {
-            Self {
-                sprayer_state: state.sprayer_state,
-                next_spray_time: state.next_spray_time,
-                spray_duration: this.spray_duration,
-                spray_interval: this.spray_interval,
-            }
-        } else {
-            state
+        let mut this = this.unwrap_or_else(|| Self::new(&Config::default()));
+
+        for zone in this.zones.iter_mut() {
+            zone.next_spray_time = state
+                .zones
+                .get(&zone.name)
+                .and_then(|t| time::OffsetDateTime::from_unix_timestamp(*t).ok())
+                .unwrap_or(state.next_spray_time);
         }
+        this.paused = state.paused;
+        this.paused_until = state.paused_until;
+        this.skip_next = state.skip_next;
+        this.fault = state.fault;
+        this
     }
 
     fn save<'de>(&self) -> Self::State<'de> {
-        let Self {
-            sprayer_state,
-            next_spray_time,
-            spray_duration,
-            spray_interval,
-        } = *self;
-
-        Self {
-            sprayer_state,
-            next_spray_time,
-            spray_duration,
-            spray_interval,
+        State {
+            sprayer_state: self.sprayer_state,
+            next_spray_time: self.next_spray_time(),
+            zones: self
+                .zones
+                .iter()
+                .map(|z| (z.name.clone(), z.next_spray_time.unix_timestamp()))
+                .collect(),
+            paused: self.paused,
+            paused_until: self.paused_until,
+            skip_next: self.skip_next,
+            fault: self.fault,
         }
     }
 }
 impl mqtt::add_on::action_message::PublishStatus<action::AeroponicSprayerStatus> for Manager {
     fn query_state(
     ) -> impl bevy_internal::prelude::System<In = (), Out = action::AeroponicSprayerStatus> {
-        fn func(this: Res<Manager>) -> action::AeroponicSprayerStatus {
+        fn func(
+            this: Res<Manager>,
+            schedule: Res<Schedule>,
+            pressure: Res<Pressure>,
+        ) -> action::AeroponicSprayerStatus {
             action::AeroponicSprayerStatus {
                 sprayer_state: this.sprayer_state,
-                next_spray_time: this.next_spray_time.unix_timestamp().to_string().into(),
+                next_spray_time: this.next_spray_time().unix_timestamp().to_string().into(),
+                spray_end_time: this.spray_end_time().map(|t| t.unix_timestamp()),
+                zones: this
+                    .zones
+                    .iter()
+                    .map(|z| action::ZoneStatus {
+                        name: z.name.clone(),
+                        state: z.state,
+                        next_spray_time: z.next_spray_time.unix_timestamp(),
+                        spray_end_time: z.spray_end_time.map(|t| t.unix_timestamp()),
+                    })
+                    .collect(),
+                paused: this.paused,
+                paused_until: this.paused_until,
+                skip_next: this.skip_next,
+                profile: schedule.profile.clone(),
+                spray_duration: this.spray_duration.as_secs_f32(),
+                spray_interval: this.spray_interval.as_secs_f32(),
+                interval_factor: schedule.factor,
+                reading: schedule.reading,
+                pressure: pressure.pressure,
+                fault: this.fault,
             }
         }
 
@@ -258,19 +1430,225 @@ impl mqtt::add_on::action_message::PublishStatus<action::AeroponicSprayerStatus>
     }
 }
 
+/// Persisted part of the [`Manager`].
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct State {
+    sprayer_state: bool,
+    /// earliest next spray, applies to zones missing from `zones`
+    #[serde(
+        serialize_with = "crate::helper::serde_time::serialize_offset_datetime_as_local",
+        deserialize_with = "crate::helper::serde_time::deserialize_offset_datetime_as_local"
+    )]
+    next_spray_time: time::OffsetDateTime,
+    /// next spray unix timestamp by zone name
+    #[serde(default)]
+    zones: HashMap<AtomicFixedString, i64>,
+    #[serde(default)]
+    paused: bool,
+    #[serde(default)]
+    paused_until: Option<i64>,
+    #[serde(default)]
+    skip_next: bool,
+    #[serde(default)]
+    fault: Option<action::Fault>,
+}
+
+#[derive(Debug, Resource)]
+struct Pressure {
+    config: Option<PressureConfig>,
+    sensor: Option<manager::pressure_sensor::Sensor>,
+    /// bar
+    pressure: Option<f32>,
+    /// start of the current pump run, or of its last rise check, with the pressure then
+    pumping_since: Option<(time::OffsetDateTime, f32)>,
+    /// reference of the drop rate
+    sample: Option<(time::OffsetDateTime, f32)>,
+}
+impl Pressure {
+    fn new(config: &Config) -> Self {
+        Self {
+            config: config.pressure.clone(),
+            sensor: None,
+            pressure: None,
+            pumping_since: None,
+            sample: None,
+        }
+    }
+
+    fn reset_tracking(&mut self) {
+        self.pumping_since = None;
+        self.sample = None;
+    }
+}
+
+#[derive(Debug, Resource)]
+struct Schedule {
+    spray_duration: std::time::Duration,
+    spray_interval: std::time::Duration,
+    /// sorted by start time
+    profiles: Vec<Profile>,
+    adaptive: Option<Adaptive>,
+    sensor: Option<manager::climate_sensor::Sensor>,
+    profile: Option<AtomicFixedString>,
+    reading: Option<f32>,
+    factor: f32,
+}
+impl Schedule {
+    fn new(config: &Config) -> Self {
+        let mut profiles = config.profiles.clone();
+        profiles.sort_by_key(|p| p.start_time);
+
+        let mut adaptive = config.adaptive.clone();
+        if let Some(adaptive) = adaptive.as_mut() {
+            adaptive.curve.sort_by(|a, b| a.value.total_cmp(&b.value));
+        }
+
+        Self {
+            spray_duration: config.spray_duration,
+            spray_interval: config.spray_interval,
+            profiles,
+            adaptive,
+            sensor: None,
+            profile: None,
+            reading: None,
+            factor: 1.0,
+        }
+    }
+
+    /// The last profile started today, or the last one of yesterday before the first start.
+    fn profile_at(&self, now: time::Time) -> Option<&Profile> {
+        self.profiles
+            .iter()
+            .rev()
+            .find(|p| p.start_time <= now)
+            .or(self.profiles.last())
+    }
+
+    fn factor_at(&self, value: f32) -> f32 {
+        let Some(curve) = self.adaptive.as_ref().map(|a| &a.curve) else {
+            return 1.0;
+        };
+
+        let Some(next) = curve.iter().position(|p| p.value > value) else {
+            return curve.last().map_or(1.0, |p| p.factor);
+        };
+
+        if next == 0 {
+            return curve[0].factor;
+        }
+
+        let (a, b) = (curve[next - 1], curve[next]);
+        a.factor + (b.factor - a.factor) * (value - a.value) / (b.value - a.value)
+    }
+}
+
 pub mod action {
     use crate::{constants, plugins::mqtt, AtomicFixedString};
 
-    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub const GROUP: &str = "aeroponics";
+
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct AeroponicSprayerStatus {
         pub sprayer_state: bool,
         pub next_spray_time: AtomicFixedString,
+        /// end of the latest running zone spray
+        pub spray_end_time: Option<i64>,
+        pub zones: Vec<ZoneStatus>,
+        pub paused: bool,
+        pub paused_until: Option<i64>,
+        pub skip_next: bool,
+        /// active time-of-day profile, `None` for the default schedule
+        pub profile: Option<AtomicFixedString>,
+        /// effective spray duration in seconds
+        pub spray_duration: f32,
+        /// effective spray interval in seconds, after the adaptive factor
+        pub spray_interval: f32,
+        pub interval_factor: f32,
+        /// temperature or VPD the interval factor follows
+        pub reading: Option<f32>,
+        /// line pressure in bar
+        pub pressure: Option<f32>,
+        pub fault: Option<Fault>,
+    }
+
+    #[derive(
+        Debug,
+        Clone,
+        Copy,
+        PartialEq,
+        Eq,
+        serde::Serialize,
+        serde::Deserialize,
+        schemars::JsonSchema,
+    )]
+    #[serde(rename_all = "snake_case")]
+    pub enum Fault {
+        /// no pressure rise while pumping, empty reservoir or a leak
+        DryRun,
+        /// pressure dropping too fast while spraying
+        BurstLine,
+        /// accumulator pressure sensor missing, the pump can not be run
+        SensorFailure,
+    }
+    impl std::fmt::Display for Fault {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(match self {
+                Fault::DryRun => "dry run",
+                Fault::BurstLine => "burst line",
+                Fault::SensorFailure => "pressure sensor",
+            })
+        }
     }
     impl mqtt::add_on::action_message::MessageImpl for AeroponicSprayerStatus {
-        const PREFIX: &'static str = constants::mqtt_prefix::STATUS;
-        const PROJECT: &'static str = constants::project::NAME;
-        const GROUP: &'static str = "aeroponics";
-        const DEVICE: &'static str = constants::project::DEVICE;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Status;
+        const GROUP: &'static str = GROUP;
+        const QOS: mqtt::Qos = mqtt::Qos::_1;
+    }
+
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct ZoneStatus {
+        pub name: AtomicFixedString,
+        pub state: bool,
+        pub next_spray_time: i64,
+        pub spray_end_time: Option<i64>,
+    }
+
+    #[derive(Debug, Default, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+    pub struct Update {
+        /// sprays immediately for `spray_duration`, or the configured duration
+        pub spray_now: Option<bool>,
+        /// manual spray duration in seconds, implies `spray_now`
+        pub spray_duration: Option<f32>,
+        /// limits `spray_now` to one zone, every zone sprays in turn otherwise
+        pub zone: Option<AtomicFixedString>,
+        /// holds scheduled sprays until resumed, `false` resumes
+        pub pause: Option<bool>,
+        /// unix timestamp ending the pause, implies `pause`
+        pub pause_until: Option<i64>,
+        pub resume: Option<bool>,
+        pub skip_next: Option<bool>,
+        /// releases a latched pressure fault
+        pub clear_fault: Option<bool>,
+        /// replies with a summary of the spray schedule
+        pub status: Option<bool>,
+    }
+    impl mqtt::add_on::action_message::MessageImpl for Update {
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Request;
+        const GROUP: &'static str = GROUP;
         const QOS: mqtt::Qos = mqtt::Qos::_1;
     }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct Response(pub Result<AtomicFixedString, AtomicFixedString>);
+    impl mqtt::add_on::action_message::MessageImpl for Response {
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Response;
+        const GROUP: &'static str = GROUP;
+        const QOS: mqtt::Qos = mqtt::Qos::_1;
+    }
+}
+
+fn local_time(t: time::OffsetDateTime) -> String {
+    t.to_offset(*crate::timezone_offset())
+        .format(&crate::time_log_fmt())
+        .unwrap()
 }
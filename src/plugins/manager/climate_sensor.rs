@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use bevy_tokio_tasks::TokioTasksRuntime;
+
+use crate::{log, plugins::manager::modbus, AtomicFixedString};
+
+/// Air temperature and relative humidity sensor.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, schemars::JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[schemars(rename = "ClimateSensorConfig")]
+pub enum Config {
+    /// sensor holding temperature (signed) and humidity in holding registers
+    Modbus {
+        path: AtomicFixedString,
+        baud_rate: u32,
+        slave: u8,
+        temperature_register: u16,
+        humidity_register: u16,
+        /// °C and % per register count
+        #[serde(default = "Config::default_scale")]
+        scale: f32,
+    },
+    /// fixed reading, stands in for a missing sensor
+    Simulated { temperature: f32, humidity: f32 },
+}
+impl Config {
+    fn default_scale() -> f32 {
+        0.1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    /// °C
+    pub temperature: f32,
+    /// relative humidity in percent
+    pub humidity: f32,
+}
+impl Reading {
+    /// Vapour pressure deficit in kPa (Tetens equation).
+    pub fn vpd(&self) -> f32 {
+        let saturation = 0.6108 * (17.27 * self.temperature / (self.temperature + 237.3)).exp();
+        saturation * (1.0 - self.humidity.clamp(0.0, 100.0) / 100.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum Sensor {
+    Modbus {
+        rx: tokio::sync::watch::Receiver<Option<Reading>>,
+        _task: modbus::Task,
+    },
+    Simulated(Reading),
+}
+impl Sensor {
+    const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+    pub fn new(config: &Config, rt: &TokioTasksRuntime) -> Result<Self, AtomicFixedString> {
+        let (path, baud_rate, slave, registers, scale) = match *config {
+            Config::Modbus {
+                ref path,
+                baud_rate,
+                slave,
+                temperature_register,
+                humidity_register,
+                scale,
+            } => (
+                path,
+                baud_rate,
+                slave,
+                [temperature_register, humidity_register],
+                scale,
+            ),
+            Config::Simulated {
+                temperature,
+                humidity,
+            } => {
+                return Ok(Self::Simulated(Reading {
+                    temperature,
+                    humidity,
+                }))
+            }
+        };
+
+        let (tx, rx) = tokio::sync::watch::channel(None);
+
+        let bus = modbus::Bus::open(path, baud_rate, rt)?;
+
+        let task = rt.spawn_background_task(move |_| async move {
+            'poll: loop {
+                tokio::time::sleep(Self::POLL_INTERVAL).await;
+
+                let mut values = [0.0; 2];
+                for (value, register) in values.iter_mut().zip(registers) {
+                    match bus.read_holding_registers(slave, register, 1).await {
+                        Ok(data) => *value = data[0] as i16 as f32 * scale,
+                        Err(e) => {
+                            log::warn!("[climate_sensor] {e}");
+                            tx.send_replace(None);
+                            continue 'poll;
+                        }
+                    }
+                }
+
+                let [temperature, humidity] = values;
+                log::trace!(
+                    "[climate_sensor] temperature -> {temperature:.1}, humidity -> {humidity:.1}"
+                );
+
+                if tx
+                    .send(Some(Reading {
+                        temperature,
+                        humidity,
+                    }))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self::Modbus {
+            rx,
+            _task: task.into(),
+        })
+    }
+
+    pub fn reading(&self) -> Option<Reading> {
+        match self {
+            Sensor::Modbus { rx, .. } => *rx.borrow(),
+            Sensor::Simulated(reading) => Some(*reading),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use bevy_tokio_tasks::TokioTasksRuntime;
+
+use crate::{log, plugins::manager::modbus, AtomicFixedString};
+
+/// Brightness output of a dimmable light.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, schemars::JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[schemars(rename = "DimmerConfig")]
+pub enum Config {
+    /// rppal hardware PWM, `channel` 0 or 1
+    HardwarePwm {
+        channel: u8,
+        frequency: f64,
+        #[serde(default)]
+        inverted: bool,
+    },
+    /// rppal software PWM on any GPIO pin (BCM numbering)
+    SoftwarePwm {
+        pin: u8,
+        frequency: f64,
+        #[serde(default)]
+        inverted: bool,
+    },
+    /// 0-10 V dimmer module, `max_value` is the register value written for 10 V
+    Modbus {
+        path: AtomicFixedString,
+        baud_rate: u32,
+        slave: u8,
+        register: u16,
+        max_value: u16,
+    },
+    /// logs the output level instead of driving hardware
+    Simulated,
+}
+
+#[derive(Debug)]
+pub enum Output {
+    HardwarePwm {
+        pwm: rppal::pwm::Pwm,
+        inverted: bool,
+    },
+    SoftwarePwm {
+        pin: rppal::gpio::OutputPin,
+        frequency: f64,
+        inverted: bool,
+    },
+    Modbus {
+        tx: tokio::sync::watch::Sender<u16>,
+        max_value: u16,
+        _task: modbus::Task,
+    },
+    Simulated {
+        level: f32,
+    },
+}
+impl Output {
+    pub fn new(config: &Config, rt: &TokioTasksRuntime) -> Result<Self, AtomicFixedString> {
+        match config {
+            Config::HardwarePwm {
+                channel,
+                frequency,
+                inverted,
+            } => {
+                let channel = match channel {
+                    0 => rppal::pwm::Channel::Pwm0,
+                    1 => rppal::pwm::Channel::Pwm1,
+                    _ => return Err(format!("invalid hardware pwm channel: {channel}").into()),
+                };
+
+                let pwm = rppal::pwm::Pwm::with_frequency(
+                    channel,
+                    *frequency,
+                    Self::duty_cycle(0.0, *inverted),
+                    rppal::pwm::Polarity::Normal,
+                    true,
+                )
+                .map_err(|e| format!("failed to setup hardware pwm, reason: {e}"))?;
+
+                Ok(Self::HardwarePwm {
+                    pwm,
+                    inverted: *inverted,
+                })
+            }
+            Config::SoftwarePwm {
+                pin,
+                frequency,
+                inverted,
+            } => {
+                let mut pin = rppal::gpio::Gpio::new()
+                    .and_then(|gpio| gpio.get(*pin))
+                    .map_err(|e| format!("failed to setup pwm pin {pin}, reason: {e}"))?
+                    .into_output();
+
+                pin.set_pwm_frequency(*frequency, Self::duty_cycle(0.0, *inverted))
+                    .map_err(|e| format!("failed to start software pwm, reason: {e}"))?;
+
+                Ok(Self::SoftwarePwm {
+                    pin,
+                    frequency: *frequency,
+                    inverted: *inverted,
+                })
+            }
+            Config::Modbus {
+                path,
+                baud_rate,
+                slave,
+                register,
+                max_value,
+            } => {
+                let (tx, mut rx) = tokio::sync::watch::channel(0);
+
+                let bus = modbus::Bus::open(path, *baud_rate, rt)?;
+                let (slave, register) = (*slave, *register);
+
+                let task = rt.spawn_background_task(move |_| async move {
+                    while rx.changed().await.is_ok() {
+                        let value = *rx.borrow_and_update();
+
+                        match bus.write_single_register(slave, register, value).await {
+                            Ok(()) => log::trace!("[dimmer] modbus output -> {value}"),
+                            Err(e) => log::warn!("[dimmer] {e}"),
+                        }
+                    }
+                });
+
+                Ok(Self::Modbus {
+                    tx,
+                    max_value: *max_value,
+                    _task: task.into(),
+                })
+            }
+            Config::Simulated => Ok(Self::Simulated { level: 0.0 }),
+        }
+    }
+
+    /// Sets the output level, `level` is clamped to `0.0..=1.0`.
+    pub fn set(&mut self, level: f32) -> Result<(), AtomicFixedString> {
+        let level = level.clamp(0.0, 1.0);
+
+        match self {
+            Output::HardwarePwm { pwm, inverted } => pwm
+                .set_duty_cycle(Self::duty_cycle(level, *inverted))
+                .map_err(|e| format!("failed to set pwm duty cycle, reason: {e}").into()),
+            Output::SoftwarePwm {
+                pin,
+                frequency,
+                inverted,
+            } => pin
+                .set_pwm_frequency(*frequency, Self::duty_cycle(level, *inverted))
+                .map_err(|e| format!("failed to set pwm duty cycle, reason: {e}").into()),
+            Output::Modbus { tx, max_value, .. } => {
+                tx.send_replace((level * *max_value as f32).round() as u16);
+                Ok(())
+            }
+            Output::Simulated { level: current } => {
+                log::debug!("[dimmer] simulated output -> {current:.3} => {level:.3}");
+                *current = level;
+                Ok(())
+            }
+        }
+    }
+
+    fn duty_cycle(level: f32, inverted: bool) -> f64 {
+        if inverted {
+            1.0 - level as f64
+        } else {
+            level as f64
+        }
+    }
+}
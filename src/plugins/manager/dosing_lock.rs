@@ -0,0 +1,28 @@
+use bevy_ecs::system::Resource;
+
+/// Shared between the dosing managers so only one of them runs a pump at a time.
+#[derive(Debug, Default, Resource)]
+pub struct DosingLock {
+    owner: Option<&'static str>,
+}
+impl DosingLock {
+    pub fn try_acquire(&mut self, owner: &'static str) -> bool {
+        match self.owner {
+            Some(current) => current == owner,
+            None => {
+                self.owner = Some(owner);
+                true
+            }
+        }
+    }
+
+    pub fn release(&mut self, owner: &'static str) {
+        if self.owner == Some(owner) {
+            self.owner = None;
+        }
+    }
+
+    pub fn owner(&self) -> Option<&'static str> {
+        self.owner
+    }
+}
@@ -2,49 +2,387 @@ use std::time::Duration;
 
 use bevy_app::{Startup, Update};
 use bevy_ecs::{
-    schedule::IntoSystemConfigs,
-    system::{Commands, IntoSystem, Local, Res, ResMut, Resource},
+    change_detection::Mut,
+    schedule::{common_conditions::run_once, IntoSystemConfigs},
+    system::{Commands, IntoSystem, Res, ResMut, Resource},
+    world::World,
 };
-use bevy_internal::{prelude::DetectChanges, time::common_conditions::on_timer};
+use bevy_internal::{
+    prelude::{DetectChanges, DetectChangesMut},
+    time::{common_conditions::on_timer, Time},
+};
+use bevy_tokio_tasks::TokioTasksRuntime;
 
 use crate::{
-    config::ConfigFile,
+    config::{ConfigFile, Errors, HotReload, Migration, Validate},
     constants,
     helper::ToBytes,
     log,
     mqtt::add_on::action_message::ConfigMessage,
-    plugins::{manager, mqtt},
+    plugins::{
+        manager,
+        mqtt::{self, add_on::home_assistant, message::MessageInfo},
+        state_file,
+    },
+    AtomicFixedString,
 };
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, schemars::JsonSchema)]
 pub struct Config {
+    /// daily on-windows, a window may run past midnight and windows may overlap
+    #[serde(default)]
+    windows: Vec<Window>,
+    /// brightness setpoint in percent, requires `dimmer`
+    #[serde(default = "Config::default_brightness")]
+    brightness: f32,
     #[serde(
-        serialize_with = "crate::helper::serde_time::serialize_time",
-        deserialize_with = "crate::helper::serde_time::deserialize_time"
+        default,
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
     )]
-    start_time: time::Time,
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    sunrise_duration: Duration,
     #[serde(
+        default,
         serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
         deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
     )]
-    on_duration: Duration,
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    sunset_duration: Duration,
+    /// brightness output, the light is only switched by `relay_8` without one
+    #[serde(default)]
+    dimmer: Option<manager::dimmer::Config>,
+    /// separately driven spectrum channels, dimmed along with `brightness`
+    #[serde(default)]
+    channels: Vec<ChannelConfig>,
+    /// date the crop was planted, selects the active entry of `profiles`
+    #[serde(
+        default,
+        serialize_with = "crate::helper::serde_time::serialize_optional_date",
+        deserialize_with = "crate::helper::serde_time::deserialize_optional_date"
+    )]
+    #[schemars(with = "Option<crate::helper::serde_time::schema::Date>")]
+    crop_start_date: Option<time::Date>,
+    /// growth-stage profiles, replace `windows` and `brightness` once a profile is
+    /// reached
+    #[serde(default)]
+    profiles: Vec<Profile>,
+    /// supplements daylight instead of following `windows` or profile windows
+    #[serde(default)]
+    astronomical: Option<Astronomical>,
+    /// extends the lit period, and dims with a `dimmer`, towards a daily light integral
+    /// goal
+    #[serde(default)]
+    dli: Option<DliConfig>,
+}
+impl Config {
+    fn default_brightness() -> f32 {
+        100.0
+    }
+
+    /// Version 1 moved the single window of config files written before on-windows were
+    /// introduced into `windows`.
+    fn migrate_legacy_window(config: &mut serde_json::Value) -> Result<(), AtomicFixedString> {
+        let config = config
+            .as_object_mut()
+            .ok_or("growlight config is not an object")?;
+
+        if !(config.contains_key("start_time") && config.contains_key("on_duration")) {
+            return Ok(());
+        }
+
+        let window = serde_json::json!({
+            "start_time": config.remove("start_time"),
+            "on_duration": config.remove("on_duration"),
+        });
+
+        config
+            .entry("windows")
+            .or_insert_with(|| serde_json::Value::Array(Vec::new()))
+            .as_array_mut()
+            .ok_or("windows is not an array")?
+            .push(window);
+
+        Ok(())
+    }
 }
 impl Default for Config {
     fn default() -> Self {
         Self {
-            start_time: time::macros::time!(7:00 am),
-            on_duration: Duration::from_secs(12 * 60 * 60),
+            windows: vec![Window {
+                start_time: time::macros::time!(7:00 am),
+                on_duration: Duration::from_secs(12 * 60 * 60),
+            }],
+            brightness: Self::default_brightness(),
+            sunrise_duration: Duration::ZERO,
+            sunset_duration: Duration::ZERO,
+            dimmer: None,
+            channels: Vec::new(),
+            crop_start_date: None,
+            profiles: Vec::new(),
+            astronomical: None,
+            dli: None,
+        }
+    }
+}
+impl Validate for Config {
+    fn validate(&self, errors: &mut Errors) {
+        fn percent(errors: &mut Errors, path: impl std::fmt::Display, value: f32) {
+            errors.check(
+                (0.0..=100.0).contains(&value),
+                path,
+                "must be between 0 and 100",
+            );
+        }
+
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+        // on-periods repeat daily, longer windows would overlap themselves
+        let check_windows = |errors: &mut Errors, path: &str, windows: &[Window]| {
+            for (i, window) in windows.iter().enumerate() {
+                errors.check_duration(
+                    format_args!("{path}windows[{i}].on_duration"),
+                    window.on_duration,
+                    DAY,
+                );
+            }
+        };
+
+        percent(errors, "brightness", self.brightness);
+        check_windows(errors, "", &self.windows);
+        errors.check_duration("sunrise_duration", self.sunrise_duration, DAY / 2);
+        errors.check_duration("sunset_duration", self.sunset_duration, DAY / 2);
+
+        for (i, channel) in self.channels.iter().enumerate() {
+            errors.check(
+                !self.channels[..i].iter().any(|c| c.name == channel.name),
+                format_args!("channels[{i}].name"),
+                format_args!("duplicate channel '{}'", channel.name),
+            );
+            percent(
+                errors,
+                format_args!("channels[{i}].intensity"),
+                channel.intensity,
+            );
+            for (j, point) in channel.curve.iter().enumerate() {
+                errors.check(
+                    (0.0..=1.0).contains(&point.progress),
+                    format_args!("channels[{i}].curve[{j}].progress"),
+                    "must be between 0 and 1",
+                );
+                percent(
+                    errors,
+                    format_args!("channels[{i}].curve[{j}].intensity"),
+                    point.intensity,
+                );
+            }
+        }
+
+        for (i, profile) in self.profiles.iter().enumerate() {
+            errors.check(
+                !self.profiles[..i].iter().any(|p| p.name == profile.name),
+                format_args!("profiles[{i}].name"),
+                format_args!("duplicate profile '{}'", profile.name),
+            );
+            check_windows(errors, &format!("profiles[{i}]."), &profile.windows);
+            percent(
+                errors,
+                format_args!("profiles[{i}].brightness"),
+                profile.brightness,
+            );
+        }
+
+        if let Some(astronomical) = self.astronomical.as_ref() {
+            errors.check(
+                (-90.0..=90.0).contains(&astronomical.latitude),
+                "astronomical.latitude",
+                "must be between -90 and 90",
+            );
+            errors.check(
+                (-180.0..=180.0).contains(&astronomical.longitude),
+                "astronomical.longitude",
+                "must be between -180 and 180",
+            );
+            errors.check_duration("astronomical.photoperiod", astronomical.photoperiod, DAY);
+            errors.check(
+                (0.0..=1.0).contains(&astronomical.morning_share),
+                "astronomical.morning_share",
+                "must be between 0 and 1",
+            );
+        }
+
+        if let Some(dli) = self.dli.as_ref() {
+            errors.check(dli.target > 0.0, "dli.target", "must be positive");
+            errors.check(dli.lamp_ppfd > 0.0, "dli.lamp_ppfd", "must be positive");
+            errors.check_duration("dli.max_extension", dli.max_extension, DAY);
+            percent(errors, "dli.min_brightness", dli.min_brightness);
         }
     }
 }
 impl mqtt::add_on::action_message::MessageImpl for Config {
-    const PREFIX: &'static str = constants::mqtt_prefix::CONFIG;
-    const PROJECT: &'static str = constants::project::NAME;
+    const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Config;
     const GROUP: &'static str = action::GROUP;
-    const DEVICE: &'static str = constants::project::DEVICE;
     const QOS: mqtt::Qos = action::QOS;
 }
 
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, schemars::JsonSchema)]
+pub struct Window {
+    #[serde(
+        serialize_with = "crate::helper::serde_time::serialize_time",
+        deserialize_with = "crate::helper::serde_time::deserialize_time"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Time")]
+    start_time: time::Time,
+    #[serde(
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    on_duration: Duration,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, schemars::JsonSchema)]
+pub struct ChannelConfig {
+    name: AtomicFixedString,
+    dimmer: manager::dimmer::Config,
+    /// channel intensity in percent of the growlight brightness
+    #[serde(default = "Config::default_brightness")]
+    intensity: f32,
+    /// intensity over the progress of a scheduled on-period, constant 100 % if empty
+    #[serde(default)]
+    curve: Vec<CurvePoint>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, schemars::JsonSchema)]
+pub struct Profile {
+    name: AtomicFixedString,
+    /// days since `crop_start_date` at which the stage begins
+    start_day: u32,
+    windows: Vec<Window>,
+    #[serde(default = "Config::default_brightness")]
+    brightness: f32,
+    /// days over which windows and brightness blend in from the previous profile,
+    /// windows only blend if both profiles have the same number of windows
+    #[serde(default)]
+    transition_days: u32,
+}
+
+/// Lights up before sunrise and after sunset until the day reaches `photoperiod`.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, schemars::JsonSchema)]
+pub struct Astronomical {
+    /// degrees, north positive
+    latitude: f64,
+    /// degrees, east positive
+    longitude: f64,
+    /// target day length including daylight
+    #[serde(
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    photoperiod: Duration,
+    /// share of the missing light added before sunrise, the rest follows sunset
+    #[serde(default = "Astronomical::default_morning_share")]
+    morning_share: f32,
+}
+impl Astronomical {
+    fn default_morning_share() -> f32 {
+        0.5
+    }
+
+    /// Supplemental on-windows for a day with the sun times `sun`.
+    fn windows(&self, sun: &manager::solar::SunTimes) -> Vec<Window> {
+        let window = |start: time::OffsetDateTime, on_duration: time::Duration| Window {
+            start_time: start.time(),
+            on_duration: on_duration.unsigned_abs(),
+        };
+
+        let (Some(sunrise), Some(sunset)) = (sun.sunrise, sun.sunset) else {
+            if sun.polar_day {
+                return Vec::new();
+            }
+
+            // polar night, the whole photoperiod centered on solar noon
+            let photoperiod = time::Duration::try_from(self.photoperiod).unwrap();
+            return vec![window(sun.noon - photoperiod / 2, photoperiod)];
+        };
+
+        let missing = time::Duration::try_from(self.photoperiod).unwrap() - (sunset - sunrise);
+        if !missing.is_positive() {
+            return Vec::new();
+        }
+
+        let morning = missing * self.morning_share.clamp(0.0, 1.0);
+        let evening = missing - morning;
+
+        [(sunrise - morning, morning), (sunset, evening)]
+            .into_iter()
+            .filter(|(_, duration)| duration.is_positive())
+            .map(|(start, duration)| window(start, duration))
+            .collect()
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, schemars::JsonSchema)]
+pub struct DliConfig {
+    /// daily light integral goal in mol/m²/day
+    target: f32,
+    sensor: manager::light_sensor::Config,
+    /// PPFD (µmol/m²/s) the lamp delivers at 100 % brightness
+    lamp_ppfd: f32,
+    /// longest extension past the end of a scheduled on-period
+    #[serde(
+        default = "DliConfig::default_max_extension",
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    max_extension: Duration,
+    /// lowest brightness (percent) when dimming towards the goal
+    #[serde(default = "DliConfig::default_min_brightness")]
+    min_brightness: f32,
+}
+impl DliConfig {
+    fn default_max_extension() -> Duration {
+        Duration::from_secs(4 * 60 * 60)
+    }
+
+    fn default_min_brightness() -> f32 {
+        30.0
+    }
+}
+
+/// `intensity` (percent) at `progress` (0.0 at the start to 1.0 at the end of the
+/// on-period), linearly interpolated between points.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, schemars::JsonSchema)]
+pub struct CurvePoint {
+    progress: f32,
+    intensity: f32,
+}
+
+impl Window {
+    /// Interpolates from `self` (`t` = 0.0) to `other` (`t` = 1.0), start times take
+    /// the shorter way around midnight.
+    fn blend(&self, other: &Window, t: f32) -> Window {
+        const HALF_DAY: time::Duration = time::Duration::hours(12);
+
+        let mut shift = other.start_time - self.start_time;
+        if shift > HALF_DAY {
+            shift -= time::Duration::DAY;
+        } else if shift < -HALF_DAY {
+            shift += time::Duration::DAY;
+        }
+
+        let duration = self.on_duration.as_secs_f32()
+            + (other.on_duration.as_secs_f32() - self.on_duration.as_secs_f32()) * t;
+
+        Window {
+            start_time: self.start_time + shift * t,
+            on_duration: Duration::from_secs_f32(duration),
+        }
+    }
+}
+
 pub struct Plugin {
     pub config: Config,
 }
@@ -53,34 +391,41 @@ impl bevy_app::Plugin for Plugin {
         use mqtt::add_on::action_message::{RequestMessage, StatusMessage};
 
         app.init_resource::<manager::RelayManager>()
-            .init_resource::<Manager>()
-            .insert_resource(StartTime(local::datetime_today(&self.config.start_time)))
-            .insert_resource(EndTime(local::datetime_today(
-                &(self.config.start_time + self.config.on_duration),
-            )))
+            .insert_resource(Manager::new(&self.config))
+            .insert_resource(Schedule::new(self.config.windows.clone()))
+            .insert_resource(Dimming::new(&self.config))
+            .insert_resource(Growth::new(&self.config))
+            .insert_resource(Sun::new(&self.config))
+            .insert_resource(Dli::new(&self.config))
             .add_plugins((
                 RequestMessage::<Manager>::new(),
                 ConfigMessage::<Manager, Config>::new(),
+                state_file::StateFile::<Manager>::new(),
                 StatusMessage::<Manager, action::StatusMqtt>::publish_condition(
                     on_timer(std::time::Duration::from_secs(1)), //
                 ),
             ))
-            .add_systems(Startup, Manager::setup)
+            .add_systems(
+                Startup,
+                (
+                    Manager::setup,
+                    Manager::setup_dimmer,
+                    Manager::setup_light_sensor,
+                ),
+            )
             .add_systems(
                 Update,
                 (
+                    Manager::follow_profile,
+                    Manager::follow_sun,
+                    Manager::resume.run_if(run_once()),
+                    Manager::follow_schedule,
+                    Manager::follow_dli,
+                    Manager::dim,
                     Manager::update,
-                    Manager::trigger_on,
-                    Manager::trigger_off,
-                    Manager::update_datetime.run_if(
-                        |start_time: Res<StartTime>| -> bool {
-                            time::OffsetDateTime::now_utc()
-                                .to_offset(*crate::timezone_offset())
-                                .date()
-                                > start_time.0.date()
-                        }, // new day
-                    ),
-                ),
+                    Manager::drive_dimmer,
+                )
+                    .chain(),
             );
     }
 }
@@ -88,14 +433,64 @@ impl bevy_app::Plugin for Plugin {
 #[derive(Debug, Default, Resource, serde::Serialize, serde::Deserialize)]
 pub struct Manager {
     pub state: bool,
+    pub mode: action::Mode,
+    /// unix timestamp ending an override, `None` holds it until the next schedule boundary
+    pub override_until: Option<i64>,
+    /// unix timestamp of the last mode change
+    pub mode_since: Option<i64>,
+    /// unix timestamp of the last on/off switch
+    pub switched_at: Option<i64>,
+    /// re-applies the scheduled state after returning to auto
+    #[serde(skip)]
+    resync: bool,
+    /// brightness setpoint in percent
+    pub brightness: f32,
+    /// current brightness in percent, follows the setpoint through the ramps
+    pub output: f32,
+    pub channels: Vec<Channel>,
 }
 impl Manager {
+    fn new(config: &Config) -> Self {
+        Self {
+            state: false,
+            mode: action::Mode::Auto,
+            override_until: None,
+            mode_since: None,
+            switched_at: None,
+            resync: false,
+            brightness: config.brightness.clamp(0.0, 100.0),
+            output: 0.0,
+            channels: config
+                .channels
+                .iter()
+                .map(|channel| Channel {
+                    name: channel.name.clone(),
+                    intensity: channel.intensity.clamp(0.0, 100.0),
+                    output: 0.0,
+                })
+                .collect(),
+        }
+    }
+
     pub fn turn_on(&mut self) {
-        self.state = true;
+        self.switch(true);
     }
 
     pub fn turn_off(&mut self) {
-        self.state = false;
+        self.switch(false);
+    }
+
+    fn switch(&mut self, state: bool) {
+        if self.state != state {
+            self.state = state;
+            self.switched_at = Some(time::OffsetDateTime::now_utc().unix_timestamp());
+        }
+    }
+
+    fn set_mode(&mut self, mode: action::Mode, until: Option<i64>) {
+        self.mode = mode;
+        self.override_until = until;
+        self.mode_since = Some(time::OffsetDateTime::now_utc().unix_timestamp());
     }
 
     fn setup(mut cmd: Commands) {
@@ -103,21 +498,21 @@ impl Manager {
         struct Config {
             name: &'static str,
             icon: &'static str,
-            state_topic: &'static str,
+            state_topic: AtomicFixedString,
             value_template: &'static str,
-            device: mqtt::add_on::home_assistant::Device,
+            device: home_assistant::Device,
         }
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/on_time/growlight/config".into(),
+            topic: home_assistant::topic("sensor", "growlight", "on_time"),
             payload: {
                 serde_json::to_value(Config {
                     name: "On Time",
                     icon: "mdi:clock",
-                    state_topic: "status/triponics/growlight/0",
+                    state_topic: action::StatusMqtt::topic(),
                     value_template:
-                        "{{ (as_datetime(value_json.start_time) | as_local | string)[:19] }}",
-                    device: mqtt::add_on::home_assistant::Device {
+                        "{{ (as_datetime(value_json.start_time) | as_local | string)[:19] if value_json.start_time else None }}",
+                    device: home_assistant::Device {
                         identifiers: &["growlight"],
                         name: "Growlight",
                     },
@@ -130,15 +525,179 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/off_time/growlight/config".into(),
+            topic: home_assistant::topic("sensor", "growlight", "off_time"),
             payload: {
                 serde_json::to_value(Config {
                     name: "Off Time",
                     icon: "mdi:clock-outline",
-                    state_topic: "status/triponics/growlight/0",
+                    state_topic: action::StatusMqtt::topic(),
+                    value_template:
+                        "{{ (as_datetime(value_json.stop_time) | as_local | string)[:19] if value_json.stop_time else None }}",
+                    device: home_assistant::Device {
+                        identifiers: &["growlight"],
+                        name: "Growlight",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        #[derive(serde::Serialize)]
+        struct Light {
+            name: &'static str,
+            schema: &'static str,
+            command_topic: AtomicFixedString,
+            command_on_template: &'static str,
+            command_off_template: &'static str,
+            state_topic: AtomicFixedString,
+            state_template: &'static str,
+            brightness_template: &'static str,
+            device: home_assistant::Device,
+        }
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("light", "growlight", "light"),
+            payload: {
+                serde_json::to_value(Light {
+                    name: "Growlight",
+                    schema: "template",
+                    command_topic: action::Update::topic(),
+                    command_on_template: "{ \"state\" : true{% if brightness is defined %}, \"brightness\" : {{ (brightness / 2.55) | round(1) }}{% endif %} }",
+                    command_off_template: "{ \"state\" : false }",
+                    state_topic: action::StatusMqtt::topic(),
+                    state_template: "{{ \"on\" if value_json.state else \"off\" }}",
+                    brightness_template: "{{ (value_json.brightness_setpoint * 2.55) | round(0) | int }}",
+                    device: home_assistant::Device {
+                        identifiers: &["growlight"],
+                        name: "Growlight",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "growlight", "brightness"),
+            payload: {
+                serde_json::to_value(Config {
+                    name: "Brightness",
+                    icon: "mdi:brightness-6",
+                    state_topic: action::StatusMqtt::topic(),
+                    value_template: "{{ value_json.brightness }}",
+                    device: home_assistant::Device {
+                        identifiers: &["growlight"],
+                        name: "Growlight",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "growlight", "stage"),
+            payload: {
+                serde_json::to_value(Config {
+                    name: "Growth Stage",
+                    icon: "mdi:sprout",
+                    state_topic: action::StatusMqtt::topic(),
+                    value_template: "{{ value_json.stage }}",
+                    device: home_assistant::Device {
+                        identifiers: &["growlight"],
+                        name: "Growlight",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "growlight", "crop_day"),
+            payload: {
+                serde_json::to_value(Config {
+                    name: "Crop Day",
+                    icon: "mdi:calendar-today",
+                    state_topic: action::StatusMqtt::topic(),
+                    value_template: "{{ value_json.crop_day }}",
+                    device: home_assistant::Device {
+                        identifiers: &["growlight"],
+                        name: "Growlight",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        #[derive(serde::Serialize)]
+        struct Select {
+            name: &'static str,
+            icon: &'static str,
+            command_topic: AtomicFixedString,
+            command_template: &'static str,
+            state_topic: AtomicFixedString,
+            value_template: &'static str,
+            options: &'static [&'static str],
+            device: home_assistant::Device,
+        }
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("select", "growlight", "mode"),
+            payload: {
+                serde_json::to_value(Select {
+                    name: "Growlight Mode",
+                    icon: "mdi:lightbulb-auto",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"mode\" : \"{{ value }}\" }",
+                    state_topic: action::StatusMqtt::topic(),
+                    value_template: "{{ value_json.mode }}",
+                    options: &["auto", "manual_on", "manual_off", "override"],
+                    device: home_assistant::Device {
+                        identifiers: &["growlight"],
+                        name: "Growlight",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        #[derive(serde::Serialize)]
+        struct Measurement {
+            name: &'static str,
+            icon: &'static str,
+            state_topic: AtomicFixedString,
+            value_template: &'static str,
+            unit_of_measurement: &'static str,
+            device: home_assistant::Device,
+        }
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "growlight", "dli"),
+            payload: {
+                serde_json::to_value(Measurement {
+                    name: "Daily Light Integral",
+                    icon: "mdi:white-balance-sunny",
+                    state_topic: action::StatusMqtt::topic(),
                     value_template:
-                        "{{ (as_datetime(value_json.stop_time) | as_local | string)[:19] }}",
-                    device: mqtt::add_on::home_assistant::Device {
+                        "{{ value_json.dli.accumulated | round(2) if value_json.dli else None }}",
+                    unit_of_measurement: "mol/m²/d",
+                    device: home_assistant::Device {
                         identifiers: &["growlight"],
                         name: "Growlight",
                     },
@@ -151,14 +710,36 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/auto_state/growlight/config".into(),
+            topic: home_assistant::topic("sensor", "growlight", "dli_projection"),
+            payload: {
+                serde_json::to_value(Measurement {
+                    name: "Projected Daily Light Integral",
+                    icon: "mdi:chart-timeline-variant",
+                    state_topic: action::StatusMqtt::topic(),
+                    value_template:
+                        "{{ value_json.dli.projection | round(2) if value_json.dli else None }}",
+                    unit_of_measurement: "mol/m²/d",
+                    device: home_assistant::Device {
+                        identifiers: &["growlight"],
+                        name: "Growlight",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "growlight", "auto_state"),
             payload: {
                 serde_json::to_value(Config {
                     name: "Growlight Controller Command",
                     icon: "mdi:car-cruise-control",
-                    state_topic: "status/triponics/growlight/0",
+                    state_topic: action::StatusMqtt::topic(),
                     value_template: "{{ \"ON\" if value_json.state else \"OFF\"}}",
-                    device: mqtt::add_on::home_assistant::Device {
+                    device: home_assistant::Device {
                         identifiers: &["growlight"],
                         name: "Growlight",
                     },
@@ -184,142 +765,1116 @@ impl Manager {
         }
     }
 
-    fn trigger_on(
-        mut this: ResMut<Manager>,
-        start_time: Res<StartTime>,
-        end_time: Res<EndTime>,
-        mut pending_update: Local<bool>,
-    ) {
-        if start_time.is_changed() {
-            *pending_update = true;
-        }
+    fn setup_dimmer(rt: Res<TokioTasksRuntime>, mut dimming: ResMut<Dimming>) {
+        dimming.connect(&rt);
+    }
 
-        if time::OffsetDateTime::now_utc().to_offset(*crate::timezone_offset()) >= start_time.0
-            && time::OffsetDateTime::now_utc().to_offset(*crate::timezone_offset()) < end_time.0
-            && *pending_update
-        {
-            this.turn_on();
-            *pending_update = false;
+    fn setup_light_sensor(rt: Res<TokioTasksRuntime>, mut dli: ResMut<Dli>) {
+        let Some(config) = dli.config.as_ref() else {
+            return;
+        };
+
+        match manager::light_sensor::Sensor::new(&config.sensor, &rt) {
+            Ok(sensor) => dli.sensor = Some(sensor),
+            Err(e) => log::error!("[growlight] light sensor disabled, reason: {e}"),
         }
     }
 
-    fn trigger_off(
+    /// Integrates the measured light of the day, switches off once the goal is reached
+    /// and extends on-periods that ended short of it.
+    fn follow_dli(
         mut this: ResMut<Manager>,
-        end_time: Res<EndTime>,
-        mut pending_update: Local<bool>,
+        mut dli: ResMut<Dli>,
+        schedule: Res<Schedule>,
+        dimming: Res<Dimming>,
+        time: Res<Time>,
     ) {
-        if end_time.is_changed() {
-            *pending_update = true;
+        let Some(config) = dli.config.clone() else {
+            return;
+        };
+
+        let now = local::now();
+        if dli.date != Some(now.date()) {
+            dli.date = Some(now.date());
+            dli.accumulated = 0.0;
+            dli.reached = false;
         }
 
-        if time::OffsetDateTime::now_utc().to_offset(*crate::timezone_offset()) >= end_time.0
-            && *pending_update
-        {
-            this.turn_off();
-            *pending_update = false;
+        let lamp_ppfd = config.lamp_ppfd * this.output / 100.0;
+        dli.ppfd = dli.sensor.as_ref().and_then(|s| s.ppfd(lamp_ppfd));
+        if let Some(ppfd) = dli.ppfd {
+            dli.accumulated += ppfd * time.delta_seconds() / 1e6;
         }
-    }
 
-    fn update_datetime(mut start_time: ResMut<StartTime>, mut end_time: ResMut<EndTime>) {
-        *start_time = StartTime(local::datetime_today(&start_time.0.time()));
-        *end_time = EndTime(local::datetime_today(&end_time.0.time()));
-    }
-}
-impl mqtt::add_on::action_message::RequestHandler for Manager {
-    type Request = action::Update;
-    type Response = action::MqttResponse;
+        // scheduled light left until midnight
+        let midnight = now.replace_time(time::Time::MIDNIGHT) + time::Duration::DAY;
+        let remaining = schedule.period.map_or(0.0, |p| {
+            (p.end.min(midnight) - p.start.max(now))
+                .as_seconds_f32()
+                .max(0.0)
+        });
 
-    fn update_state(request: Self::Request, this: &mut Self) -> Option<Self::Response> {
-        log::info!("[growlight] <USER> set -> {}", request);
+        let full_ppfd = config.lamp_ppfd * this.brightness / 100.0;
+        dli.projection = dli.accumulated + full_ppfd * remaining / 1e6;
 
-        match request.state {
-            true => {
-                this.turn_on();
-                Some(Ok("growlight turned on").into())
-            }
-            false => {
+        dli.factor = if dimming.output.is_some() && remaining > 0.0 && full_ppfd > 0.0 {
+            let required = (config.target - dli.accumulated).max(0.0) * 1e6 / remaining;
+            let min = (config.min_brightness / this.brightness).min(1.0);
+            (required / full_ppfd).clamp(min, 1.0)
+        } else {
+            1.0
+        };
+
+        // manual modes and overrides keep the light as the user set it
+        let auto = this.mode == action::Mode::Auto;
+
+        if !dli.reached && dli.accumulated >= config.target {
+            dli.reached = true;
+            dli.extending_until = None;
+            log::info!(
+                "[growlight] <APP> DLI goal of {:.2} mol/m²/d reached -> OFF",
+                config.target
+            );
+            if auto {
                 this.turn_off();
-                Some(Ok("growlight turned off").into())
             }
         }
-    }
-}
-impl mqtt::add_on::action_message::PublishStatus<action::StatusMqtt> for Manager {
-    fn query_state() -> impl bevy_internal::prelude::System<In = (), Out = action::StatusMqtt> {
-        fn func(
-            this: Res<Manager>,
-            start_time: Res<StartTime>,
-            end_time: Res<EndTime>,
-        ) -> action::StatusMqtt {
-            action::StatusMqtt {
-                state: this.state,
-                start_time: start_time.0.unix_timestamp(),
-                stop_time: end_time.0.unix_timestamp(),
+
+        if dli.scheduled != schedule.state {
+            match (dli.scheduled, schedule.state) {
+                _ if !auto => {}
+                (Some(true), Some(false)) if !dli.reached => {
+                    dli.extending_until = Some(now + config.max_extension);
+                    log::info!(
+                        "[growlight] <APP> DLI {:.2}/{:.2} mol/m²/d, extending -> ON",
+                        dli.accumulated,
+                        config.target
+                    );
+                    this.turn_on();
+                }
+                (_, Some(true)) if dli.reached => {
+                    log::info!("[growlight] <APP> DLI goal already reached, skipping -> OFF");
+                    this.turn_off();
+                }
+                _ => {}
             }
+            dli.scheduled = schedule.state;
         }
 
-        IntoSystem::into_system(func)
-    }
+        if dli.extending_until.is_some_and(|until| now >= until) {
+            dli.extending_until = None;
+            if auto {
+                log::info!("[growlight] <APP> DLI extension limit reached -> OFF");
+                this.turn_off();
+            }
+        }
+    }
+
+    /// Moves the output towards the setpoint, scaled down during the sunrise and sunset
+    /// of a scheduled on-period and limited to the ramp rates.
+    fn dim(
+        mut this: ResMut<Manager>,
+        mut dimming: ResMut<Dimming>,
+        schedule: Res<Schedule>,
+        dli: Res<Dli>,
+        time: Res<Time>,
+    ) {
+        let now = local::now();
+        let scheduled_period = schedule.period.filter(|_| schedule.state == Some(true));
+
+        let target = dli.factor
+            * match scheduled_period {
+                _ if !this.state => 0.0,
+                Some(Period { start, end }) => {
+                    this.brightness * dimming.ramp_factor(now, start, end)
+                }
+                None => this.brightness,
+            };
+
+        let max_step = |ramp: Duration| {
+            if ramp.is_zero() {
+                f32::INFINITY
+            } else {
+                100.0 * time.delta_seconds() / ramp.as_secs_f32()
+            }
+        };
+
+        dimming.level = if target > dimming.level {
+            (dimming.level + max_step(dimming.sunrise)).min(target)
+        } else {
+            (dimming.level - max_step(dimming.sunset)).max(target)
+        };
+
+        // 0.1 % steps, so the outputs are not rewritten every frame
+        let quantize = |level: f32| (level * 10.0).round() / 10.0;
+
+        // outputs bypass change detection, ramps would rewrite the state file every frame
+        let this = this.bypass_change_detection();
+
+        let output = quantize(dimming.level);
+        if this.output != output {
+            this.output = output;
+            dimming.dirty = true;
+        }
+
+        let progress = scheduled_period.map(|Period { start, end }| {
+            ((now - start).as_seconds_f32() / (end - start).as_seconds_f32()).clamp(0.0, 1.0)
+        });
+
+        let dimming = &mut *dimming;
+        for (i, channel) in dimming.channels.iter().enumerate() {
+            let curve = progress.map_or(100.0, |p| channel.intensity_at(p));
+            let output = quantize(dimming.level * this.channels[i].intensity * curve / 1e4);
+
+            if this.channels[i].output != output {
+                this.channels[i].output = output;
+                dimming.dirty = true;
+            }
+        }
+    }
+
+    fn drive_dimmer(this: Res<Manager>, mut dimming: ResMut<Dimming>) {
+        if !std::mem::take(&mut dimming.dirty) {
+            return;
+        }
+
+        if let Some(output) = dimming.output.as_mut() {
+            if let Err(e) = output.set(this.output / 100.0) {
+                log::warn!("[growlight] failed to update dimmer, reason: {e}");
+            }
+        }
+
+        for (channel, state) in dimming.channels.iter_mut().zip(this.channels.iter()) {
+            if let Some(output) = channel.output.as_mut() {
+                if let Err(e) = output.set(state.output / 100.0) {
+                    log::warn!(
+                        "[growlight] failed to update {} channel, reason: {e}",
+                        state.name
+                    );
+                }
+            }
+        }
+    }
+
+    /// Applies the growth-stage profile of the current crop day, once per day.
+    fn follow_profile(
+        mut this: ResMut<Manager>,
+        mut growth: ResMut<Growth>,
+        mut schedule: ResMut<Schedule>,
+    ) {
+        let Some(crop_start_date) = growth.crop_start_date else {
+            return;
+        };
+
+        let day = (local::now().date() - crop_start_date).whole_days();
+        if growth.day == Some(day) {
+            return;
+        }
+        growth.day = Some(day);
+
+        let Some((stage, windows, brightness)) = growth.plan(day) else {
+            return;
+        };
+
+        if growth.stage.as_ref() != Some(&stage) {
+            log::info!("[growlight] <APP> stage -> {stage} (day {day})");
+        }
+        log::debug!("[growlight] day {day} -> windows: {windows:?}, brightness: {brightness:.1}");
+
+        growth.stage = Some(stage);
+        schedule.windows = windows;
+        schedule.period = None;
+
+        // only overrides a user set brightness once the profile asks for another one
+        if growth.brightness != Some(brightness) {
+            growth.brightness = Some(brightness);
+            this.brightness = brightness;
+        }
+    }
+
+    /// Replaces the on-windows with the supplemental windows of the day in astronomical
+    /// mode, once per day.
+    fn follow_sun(mut sun: ResMut<Sun>, mut schedule: ResMut<Schedule>) {
+        let Some(astronomical) = sun.config else {
+            return;
+        };
+
+        let today = local::now().date();
+        if sun.date == Some(today) {
+            return;
+        }
+        sun.date = Some(today);
+
+        let times = manager::solar::SunTimes::on(
+            today,
+            astronomical.latitude,
+            astronomical.longitude,
+            *crate::timezone_offset(),
+        );
+        let windows = astronomical.windows(&times);
+
+        log::info!(
+            "[growlight] <APP> sun -> sunrise: {:?}, sunset: {:?}, windows: {windows:?}",
+            times.sunrise.map(|t| t.time()),
+            times.sunset.map(|t| t.time()),
+        );
+
+        sun.times = Some(times);
+        schedule.windows = windows;
+        schedule.period = None;
+    }
+
+    /// Reconstructs the light from the restored mode on the first frame, an override
+    /// that expired or whose boundary passed while offline returns to auto.
+    fn resume(mut this: ResMut<Manager>, schedule: Res<Schedule>) {
+        let now = local::now();
+        let at = |t: i64| {
+            time::OffsetDateTime::from_unix_timestamp(t)
+                .unwrap_or(now)
+                .to_offset(*crate::timezone_offset())
+        };
+        let scheduled_at = |t: time::OffsetDateTime| {
+            let period = schedule.period_at(t);
+            (
+                period.is_some_and(|p| p.start <= t && t < p.end),
+                period.map(|p| p.start),
+            )
+        };
+        let on_off = |state: bool| if state { "ON" } else { "OFF" };
+
+        match (this.mode, this.override_until) {
+            (action::Mode::ManualOn, _) => {
+                log::info!("[growlight] <APP> resumed in manual_on mode -> ON");
+                this.turn_on();
+            }
+            (action::Mode::ManualOff, _) => {
+                log::info!("[growlight] <APP> resumed in manual_off mode -> OFF");
+                this.turn_off();
+            }
+            (action::Mode::Override, Some(until)) if now.unix_timestamp() < until => {
+                log::info!(
+                    "[growlight] <APP> resumed override until {} -> {}",
+                    at(until),
+                    on_off(this.state)
+                );
+            }
+            (action::Mode::Override, Some(until)) => {
+                log::info!(
+                    "[growlight] <APP> override expired at {} while offline -> auto",
+                    at(until)
+                );
+                this.set_mode(action::Mode::Auto, None);
+            }
+            (action::Mode::Override, None) => {
+                let since = at(this.mode_since.unwrap_or(now.unix_timestamp()));
+
+                if scheduled_at(since) == scheduled_at(now) {
+                    log::info!(
+                        "[growlight] <APP> resumed override until the next schedule boundary -> {}",
+                        on_off(this.state)
+                    );
+                } else {
+                    log::info!(
+                        "[growlight] <APP> schedule boundary passed while offline, override of {since} ended -> auto"
+                    );
+                    this.set_mode(action::Mode::Auto, None);
+                }
+            }
+            (action::Mode::Auto, _) => {}
+        }
+
+        if this.mode == action::Mode::Auto {
+            match schedule.period_at(now) {
+                Some(Period { start, end }) if start <= now => log::info!(
+                    "[growlight] <APP> resumed in auto mode -> ON (within on-period {start} - {end})"
+                ),
+                Some(Period { start, .. }) => log::info!(
+                    "[growlight] <APP> resumed in auto mode -> OFF (next on {start})"
+                ),
+                None => log::info!("[growlight] <APP> resumed in auto mode -> OFF (no on-window)"),
+            }
+        }
+    }
+
+    /// Switches the light whenever the scheduled state changes in auto mode. Overrides
+    /// expire at their deadline or, without one, at the next window boundary.
+    fn follow_schedule(mut this: ResMut<Manager>, mut schedule: ResMut<Schedule>) {
+        let now = local::now();
+
+        if schedule.period.is_none_or(|p| now >= p.end) {
+            schedule.period = schedule.period_at(now);
+        }
+
+        if this.resync {
+            this.resync = false;
+            schedule.state = None;
+        }
+
+        if this.mode == action::Mode::Override
+            && this
+                .override_until
+                .is_some_and(|until| now.unix_timestamp() >= until)
+        {
+            log::info!("[growlight] <APP> override expired -> auto");
+            this.set_mode(action::Mode::Auto, None);
+            schedule.state = None;
+        }
+
+        let state = schedule.state_at(now);
+
+        if schedule.state != Some(state) {
+            let boundary = schedule.state.is_some();
+            schedule.state = Some(state);
+
+            match this.mode {
+                action::Mode::Auto => {}
+                action::Mode::Override if boundary && this.override_until.is_none() => {
+                    log::info!("[growlight] <APP> override ended at schedule boundary -> auto");
+                    this.set_mode(action::Mode::Auto, None);
+                }
+                mode => {
+                    log::debug!(
+                        "[growlight] scheduled -> {} ignored in {mode} mode",
+                        if state { "ON" } else { "OFF" }
+                    );
+                    return;
+                }
+            }
+
+            schedule.state = Some(state);
+
+            match schedule.period {
+                Some(Period { start, end }) if state => {
+                    log::info!("[growlight] <APP> scheduled -> ON ({start} - {end})");
+                }
+                Some(Period { start, .. }) => {
+                    log::info!("[growlight] <APP> scheduled -> OFF (next on {start})");
+                }
+                None => log::info!("[growlight] <APP> scheduled -> OFF (no on-window)"),
+            }
+
+            if state {
+                this.turn_on();
+            } else {
+                this.turn_off();
+            }
+        }
+    }
+}
+impl mqtt::add_on::action_message::RequestHandler for Manager {
+    type Request = action::Update;
+    type Response = action::MqttResponse;
+
+    fn update_state(request: Self::Request, this: &mut Self) -> Option<Self::Response> {
+        log::info!("[growlight] <USER> set -> {}", request);
+
+        let action::Update {
+            state,
+            brightness,
+            channels,
+            mode,
+            until,
+        } = request;
+        let mut out = Vec::new();
+
+        if let Some(until) = until {
+            if until <= local::now().unix_timestamp() {
+                return Some(action::MqttResponse(Err(format!(
+                    "override end is in the past: {until}"
+                )
+                .into())));
+            }
+
+            let overriding = match mode {
+                Some(mode) => mode == action::Mode::Override,
+                None => state.is_some() && this.mode.is_automatic(),
+            };
+            if !overriding {
+                return Some(action::MqttResponse(Err(
+                    "'until' only applies to overrides".into(),
+                )));
+            }
+        }
+
+        // everything is checked before the first setpoint changes
+        let mut intensities = Vec::new();
+        for (name, intensity) in channels.into_iter().flatten() {
+            let Some(i) = this.channels.iter().position(|c| c.name == name) else {
+                return Some(action::MqttResponse(Err(format!(
+                    "unknown channel '{name}'"
+                )
+                .into())));
+            };
+
+            if !(0.0..=100.0).contains(&intensity) {
+                return Some(action::MqttResponse(Err(format!(
+                    "invalid {name} channel intensity: {intensity}"
+                )
+                .into())));
+            }
+
+            intensities.push((i, intensity));
+        }
+
+        if let Some(brightness) = brightness {
+            if !(0.0..=100.0).contains(&brightness) {
+                return Some(action::MqttResponse(Err(format!(
+                    "invalid brightness: {brightness}"
+                )
+                .into())));
+            }
+        }
+
+        for (i, intensity) in intensities {
+            let channel = &mut this.channels[i];
+            channel.intensity = intensity;
+            out.push(format!(
+                "{} channel intensity set to {intensity:.1} %",
+                channel.name
+            ));
+        }
+
+        if let Some(brightness) = brightness {
+            this.brightness = brightness;
+            out.push(format!("brightness set to {brightness:.1} %"));
+        }
+
+        // a bare state switches manual modes and overrides the schedule in automatic ones
+        let mode = match (mode, state) {
+            (Some(mode), _) => Some(mode),
+            (None, Some(state)) if !this.mode.is_automatic() => Some(if state {
+                action::Mode::ManualOn
+            } else {
+                action::Mode::ManualOff
+            }),
+            (None, Some(_)) => Some(action::Mode::Override),
+            (None, None) => None,
+        };
+
+        let state = match mode {
+            Some(action::Mode::Auto) => {
+                this.resync = true;
+                None
+            }
+            Some(action::Mode::ManualOn) => Some(true),
+            Some(action::Mode::ManualOff) => Some(false),
+            Some(action::Mode::Override) => Some(state.unwrap_or(this.state)),
+            None => None,
+        };
+
+        if let Some(mode) = mode {
+            this.set_mode(mode, until.filter(|_| mode == action::Mode::Override));
+
+            match this.override_until {
+                Some(until) => out.push(format!("mode set to {mode} until {until}")),
+                None if mode == action::Mode::Override => out.push(format!(
+                    "mode set to {mode} until the next schedule boundary"
+                )),
+                None => out.push(format!("mode set to {mode}")),
+            }
+        }
+
+        match state {
+            Some(true) => {
+                this.turn_on();
+                out.push("growlight turned on".to_string());
+            }
+            Some(false) => {
+                this.turn_off();
+                out.push("growlight turned off".to_string());
+            }
+            None => {}
+        }
+
+        if out.is_empty() {
+            Some(Ok("nothing to do").into())
+        } else {
+            Some(action::MqttResponse(Ok(out.join("; ").into())))
+        }
+    }
+}
+impl state_file::SaveState for Manager {
+    type State<'de> = State;
+
+    const FILENAME: &str = "growlight_manager";
+
+    fn build(state: Self::State<'_>, this: Option<Self>) -> Self {
+        let mut this = this.unwrap_or_default();
+        this.state = state.state;
+        this.mode = state.mode;
+        this.override_until = state.override_until;
+        this.mode_since = state.mode_since;
+        this.switched_at = state.switched_at;
+        this
+    }
+
+    fn save<'de>(&self) -> Self::State<'de> {
+        State {
+            state: self.state,
+            mode: self.mode,
+            override_until: self.override_until,
+            mode_since: self.mode_since,
+            switched_at: self.switched_at,
+        }
+    }
+}
+impl mqtt::add_on::action_message::PublishStatus<action::StatusMqtt> for Manager {
+    fn query_state() -> impl bevy_internal::prelude::System<In = (), Out = action::StatusMqtt> {
+        fn func(
+            this: Res<Manager>,
+            schedule: Res<Schedule>,
+            growth: Res<Growth>,
+            sun: Res<Sun>,
+            dli: Res<Dli>,
+        ) -> action::StatusMqtt {
+            action::StatusMqtt {
+                state: this.state,
+                mode: this.mode,
+                override_until: this.override_until,
+                brightness: this.output,
+                brightness_setpoint: this.brightness,
+                channels: this.channels.clone(),
+                stage: growth.stage.clone(),
+                crop_day: growth.day,
+                sunrise: sun
+                    .times
+                    .and_then(|t| t.sunrise)
+                    .map(|t| t.unix_timestamp()),
+                sunset: sun.times.and_then(|t| t.sunset).map(|t| t.unix_timestamp()),
+                start_time: schedule.period.map(|p| p.start.unix_timestamp()),
+                stop_time: schedule.period.map(|p| p.end.unix_timestamp()),
+                dli: dli.config.as_ref().map(|config| action::DliStatus {
+                    target: config.target,
+                    accumulated: dli.accumulated,
+                    projection: dli.projection,
+                    ppfd: dli.ppfd,
+                    extending: dli.extending_until.is_some(),
+                }),
+            }
+        }
+
+        IntoSystem::into_system(func)
+    }
 }
 impl ConfigFile for Manager {
     const FILENAME: &'static str = "growlight";
+    const MIGRATIONS: &'static [Migration] = &[Config::migrate_legacy_window];
     type Config = Config;
 }
+impl HotReload for Manager {
+    fn apply_config(config: &Config, world: &mut World) -> Result<(), AtomicFixedString> {
+        fn unchanged<T: serde::Serialize>(a: &T, b: &T) -> bool {
+            serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+        }
+
+        world.resource_scope(|world, rt: Mut<TokioTasksRuntime>| {
+            let mut dimming = world.resource_mut::<Dimming>();
+            let mut next = Dimming::new(config);
+            next.level = dimming.level;
+            next.dirty = true;
+            if matches!((&dimming.config, &next.config), (Some(a), Some(b)) if unchanged(a, b)) {
+                next.output = dimming.output.take();
+            }
+            for channel in next.channels.iter_mut() {
+                channel.output = dimming
+                    .channels
+                    .iter_mut()
+                    .find(|c| {
+                        c.config.name == channel.config.name
+                            && unchanged(&c.config.dimmer, &channel.config.dimmer)
+                    })
+                    .and_then(|c| c.output.take());
+            }
+            // replaced outputs stop their tasks when dropped, the serial bus stays open
+            *dimming = next;
+            dimming.connect(&rt);
+
+            let mut dli = world.resource_mut::<Dli>();
+            let keep = matches!(
+                (&dli.config, &config.dli),
+                (Some(a), Some(b)) if unchanged(&a.sensor, &b.sensor)
+            );
+            let sensor = dli.sensor.take().filter(|_| keep);
+            dli.sensor = match (sensor, config.dli.as_ref()) {
+                (Some(sensor), _) => Some(sensor),
+                (None, Some(dli_config)) => {
+                    manager::light_sensor::Sensor::new(&dli_config.sensor, &rt)
+                        .map_err(|e| log::error!("[growlight] light sensor disabled, reason: {e}"))
+                        .ok()
+                }
+                (None, None) => None,
+            };
+            dli.config = config.dli.clone();
+            if dli.config.is_none() {
+                dli.factor = 1.0;
+                dli.extending_until = None;
+            }
+        });
+
+        // profiles and sun times rebuild the windows on the next update
+        world.insert_resource(Schedule::new(config.windows.clone()));
+        world.insert_resource(Growth::new(config));
+        world.insert_resource(Sun::new(config));
+
+        let mut this = world.resource_mut::<Manager>();
+        let next = Manager::new(config);
+        let channels = next
+            .channels
+            .into_iter()
+            .map(|mut channel| {
+                if let Some(old) = this.channels.iter().find(|c| c.name == channel.name) {
+                    channel.output = old.output;
+                }
+                channel
+            })
+            .collect();
+        this.brightness = next.brightness;
+        this.channels = channels;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct Channel {
+    pub name: AtomicFixedString,
+    /// intensity setpoint in percent of the growlight brightness
+    pub intensity: f32,
+    /// current output in percent
+    pub output: f32,
+}
+
+#[derive(Debug)]
+struct DimmingChannel {
+    config: ChannelConfig,
+    output: Option<manager::dimmer::Output>,
+}
+impl DimmingChannel {
+    fn intensity_at(&self, progress: f32) -> f32 {
+        let curve = &self.config.curve;
+
+        let Some(next) = curve.iter().position(|p| p.progress > progress) else {
+            return curve.last().map_or(100.0, |p| p.intensity);
+        };
+
+        if next == 0 {
+            return curve[0].intensity;
+        }
+
+        let (a, b) = (curve[next - 1], curve[next]);
+        a.intensity
+            + (b.intensity - a.intensity) * (progress - a.progress) / (b.progress - a.progress)
+    }
+}
+
+#[derive(Debug, Resource)]
+struct Dimming {
+    sunrise: Duration,
+    sunset: Duration,
+    config: Option<manager::dimmer::Config>,
+    output: Option<manager::dimmer::Output>,
+    channels: Vec<DimmingChannel>,
+    /// unrounded brightness in percent
+    level: f32,
+    /// outputs changed since they were last driven
+    dirty: bool,
+}
+impl Dimming {
+    fn new(config: &Config) -> Self {
+        Self {
+            sunrise: config.sunrise_duration,
+            sunset: config.sunset_duration,
+            config: config.dimmer.clone(),
+            output: None,
+            channels: config
+                .channels
+                .iter()
+                .map(|channel| {
+                    let mut config = channel.clone();
+                    config
+                        .curve
+                        .sort_by(|a, b| a.progress.total_cmp(&b.progress));
+
+                    DimmingChannel {
+                        config,
+                        output: None,
+                    }
+                })
+                .collect(),
+            level: 0.0,
+            dirty: false,
+        }
+    }
+
+    /// Opens every configured output that is not open yet.
+    fn connect(&mut self, rt: &TokioTasksRuntime) {
+        if let (Some(config), None) = (self.config.as_ref(), self.output.as_ref()) {
+            match manager::dimmer::Output::new(config, rt) {
+                Ok(output) => self.output = Some(output),
+                Err(e) => log::error!("[growlight] dimmer disabled, reason: {e}"),
+            }
+        }
+
+        for channel in self.channels.iter_mut().filter(|c| c.output.is_none()) {
+            match manager::dimmer::Output::new(&channel.config.dimmer, rt) {
+                Ok(output) => channel.output = Some(output),
+                Err(e) => log::error!(
+                    "[growlight] {} channel disabled, reason: {e}",
+                    channel.config.name
+                ),
+            }
+        }
+    }
+
+    /// Share of the setpoint at `now`, rising over the sunrise after `start` and falling
+    /// over the sunset before `end`.
+    fn ramp_factor(
+        &self,
+        now: time::OffsetDateTime,
+        start: time::OffsetDateTime,
+        end: time::OffsetDateTime,
+    ) -> f32 {
+        let ramp = |elapsed: time::Duration, duration: Duration| {
+            if duration.is_zero() {
+                1.0
+            } else {
+                (elapsed.as_seconds_f32() / duration.as_secs_f32()).clamp(0.0, 1.0)
+            }
+        };
+
+        ramp(now - start, self.sunrise).min(ramp(end - now, self.sunset))
+    }
+}
+
+#[derive(Debug, Resource)]
+struct Growth {
+    crop_start_date: Option<time::Date>,
+    /// sorted by `start_day`
+    profiles: Vec<Profile>,
+    day: Option<i64>,
+    stage: Option<AtomicFixedString>,
+    /// brightness last applied from a profile
+    brightness: Option<f32>,
+}
+impl Growth {
+    fn new(config: &Config) -> Self {
+        let mut profiles = config.profiles.clone();
+        profiles.sort_by_key(|p| p.start_day);
+
+        Self {
+            crop_start_date: config.crop_start_date,
+            profiles,
+            day: None,
+            stage: None,
+            brightness: None,
+        }
+    }
+
+    /// Stage name, windows and brightness of crop `day`, `None` before the first
+    /// profile starts.
+    fn plan(&self, day: i64) -> Option<(AtomicFixedString, Vec<Window>, f32)> {
+        let i = self
+            .profiles
+            .iter()
+            .rposition(|p| p.start_day as i64 <= day)?;
+        let profile = &self.profiles[i];
+
+        let into = day - profile.start_day as i64;
+        let previous = i.checked_sub(1).map(|i| &self.profiles[i]);
+
+        let Some(previous) = previous.filter(|_| into < profile.transition_days as i64) else {
+            return Some((
+                profile.name.clone(),
+                profile.windows.clone(),
+                profile.brightness,
+            ));
+        };
+
+        let t = (into + 1) as f32 / (profile.transition_days + 1) as f32;
+
+        let windows = if previous.windows.len() == profile.windows.len() {
+            previous
+                .windows
+                .iter()
+                .zip(profile.windows.iter())
+                .map(|(a, b)| a.blend(b, t))
+                .collect()
+        } else {
+            profile.windows.clone()
+        };
+
+        Some((
+            profile.name.clone(),
+            windows,
+            previous.brightness + (profile.brightness - previous.brightness) * t,
+        ))
+    }
+}
 
 #[derive(Debug, Resource)]
-struct StartTime(time::OffsetDateTime);
+struct Sun {
+    config: Option<Astronomical>,
+    date: Option<time::Date>,
+    /// sun times of `date`
+    times: Option<manager::solar::SunTimes>,
+}
+impl Sun {
+    fn new(config: &Config) -> Self {
+        Self {
+            config: config.astronomical,
+            date: None,
+            times: None,
+        }
+    }
+}
+
+/// Persisted part of the [`Manager`], enough to resume the light after a restart.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct State {
+    state: bool,
+    #[serde(default)]
+    mode: action::Mode,
+    override_until: Option<i64>,
+    mode_since: Option<i64>,
+    switched_at: Option<i64>,
+}
 
 #[derive(Debug, Resource)]
-struct EndTime(time::OffsetDateTime);
+struct Dli {
+    config: Option<DliConfig>,
+    sensor: Option<manager::light_sensor::Sensor>,
+    date: Option<time::Date>,
+    /// mol/m² since midnight
+    accumulated: f32,
+    projection: f32,
+    ppfd: Option<f32>,
+    reached: bool,
+    /// last seen scheduled state
+    scheduled: Option<bool>,
+    extending_until: Option<time::OffsetDateTime>,
+    /// brightness share needed to reach the goal by the end of the schedule
+    factor: f32,
+}
+impl Dli {
+    fn new(config: &Config) -> Self {
+        Self {
+            config: config.dli.clone(),
+            sensor: None,
+            date: None,
+            accumulated: 0.0,
+            projection: 0.0,
+            ppfd: None,
+            reached: false,
+            scheduled: None,
+            extending_until: None,
+            factor: 1.0,
+        }
+    }
+}
+
+/// Merged on-period of the schedule, either the current one or the next one.
+#[derive(Debug, Clone, Copy)]
+struct Period {
+    start: time::OffsetDateTime,
+    end: time::OffsetDateTime,
+}
+
+#[derive(Debug, Resource)]
+struct Schedule {
+    windows: Vec<Window>,
+    /// last scheduled state, `None` until the schedule was first evaluated
+    state: Option<bool>,
+    period: Option<Period>,
+}
+impl Schedule {
+    fn new(windows: Vec<Window>) -> Self {
+        Self {
+            windows,
+            state: None,
+            period: None,
+        }
+    }
+
+    fn state_at(&self, now: time::OffsetDateTime) -> bool {
+        self.period.is_some_and(|p| p.start <= now && now < p.end)
+    }
+
+    /// Finds the on-period containing `now`, or the next one, from the wall clock
+    /// alone.
+    fn period_at(&self, now: time::OffsetDateTime) -> Option<Period> {
+        const DAY: time::Duration = time::Duration::DAY;
+
+        // windows longer than a day may still be running from several days ago
+        let lookback = self
+            .windows
+            .iter()
+            .map(|w| w.on_duration.as_secs() / DAY.whole_seconds() as u64)
+            .max()?
+            + 1;
+
+        let mut occurrences = (-(lookback as i64)..=1)
+            .flat_map(|day| {
+                self.windows
+                    .iter()
+                    .filter(|w| !w.on_duration.is_zero())
+                    .map(move |w| {
+                        let start = now.replace_time(w.start_time) + DAY * day as i32;
+                        Period {
+                            start,
+                            end: start + w.on_duration,
+                        }
+                    })
+            })
+            .collect::<Vec<_>>();
+        occurrences.sort_by_key(|p| p.start);
+
+        let mut merged: Vec<Period> = Vec::new();
+        for period in occurrences {
+            match merged.last_mut() {
+                Some(last) if period.start <= last.end => last.end = last.end.max(period.end),
+                _ => merged.push(period),
+            }
+        }
+
+        merged.into_iter().find(|p| now < p.end)
+    }
+}
 
 pub mod action {
+    use std::collections::HashMap;
+
     use crate::{constants, plugins::mqtt, AtomicFixedString};
 
     pub(super) const GROUP: &str = "growlight";
     pub(super) const QOS: mqtt::Qos = mqtt::Qos::_1;
 
-    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct StatusMqtt {
         pub state: bool,
-        pub start_time: i64,
-        pub stop_time: i64,
+        pub mode: Mode,
+        /// end of the active override, `None` until the next schedule boundary
+        pub override_until: Option<i64>,
+        /// current brightness in percent
+        pub brightness: f32,
+        pub brightness_setpoint: f32,
+        pub channels: Vec<super::Channel>,
+        /// active growth-stage profile
+        pub stage: Option<AtomicFixedString>,
+        /// days since the crop start date
+        pub crop_day: Option<i64>,
+        /// computed sunrise of today in astronomical mode
+        pub sunrise: Option<i64>,
+        pub sunset: Option<i64>,
+        /// start of the current or next on-period
+        pub start_time: Option<i64>,
+        pub stop_time: Option<i64>,
+        pub dli: Option<DliStatus>,
     }
     impl mqtt::add_on::action_message::MessageImpl for StatusMqtt {
-        const PREFIX: &'static str = constants::mqtt_prefix::STATUS;
-        const PROJECT: &'static str = constants::project::NAME;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Status;
         const GROUP: &'static str = GROUP;
-        const DEVICE: &'static str = constants::project::DEVICE;
         const QOS: mqtt::Qos = QOS;
     }
 
-    #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+    #[derive(
+        Debug,
+        Default,
+        Clone,
+        Copy,
+        PartialEq,
+        Eq,
+        serde::Serialize,
+        serde::Deserialize,
+        schemars::JsonSchema,
+    )]
+    #[serde(rename_all = "snake_case")]
+    pub enum Mode {
+        /// follows the schedule
+        #[default]
+        Auto,
+        ManualOn,
+        ManualOff,
+        /// holds the state until `until` or the next schedule boundary, then returns to auto
+        Override,
+    }
+    impl Mode {
+        /// the schedule takes over again on its own
+        pub fn is_automatic(self) -> bool {
+            matches!(self, Mode::Auto | Mode::Override)
+        }
+    }
+    impl std::fmt::Display for Mode {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(match self {
+                Mode::Auto => "auto",
+                Mode::ManualOn => "manual_on",
+                Mode::ManualOff => "manual_off",
+                Mode::Override => "override",
+            })
+        }
+    }
+
+    /// Daily light integrals in mol/m²/day.
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct DliStatus {
+        pub target: f32,
+        pub accumulated: f32,
+        /// expected by midnight if the remaining schedule runs at the setpoint
+        pub projection: f32,
+        /// µmol/m²/s
+        pub ppfd: Option<f32>,
+        pub extending: bool,
+    }
+
+    #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct Update {
-        pub state: bool,
+        pub state: Option<bool>,
+        /// brightness setpoint in percent
+        pub brightness: Option<f32>,
+        /// channel intensities in percent by channel name
+        pub channels: Option<HashMap<AtomicFixedString, f32>>,
+        pub mode: Option<Mode>,
+        /// unix timestamp ending an override
+        pub until: Option<i64>,
     }
     impl std::fmt::Display for Update {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            if self.state {
-                write!(f, "ON")
-            } else {
-                write!(f, "OFF")
+            let mut disp = f.debug_map();
+
+            if let Some(state) = self.state {
+                disp.entry(&"state", &if state { "ON" } else { "OFF" });
+            }
+
+            if let Some(brightness) = self.brightness {
+                disp.entry(&"brightness", &brightness);
+            }
+
+            if let Some(channels) = &self.channels {
+                disp.entry(&"channels", channels);
             }
+
+            if let Some(mode) = self.mode {
+                disp.entry(&"mode", &mode.to_string());
+            }
+
+            if let Some(until) = self.until {
+                disp.entry(&"until", &until);
+            }
+
+            disp.finish()
         }
     }
     impl mqtt::add_on::action_message::MessageImpl for Update {
-        const PREFIX: &'static str = constants::mqtt_prefix::REQUEST;
-        const PROJECT: &'static str = constants::project::NAME;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Request;
         const GROUP: &'static str = GROUP;
-        const DEVICE: &'static str = constants::project::DEVICE;
         const QOS: mqtt::Qos = QOS;
     }
 
-    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct MqttResponse(pub Result<AtomicFixedString, AtomicFixedString>);
     impl mqtt::add_on::action_message::MessageImpl for MqttResponse {
-        const PREFIX: &'static str = constants::mqtt_prefix::RESPONSE;
-        const PROJECT: &'static str = constants::project::NAME;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Response;
         const GROUP: &'static str = GROUP;
-        const DEVICE: &'static str = constants::project::DEVICE;
         const QOS: mqtt::Qos = QOS;
     }
     impl From<Result<&'static str, &'static str>> for MqttResponse {
@@ -330,8 +1885,417 @@ pub mod action {
 }
 
 mod local {
-    pub fn datetime_today(time: &time::Time) -> time::OffsetDateTime {
-        let datetime = time::OffsetDateTime::now_utc().to_offset(*crate::timezone_offset());
-        datetime.replace_time(*time)
+    pub fn now() -> time::OffsetDateTime {
+        time::OffsetDateTime::now_utc().to_offset(*crate::timezone_offset())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::{system::RunSystemOnce, world::World};
+
+    use super::*;
+
+    fn channel(curve: &[(f32, f32)]) -> DimmingChannel {
+        DimmingChannel {
+            config: ChannelConfig {
+                name: "red".into(),
+                dimmer: manager::dimmer::Config::Simulated,
+                intensity: 100.0,
+                curve: curve
+                    .iter()
+                    .map(|&(progress, intensity)| CurvePoint {
+                        progress,
+                        intensity,
+                    })
+                    .collect(),
+            },
+            output: None,
+        }
+    }
+
+    #[test]
+    fn intensity_at_interpolates_the_curve() {
+        let channel = channel(&[(0.25, 20.0), (0.5, 100.0), (1.0, 40.0)]);
+
+        assert_eq!(channel.intensity_at(0.0), 20.0);
+        assert_eq!(channel.intensity_at(0.25), 20.0);
+        assert_eq!(channel.intensity_at(0.375), 60.0);
+        assert_eq!(channel.intensity_at(0.5), 100.0);
+        assert_eq!(channel.intensity_at(0.75), 70.0);
+        assert_eq!(channel.intensity_at(1.0), 40.0);
+    }
+
+    #[test]
+    fn intensity_at_is_full_without_a_curve() {
+        assert_eq!(channel(&[]).intensity_at(0.5), 100.0);
+    }
+
+    fn world(config: &Config) -> World {
+        let mut world = World::new();
+        world.insert_resource(Manager::new(config));
+        world.insert_resource(Schedule::new(Vec::new()));
+        world.insert_resource(Dimming::new(config));
+        world.insert_resource(Dli::new(config));
+        world.insert_resource(Time::<()>::default());
+
+        // simulated outputs need no runtime
+        let mut dimming = world.resource_mut::<Dimming>();
+        for channel in dimming.channels.iter_mut() {
+            channel.output = Some(manager::dimmer::Output::Simulated { level: 0.0 });
+        }
+
+        world
+    }
+
+    fn simulated_levels(world: &World) -> Vec<f32> {
+        world
+            .resource::<Dimming>()
+            .channels
+            .iter()
+            .map(|channel| match channel.output {
+                Some(manager::dimmer::Output::Simulated { level }) => level,
+                ref output => panic!("unexpected output {output:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn channels_follow_brightness_and_intensity() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "windows": [],
+            "brightness": 80.0,
+            "channels": [
+                { "name": "red", "dimmer": { "type": "simulated" }, "intensity": 50.0 },
+                { "name": "blue", "dimmer": { "type": "simulated" } },
+            ],
+        }))
+        .unwrap();
+        let mut world = world(&config);
+
+        world.resource_mut::<Manager>().turn_on();
+        world.run_system_once(Manager::dim);
+        world.run_system_once(Manager::drive_dimmer);
+
+        let manager = world.resource::<Manager>();
+        assert_eq!(manager.output, 80.0);
+        assert_eq!(manager.channels[0].output, 40.0);
+        assert_eq!(manager.channels[1].output, 80.0);
+        assert_eq!(simulated_levels(&world), [0.4, 0.8]);
+
+        world.resource_mut::<Manager>().turn_off();
+        world.run_system_once(Manager::dim);
+        world.run_system_once(Manager::drive_dimmer);
+
+        assert_eq!(simulated_levels(&world), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn invalid_update_changes_nothing() {
+        use mqtt::add_on::action_message::RequestHandler;
+
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "channels": [{ "name": "red", "dimmer": { "type": "simulated" }, "intensity": 50.0 }],
+        }))
+        .unwrap();
+        let mut manager = Manager::new(&config);
+
+        let response = Manager::update_state(
+            action::Update {
+                brightness: Some(150.0),
+                channels: Some([("red".into(), 20.0)].into_iter().collect()),
+                ..Default::default()
+            },
+            &mut manager,
+        )
+        .unwrap();
+
+        assert!(response.0.is_err());
+        assert_eq!(manager.brightness, 100.0);
+        assert_eq!(manager.channels[0].intensity, 50.0);
+    }
+
+    fn window(start_time: time::Time, hours: u64) -> Window {
+        Window {
+            start_time,
+            on_duration: Duration::from_secs(hours * 60 * 60),
+        }
+    }
+
+    /// Evaluates the schedule at `now` like [`Manager::follow_schedule`] does.
+    fn scheduled(schedule: &mut Schedule, now: time::OffsetDateTime) -> (bool, Option<Period>) {
+        schedule.period = schedule.period_at(now);
+        (schedule.state_at(now), schedule.period)
+    }
+
+    #[test]
+    fn window_crossing_midnight() {
+        use time::macros::{datetime, time};
+
+        // 18/6 starting at 20:00
+        let mut schedule = Schedule::new(vec![window(time!(20:00), 18)]);
+
+        let (state, period) = scheduled(&mut schedule, datetime!(2024-03-02 03:00 +0));
+        let period = period.unwrap();
+        assert!(state);
+        assert_eq!(period.start, datetime!(2024-03-01 20:00 +0));
+        assert_eq!(period.end, datetime!(2024-03-02 14:00 +0));
+
+        let (state, period) = scheduled(&mut schedule, datetime!(2024-03-02 14:00 +0));
+        assert!(!state);
+        assert_eq!(period.unwrap().start, datetime!(2024-03-02 20:00 +0));
+
+        let (state, _) = scheduled(&mut schedule, datetime!(2024-03-02 23:59 +0));
+        assert!(state);
+    }
+
+    #[test]
+    fn overlapping_windows_merge() {
+        use time::macros::{datetime, time};
+
+        let mut schedule = Schedule::new(vec![
+            window(time!(10:00), 4),
+            window(time!(06:00), 6),
+            window(time!(18:00), 2),
+        ]);
+
+        let (state, period) = scheduled(&mut schedule, datetime!(2024-03-01 11:59 +0));
+        let period = period.unwrap();
+        assert!(state);
+        assert_eq!(period.start, datetime!(2024-03-01 06:00 +0));
+        assert_eq!(period.end, datetime!(2024-03-01 14:00 +0));
+
+        let (state, period) = scheduled(&mut schedule, datetime!(2024-03-01 15:00 +0));
+        let period = period.unwrap();
+        assert!(!state);
+        assert_eq!(period.start, datetime!(2024-03-01 18:00 +0));
+        assert_eq!(period.end, datetime!(2024-03-01 20:00 +0));
+    }
+
+    #[test]
+    fn lookback_spans_days() {
+        use time::macros::{datetime, time};
+
+        // started two days ago, still running at any time of the day
+        let mut schedule = Schedule::new(vec![window(time!(08:00), 50)]);
+
+        for now in [
+            datetime!(2024-03-05 07:59 +0),
+            datetime!(2024-03-05 08:00 +0),
+            datetime!(2024-03-05 23:00 +0),
+        ] {
+            let (state, period) = scheduled(&mut schedule, now);
+            let period = period.unwrap();
+            assert!(state, "off at {now}");
+            assert!(period.start <= now - time::Duration::DAY * 2);
+            assert!(period.end > now + time::Duration::DAY);
+        }
+    }
+
+    #[test]
+    fn no_period_without_windows() {
+        let now = time::macros::datetime!(2024-03-01 12:00 +0);
+
+        assert!(!scheduled(&mut Schedule::new(Vec::new()), now).0);
+        assert!(Schedule::new(vec![window(time::macros::time!(12:00), 0)])
+            .period_at(now)
+            .is_none());
+    }
+
+    fn profile(name: &'static str, start_day: u32, hours: u64, brightness: f32) -> Profile {
+        Profile {
+            name: name.into(),
+            start_day,
+            windows: vec![window(time::macros::time!(06:00), hours)],
+            brightness,
+            transition_days: 0,
+        }
+    }
+
+    #[test]
+    fn plan_selects_the_profile_of_the_day() {
+        let config = Config {
+            crop_start_date: Some(time::macros::date!(2024 - 03 - 01)),
+            profiles: vec![
+                profile("flowering", 30, 12, 60.0),
+                profile("vegetative", 0, 18, 100.0),
+            ],
+            ..Default::default()
+        };
+        let growth = Growth::new(&config);
+
+        assert!(growth.plan(-1).is_none());
+
+        let (stage, windows, brightness) = growth.plan(29).unwrap();
+        assert_eq!(stage.as_ref(), "vegetative");
+        assert_eq!(windows[0].on_duration, Duration::from_secs(18 * 60 * 60));
+        assert_eq!(brightness, 100.0);
+
+        let (stage, windows, brightness) = growth.plan(30).unwrap();
+        assert_eq!(stage.as_ref(), "flowering");
+        assert_eq!(windows[0].on_duration, Duration::from_secs(12 * 60 * 60));
+        assert_eq!(brightness, 60.0);
+    }
+
+    #[test]
+    fn plan_blends_into_a_transitioning_profile() {
+        let config = Config {
+            profiles: vec![
+                profile("vegetative", 0, 18, 100.0),
+                Profile {
+                    transition_days: 4,
+                    ..profile("flowering", 30, 12, 60.0)
+                },
+            ],
+            ..Default::default()
+        };
+        let growth = Growth::new(&config);
+
+        // first of four transition days, a fifth of the way
+        let (stage, windows, brightness) = growth.plan(30).unwrap();
+        assert_eq!(stage.as_ref(), "flowering");
+        // 16.8 h
+        assert_eq!(windows[0].on_duration.as_secs_f32().round(), 60480.0);
+        assert!((brightness - 92.0).abs() < 1e-3);
+
+        let (_, windows, brightness) = growth.plan(34).unwrap();
+        assert_eq!(windows[0].on_duration, Duration::from_secs(12 * 60 * 60));
+        assert_eq!(brightness, 60.0);
+    }
+
+    fn dli_world(target: f32) -> World {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "windows": [],
+            "dli": { "target": target, "sensor": { "type": "simulated" }, "lamp_ppfd": 500.0 },
+        }))
+        .unwrap();
+
+        world(&config)
+    }
+
+    #[test]
+    fn dli_goal_switches_off_and_skips_the_next_period() {
+        let mut world = dli_world(0.0);
+        world.resource_mut::<Manager>().turn_on();
+
+        world.run_system_once(Manager::follow_dli);
+        assert!(world.resource::<Dli>().reached);
+        assert!(!world.resource::<Manager>().state);
+
+        world.resource_mut::<Schedule>().state = Some(true);
+        world.resource_mut::<Manager>().turn_on();
+        world.run_system_once(Manager::follow_dli);
+        assert!(!world.resource::<Manager>().state);
+    }
+
+    #[test]
+    fn dli_short_of_the_goal_extends_the_period() {
+        let mut world = dli_world(20.0);
+        world.resource_mut::<Schedule>().state = Some(true);
+        world.run_system_once(Manager::follow_dli);
+
+        world.resource_mut::<Schedule>().state = Some(false);
+        world.resource_mut::<Manager>().turn_off();
+        world.run_system_once(Manager::follow_dli);
+
+        assert!(world.resource::<Manager>().state);
+        assert!(world.resource::<Dli>().extending_until.is_some());
+    }
+
+    #[test]
+    fn dli_leaves_manual_modes_alone() {
+        let mut world = dli_world(0.0);
+        let mut manager = world.resource_mut::<Manager>();
+        manager.set_mode(action::Mode::ManualOn, None);
+        manager.turn_on();
+
+        world.run_system_once(Manager::follow_dli);
+        assert!(world.resource::<Dli>().reached);
+        assert!(world.resource::<Manager>().state);
+    }
+
+    fn resumed(state: State, windows: Vec<Window>) -> Manager {
+        use state_file::SaveState;
+
+        let mut world = World::new();
+        world.insert_resource(Manager::build(
+            state,
+            Some(Manager::new(&Config::default())),
+        ));
+        world.insert_resource(Schedule::new(windows));
+        world.run_system_once(Manager::resume);
+
+        world.remove_resource::<Manager>().unwrap()
+    }
+
+    fn saved(state: bool, mode: action::Mode, override_until: Option<i64>, since: i64) -> State {
+        State {
+            state,
+            mode,
+            override_until,
+            mode_since: Some(since),
+            switched_at: Some(since),
+        }
+    }
+
+    #[test]
+    fn resume_keeps_manual_modes() {
+        let now = local::now().unix_timestamp();
+
+        let manager = resumed(saved(false, action::Mode::ManualOn, None, now), Vec::new());
+        assert_eq!(manager.mode, action::Mode::ManualOn);
+        assert!(manager.state);
+
+        let manager = resumed(saved(true, action::Mode::ManualOff, None, now), Vec::new());
+        assert_eq!(manager.mode, action::Mode::ManualOff);
+        assert!(!manager.state);
+    }
+
+    #[test]
+    fn resume_keeps_running_overrides() {
+        let now = local::now().unix_timestamp();
+
+        let manager = resumed(
+            saved(true, action::Mode::Override, Some(now + 3600), now),
+            Vec::new(),
+        );
+        assert_eq!(manager.mode, action::Mode::Override);
+        assert_eq!(manager.override_until, Some(now + 3600));
+        assert!(manager.state);
+
+        // no schedule boundary since the override started
+        let manager = resumed(saved(true, action::Mode::Override, None, now), Vec::new());
+        assert_eq!(manager.mode, action::Mode::Override);
+        assert!(manager.state);
+    }
+
+    #[test]
+    fn resume_ends_overrides_that_passed_while_offline() {
+        let now = local::now();
+
+        let manager = resumed(
+            saved(
+                true,
+                action::Mode::Override,
+                Some(now.unix_timestamp() - 60),
+                now.unix_timestamp() - 3600,
+            ),
+            Vec::new(),
+        );
+        assert_eq!(manager.mode, action::Mode::Auto);
+        assert_eq!(manager.override_until, None);
+
+        // on-period started an hour ago, the override three hours ago
+        let window = window(now.time() - time::Duration::HOUR, 2);
+        let manager = resumed(
+            saved(
+                false,
+                action::Mode::Override,
+                None,
+                now.unix_timestamp() - 3 * 3600,
+            ),
+            vec![window],
+        );
+        assert_eq!(manager.mode, action::Mode::Auto);
     }
 }
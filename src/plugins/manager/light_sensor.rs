@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use bevy_tokio_tasks::TokioTasksRuntime;
+
+use crate::{log, plugins::manager::modbus, AtomicFixedString};
+
+/// lux per µmol/m²/s of sunlight
+const LUX_PER_PPFD: f32 = 54.0;
+
+/// PAR or lux sensor measuring the light reaching the canopy.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, schemars::JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[schemars(rename = "LightSensorConfig")]
+pub enum Config {
+    /// sensor holding its reading in a single holding register
+    Modbus {
+        path: AtomicFixedString,
+        baud_rate: u32,
+        slave: u8,
+        register: u16,
+        /// reading per register count
+        #[serde(default = "Config::default_scale")]
+        scale: f32,
+        #[serde(default)]
+        unit: Unit,
+    },
+    /// estimates the light from the lamp output, stands in for a missing sensor
+    Simulated,
+}
+impl Config {
+    fn default_scale() -> f32 {
+        1.0
+    }
+}
+
+#[derive(
+    Debug, Default, serde::Deserialize, serde::Serialize, Clone, Copy, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    /// µmol/m²/s
+    #[default]
+    Ppfd,
+    Lux,
+}
+
+#[derive(Debug)]
+pub enum Sensor {
+    Modbus {
+        rx: tokio::sync::watch::Receiver<Option<f32>>,
+        _task: modbus::Task,
+    },
+    Simulated,
+}
+impl Sensor {
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(config: &Config, rt: &TokioTasksRuntime) -> Result<Self, AtomicFixedString> {
+        let Config::Modbus {
+            path,
+            baud_rate,
+            slave,
+            register,
+            scale,
+            unit,
+        } = config
+        else {
+            return Ok(Self::Simulated);
+        };
+
+        let (tx, rx) = tokio::sync::watch::channel(None);
+
+        let bus = modbus::Bus::open(path, *baud_rate, rt)?;
+        let (slave, register, scale, unit) = (*slave, *register, *scale, *unit);
+
+        let task = rt.spawn_background_task(move |_| async move {
+            loop {
+                tokio::time::sleep(Self::POLL_INTERVAL).await;
+
+                let reading = match bus.read_holding_registers(slave, register, 1).await {
+                    Ok(data) => data[0] as f32 * scale,
+                    Err(e) => {
+                        log::warn!("[light_sensor] {e}");
+                        tx.send_replace(None);
+                        continue;
+                    }
+                };
+
+                let ppfd = match unit {
+                    Unit::Ppfd => reading,
+                    Unit::Lux => reading / LUX_PER_PPFD,
+                };
+                log::trace!("[light_sensor] ppfd -> {ppfd:.1}");
+
+                if tx.send(Some(ppfd)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self::Modbus {
+            rx,
+            _task: task.into(),
+        })
+    }
+
+    /// Latest PPFD in µmol/m²/s, the simulated sensor reports `lamp_ppfd`.
+    pub fn ppfd(&self, lamp_ppfd: f32) -> Option<f32> {
+        match self {
+            Sensor::Modbus { rx, .. } => *rx.borrow(),
+            Sensor::Simulated => Some(lamp_ppfd),
+        }
+    }
+}
@@ -5,6 +5,18 @@ pub use aeroponic_spray::Manager as AeroponicSprayManager;
 pub mod growlight;
 pub use growlight::Manager as GrowlightManager;
 
+pub mod dimmer;
+
+pub mod modbus;
+
+pub mod solar;
+
+pub mod light_sensor;
+
+pub mod climate_sensor;
+
+pub mod pressure_sensor;
+
 pub mod relay_module;
 pub use relay_module::Manager as RelayManager;
 
@@ -13,3 +25,9 @@ pub mod water_quality_sensor;
 
 pub mod ph_dosing;
 pub use ph_dosing::Manager as PhDosingManager;
+
+pub mod nutrient_dosing;
+pub use nutrient_dosing::Manager as NutrientDosingManager;
+
+pub mod dosing_lock;
+pub use dosing_lock::DosingLock;
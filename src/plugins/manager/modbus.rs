@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use bevy_tokio_tasks::TokioTasksRuntime;
+use tokio::sync::{mpsc, oneshot};
+use tokio_modbus::prelude::*;
+
+use crate::{log, AtomicFixedString};
+
+/// Buses opened so far by port path, ports stay open until the app exits so a replaced
+/// device never races the port it releases.
+static BUSES: LazyLock<Mutex<HashMap<AtomicFixedString, Bus>>> = LazyLock::new(Default::default);
+
+#[derive(Debug)]
+enum Request {
+    ReadHoldingRegisters {
+        slave: u8,
+        register: u16,
+        count: u16,
+        reply: oneshot::Sender<Result<Vec<u16>, AtomicFixedString>>,
+    },
+    WriteSingleRegister {
+        slave: u8,
+        register: u16,
+        value: u16,
+        reply: oneshot::Sender<Result<(), AtomicFixedString>>,
+    },
+}
+
+/// Modbus RTU serial port shared by every slave wired to it. A single task owns the
+/// port and runs the requests one at a time.
+#[derive(Debug, Clone)]
+pub struct Bus {
+    path: AtomicFixedString,
+    baud_rate: u32,
+    tx: mpsc::Sender<Request>,
+}
+impl Bus {
+    /// a slave not answering in time fails its request instead of stalling the bus
+    const TIMEOUT: Duration = Duration::from_secs(1);
+    const QUEUE_LEN: usize = 16;
+
+    /// Opens the port at `path`, or joins it if another device already did.
+    pub fn open(
+        path: &AtomicFixedString,
+        baud_rate: u32,
+        rt: &TokioTasksRuntime,
+    ) -> Result<Self, AtomicFixedString> {
+        let mut buses = BUSES.lock().unwrap();
+
+        if let Some(bus) = buses.get(path) {
+            if bus.baud_rate != baud_rate {
+                return Err(format!(
+                    "{path} is already open at {} baud, changing it needs a restart",
+                    bus.baud_rate
+                )
+                .into());
+            }
+            return Ok(bus.clone());
+        }
+
+        let port = {
+            // registering the port with the reactor needs the runtime context
+            let _runtime = rt.runtime().enter();
+            tokio_serial::SerialStream::open(&tokio_serial::new(path.as_ref(), baud_rate))
+                .map_err(|e| format!("failed to open modbus port {path}, reason: {e}"))?
+        };
+
+        let (tx, mut rx) = mpsc::channel(Self::QUEUE_LEN);
+
+        rt.spawn_background_task(move |_| async move {
+            let mut modbus_ctx = rtu::attach(port);
+
+            while let Some(request) = rx.recv().await {
+                match request {
+                    Request::ReadHoldingRegisters {
+                        slave,
+                        register,
+                        count,
+                        reply,
+                    } => {
+                        modbus_ctx.set_slave(Slave(slave));
+                        let result =
+                            Self::exchange(modbus_ctx.read_holding_registers(register, count))
+                                .await;
+                        let _ = reply.send(result);
+                    }
+                    Request::WriteSingleRegister {
+                        slave,
+                        register,
+                        value,
+                        reply,
+                    } => {
+                        modbus_ctx.set_slave(Slave(slave));
+                        let result =
+                            Self::exchange(modbus_ctx.write_single_register(register, value)).await;
+                        let _ = reply.send(result);
+                    }
+                }
+            }
+        });
+
+        log::debug!("[modbus] opened {path} at {baud_rate} baud");
+
+        let bus = Self {
+            path: path.clone(),
+            baud_rate,
+            tx,
+        };
+        buses.insert(path.clone(), bus.clone());
+
+        Ok(bus)
+    }
+
+    pub async fn read_holding_registers(
+        &self,
+        slave: u8,
+        register: u16,
+        count: u16,
+    ) -> Result<Vec<u16>, AtomicFixedString> {
+        let (reply, rx) = oneshot::channel();
+        self.send(Request::ReadHoldingRegisters {
+            slave,
+            register,
+            count,
+            reply,
+        })
+        .await?;
+
+        rx.await
+            .map_err(|_| format!("modbus port {} closed", self.path))?
+    }
+
+    pub async fn write_single_register(
+        &self,
+        slave: u8,
+        register: u16,
+        value: u16,
+    ) -> Result<(), AtomicFixedString> {
+        let (reply, rx) = oneshot::channel();
+        self.send(Request::WriteSingleRegister {
+            slave,
+            register,
+            value,
+            reply,
+        })
+        .await?;
+
+        rx.await
+            .map_err(|_| format!("modbus port {} closed", self.path))?
+    }
+
+    async fn send(&self, request: Request) -> Result<(), AtomicFixedString> {
+        self.tx
+            .send(request)
+            .await
+            .map_err(|_| format!("modbus port {} closed", self.path).into())
+    }
+
+    async fn exchange<T>(
+        request: impl std::future::Future<Output = tokio_modbus::Result<T>>,
+    ) -> Result<T, AtomicFixedString> {
+        match tokio::time::timeout(Self::TIMEOUT, request).await {
+            Ok(Ok(Ok(data))) => Ok(data),
+            Ok(Ok(Err(e))) => Err(format!("modbus exception: {e}").into()),
+            Ok(Err(e)) => Err(format!("modbus request failed, reason: {e}").into()),
+            Err(_) => Err("modbus request timed out".into()),
+        }
+    }
+}
+
+/// Task polling or driving a slave for a device, stopped when the device is dropped so
+/// a replaced device does not keep using the bus.
+#[derive(Debug)]
+pub struct Task(tokio::task::JoinHandle<()>);
+impl From<tokio::task::JoinHandle<()>> for Task {
+    fn from(handle: tokio::task::JoinHandle<()>) -> Self {
+        Self(handle)
+    }
+}
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
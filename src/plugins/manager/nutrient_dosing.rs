@@ -0,0 +1,1390 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy_app::{Startup, Update};
+use bevy_ecs::{
+    change_detection::Mut,
+    system::{Commands, IntoSystem, Res, ResMut, Resource},
+    world::World,
+};
+use bevy_internal::{prelude::DetectChangesMut, time::common_conditions::on_timer};
+use bevy_tokio_tasks::TokioTasksRuntime;
+
+use crate::{
+    config::{ConfigFile, Errors, HotReload, Validate},
+    constants,
+    helper::{ErrorLogFormat, ToBytes},
+    log,
+    mqtt::{self, message::MessageInfo},
+    plugins::{self, manager::relay_module, state_file},
+    AtomicFixedString,
+};
+
+pub struct Plugin {
+    pub config: Config,
+}
+impl bevy_app::Plugin for Plugin {
+    fn build(&self, app: &mut bevy_app::App) {
+        use mqtt::add_on::action_message::{ConfigMessage, RequestMessage, StatusMessage};
+
+        app.init_resource::<plugins::manager::RelayManager>()
+            .init_resource::<plugins::manager::DosingLock>()
+            .insert_resource(Manager::new(self.config.clone()))
+            .add_plugins((
+                RequestMessage::<Manager>::new(),
+                ConfigMessage::<Manager, Config>::new(),
+                StatusMessage::<Manager, action::Status>::publish_condition(
+                    on_timer(Duration::from_secs(1)), //
+                ),
+                state_file::StateFile::<Manager>::new(),
+            ))
+            .add_systems(Startup, (Manager::register_home_assistant,))
+            .add_systems(Update, (Manager::auto_dose, Manager::run_steps));
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, schemars::JsonSchema)]
+pub struct Config {
+    /// every normally open relay but `relay_1` is wired to another device by default, so
+    /// pump `B` has no relay and sets are rejected until it is wired
+    pub pumps: Vec<PumpConfig>,
+    /// wait between the pumps of a set so the concentrates do not react with each other
+    #[serde(
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    pub mixing_delay: Duration,
+    /// total volume (ml) of a set dosed per user press
+    pub unit_volume_user: f32,
+    pub auto: AutoConfig,
+    #[serde(default)]
+    pub limits: Limits,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            pumps: vec![
+                PumpConfig {
+                    name: "A".into(),
+                    relay: Some(relay_module::Channel::Relay1),
+                    ratio: 1.0,
+                    flow_rate: None,
+                },
+                PumpConfig {
+                    name: "B".into(),
+                    relay: None,
+                    ratio: 1.0,
+                    flow_rate: None,
+                },
+            ],
+            mixing_delay: Duration::from_secs(60),
+            unit_volume_user: 5.0,
+            auto: AutoConfig::default(),
+            limits: Limits::default(),
+        }
+    }
+}
+impl Validate for Config {
+    fn validate(&self, errors: &mut Errors) {
+        use relay_module::Channel;
+
+        fn positive(errors: &mut Errors, path: &str, value: Option<f32>) {
+            errors.check(
+                value.is_none_or(|v| v.is_finite() && v > 0.0),
+                path,
+                "must be positive",
+            );
+        }
+
+        // the normally closed relay would run its pump whenever it is released, the
+        // sprayer relays are in its own file and checked by `check_sprayer_relays`
+        let reserved = [
+            (Channel::Relay3, "normally closed"),
+            (Channel::Relay6, "used by the pH down pump"),
+            (Channel::Relay7, "used by the pH up pump"),
+            (Channel::Relay8, "used by the growlight"),
+        ];
+
+        for (i, pump) in self.pumps.iter().enumerate() {
+            errors.check(
+                !self.pumps[..i].iter().any(|p| p.name == pump.name),
+                format_args!("pumps[{i}].name"),
+                format_args!("duplicate pump '{}'", pump.name),
+            );
+            if let Some(relay) = pump.relay {
+                errors.check(
+                    !self.pumps[..i].iter().any(|p| p.relay == Some(relay)),
+                    format_args!("pumps[{i}].relay"),
+                    format_args!("{relay:?} is used by another pump"),
+                );
+                if let Some((_, reason)) = reserved.iter().find(|(r, _)| *r == relay) {
+                    errors.check(
+                        false,
+                        format_args!("pumps[{i}].relay"),
+                        format_args!("{relay:?} is {reason}"),
+                    );
+                }
+            }
+            errors.check(
+                pump.ratio >= 0.0,
+                format_args!("pumps[{i}].ratio"),
+                "must not be negative",
+            );
+            errors.check(
+                pump.flow_rate.is_none_or(|rate| rate > 0.0),
+                format_args!("pumps[{i}].flow_rate"),
+                "must be positive",
+            );
+        }
+
+        errors.check(
+            self.unit_volume_user > 0.0,
+            "unit_volume_user",
+            "must be positive",
+        );
+        errors.check(
+            self.auto.ec_setpoint > 0.0,
+            "auto.ec_setpoint",
+            "must be positive",
+        );
+        errors.check(
+            self.auto.ec_deadband >= 0.0 && self.auto.ec_deadband < self.auto.ec_setpoint,
+            "auto.ec_deadband",
+            "must be between 0 and auto.ec_setpoint",
+        );
+        errors.check(
+            self.auto.set_volume > 0.0,
+            "auto.set_volume",
+            "must be positive",
+        );
+        errors.check(
+            self.limits
+                .max_set_volume
+                .is_none_or(|max| self.auto.set_volume <= max),
+            "auto.set_volume",
+            "must not exceed limits.max_set_volume",
+        );
+        errors.check(
+            !self.auto.max_reading_age.is_zero(),
+            "auto.max_reading_age",
+            "must be positive",
+        );
+        errors.check_duration(
+            "mixing_delay",
+            self.mixing_delay,
+            Duration::from_secs(60 * 60),
+        );
+        errors.check_duration(
+            "auto.settle_time",
+            self.auto.settle_time,
+            Duration::from_secs(24 * 60 * 60),
+        );
+        errors.check_duration(
+            "auto.max_reading_age",
+            self.auto.max_reading_age,
+            Duration::from_secs(24 * 60 * 60),
+        );
+
+        positive(errors, "limits.max_set_volume", self.limits.max_set_volume);
+        positive(
+            errors,
+            "limits.max_hourly_volume",
+            self.limits.max_hourly_volume,
+        );
+        positive(
+            errors,
+            "limits.max_daily_volume",
+            self.limits.max_daily_volume,
+        );
+        errors.check(
+            self.limits.min_ec_change >= 0.0,
+            "limits.min_ec_change",
+            "must not be negative",
+        );
+    }
+}
+impl Config {
+    /// Checks the pumps against the relays of the sprayer. Its config is a separate file,
+    /// so this runs once both are loaded and whenever either is applied.
+    pub fn check_sprayer_relays(
+        &self,
+        sprayer: impl IntoIterator<Item = relay_module::Channel>,
+    ) -> Result<(), AtomicFixedString> {
+        let sprayer = sprayer.into_iter().collect::<Vec<_>>();
+
+        let conflicts = self
+            .pumps
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pump)| pump.relay.map(|relay| (i, relay)))
+            .filter(|(_, relay)| sprayer.contains(relay))
+            .map(|(i, relay)| format!("pumps[{i}].relay: {relay:?} is used by the sprayer"))
+            .collect::<Vec<_>>();
+
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(conflicts.join(", ").into())
+        }
+    }
+}
+impl mqtt::add_on::action_message::MessageImpl for Config {
+    const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Config;
+    const GROUP: &'static str = action::GROUP;
+    const QOS: mqtt::Qos = mqtt::Qos::_1;
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, schemars::JsonSchema)]
+pub struct PumpConfig {
+    pub name: AtomicFixedString,
+    /// `None` until the pump is wired, it can not be dosed before
+    pub relay: Option<relay_module::Channel>,
+    /// share of a set, relative to the other pumps
+    pub ratio: f32,
+    /// calibrated flow rate in ml per second
+    pub flow_rate: Option<f32>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, schemars::JsonSchema)]
+pub struct AutoConfig {
+    pub enabled: bool,
+    /// target EC in mS/cm
+    pub ec_setpoint: f32,
+    /// a set is dosed once the EC drops below `ec_setpoint - ec_deadband`
+    pub ec_deadband: f32,
+    /// total volume (ml) of an automatic set
+    pub set_volume: f32,
+    /// wait after a set before the EC is evaluated again
+    #[serde(
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    pub settle_time: Duration,
+    /// automatic dosing turns off when the EC was not sampled for this long
+    #[serde(
+        default = "AutoConfig::default_max_reading_age",
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    pub max_reading_age: Duration,
+}
+impl AutoConfig {
+    fn default_max_reading_age() -> Duration {
+        Duration::from_secs(60)
+    }
+}
+impl Default for AutoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ec_setpoint: 1.5,
+            ec_deadband: 0.1,
+            set_volume: 5.0,
+            settle_time: Duration::from_secs(15 * 60),
+            max_reading_age: Self::default_max_reading_age(),
+        }
+    }
+}
+
+/// Safety limits over the total volume of every pump, volume limits require calibrated
+/// pumps.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, schemars::JsonSchema)]
+#[serde(default)]
+pub struct Limits {
+    /// total volume (ml) queued by one request or automatic set
+    pub max_set_volume: Option<f32>,
+    /// rolling 1 hour window
+    pub max_hourly_volume: Option<f32>,
+    /// rolling 24 hour window
+    pub max_daily_volume: Option<f32>,
+    /// sets allowed before locking out when the EC does not move by `min_ec_change`
+    pub max_sets_without_change: Option<u32>,
+    pub min_ec_change: f32,
+}
+impl Limits {
+    fn has_volume_limit(&self) -> bool {
+        self.max_set_volume.is_some()
+            || self.max_hourly_volume.is_some()
+            || self.max_daily_volume.is_some()
+    }
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_set_volume: None,
+            max_hourly_volume: None,
+            max_daily_volume: None,
+            max_sets_without_change: Some(3),
+            min_ec_change: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Dose {
+        pump: usize,
+        /// relay at planning time, a reloaded config can not leave it energized
+        relay: relay_module::Channel,
+        run_time: Duration,
+        volume: Option<f32>,
+    },
+    Mix(Duration),
+}
+impl Step {
+    fn duration(&self) -> Duration {
+        match *self {
+            Step::Dose { run_time, .. } => run_time,
+            Step::Mix(delay) => delay,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ActiveStep {
+    id: u32,
+    step: Step,
+    start_time: time::OffsetDateTime,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct PumpState {
+    last_volume: Option<f32>,
+    total_volume: f32,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+struct DoseRecord {
+    timestamp: i64,
+    volume: f32,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SafetyState {
+    lockout: bool,
+    consecutive_sets: u32,
+    reference_ec: Option<f32>,
+    /// doses of the last 24 hours, over every pump
+    history: VecDeque<DoseRecord>,
+}
+impl SafetyState {
+    fn dispensed_since(&self, timestamp: i64) -> f32 {
+        self.history
+            .iter()
+            .filter(|record| record.timestamp > timestamp)
+            .map(|record| record.volume)
+            .sum()
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct Manager {
+    config: Config,
+    auto: bool,
+    pumps: Vec<PumpState>,
+    queue: VecDeque<(u32, Step)>,
+    active: Option<ActiveStep>,
+    cancelled: Option<ActiveStep>,
+    next_id: u32,
+    last_set_time: Option<time::OffsetDateTime>,
+    latest_ec: Option<f32>,
+    safety: SafetyState,
+}
+impl Manager {
+    const HOUR: i64 = 60 * 60;
+    const DAY: i64 = 24 * Self::HOUR;
+    /// longest a pump may run for a single dose, whatever the volume limits
+    pub const MAX_RUN_TIME: Duration = Duration::from_secs(10 * 60);
+
+    fn new(config: Config) -> Self {
+        if config.pumps.is_empty() {
+            log::warn!("[nutrient_dosing] no nutrient pumps configured");
+        }
+
+        Self {
+            auto: config.auto.enabled,
+            pumps: vec![PumpState::default(); config.pumps.len()],
+            queue: VecDeque::new(),
+            active: None,
+            cancelled: None,
+            next_id: 0,
+            last_set_time: None,
+            latest_ec: None,
+            safety: SafetyState::default(),
+            config,
+        }
+    }
+
+    /// see [`Config::check_sprayer_relays`]
+    pub fn check_sprayer_relays(
+        &self,
+        sprayer: impl IntoIterator<Item = relay_module::Channel>,
+    ) -> Result<(), AtomicFixedString> {
+        self.config.check_sprayer_relays(sprayer)
+    }
+
+    pub fn is_dosing(&self) -> bool {
+        self.active.is_some() || !self.queue.is_empty()
+    }
+
+    fn find_pump(&self, name: &str) -> Result<usize, AtomicFixedString> {
+        self.config
+            .pumps
+            .iter()
+            .position(|p| p.name.as_ref() == name)
+            .ok_or_else(|| format!("unknown nutrient pump '{name}'").into())
+    }
+
+    fn plan_dose(&self, pump: usize, amount: DoseAmount) -> Result<Step, AtomicFixedString> {
+        let PumpConfig {
+            name,
+            relay,
+            flow_rate,
+            ..
+        } = &self.config.pumps[pump];
+
+        let Some(relay) = *relay else {
+            return Err(format!("{name} pump has no relay configured").into());
+        };
+
+        let (run_time, volume) = match amount {
+            DoseAmount::Time(run_time) => (
+                run_time,
+                flow_rate.map(|rate| rate * run_time.as_secs_f32()),
+            ),
+            DoseAmount::Volume(volume) => {
+                if !volume.is_finite() || volume <= 0.0 {
+                    return Err(format!("invalid {name} dose volume: {volume}").into());
+                }
+
+                let Some(rate) = flow_rate.filter(|rate| *rate > 0.0) else {
+                    return Err(format!("{name} pump is not calibrated").into());
+                };
+
+                let run_time = Duration::try_from_secs_f32(volume / rate)
+                    .map_err(|_| format!("{name} dose volume out of range: {volume}"))?;
+                (run_time, Some(volume))
+            }
+        };
+
+        if run_time > Self::MAX_RUN_TIME {
+            return Err(format!(
+                "{name} dose of {:.2} s exceeds the {} s pump run limit",
+                run_time.as_secs_f32(),
+                Self::MAX_RUN_TIME.as_secs()
+            )
+            .into());
+        }
+
+        Ok(Step::Dose {
+            pump,
+            relay,
+            run_time,
+            volume,
+        })
+    }
+
+    /// Splits `volume` over every pump by their ratio, with the mixing delay between
+    /// pumps.
+    fn plan_set(&self, volume: f32) -> Result<Vec<Step>, AtomicFixedString> {
+        let total_ratio = self.config.pumps.iter().map(|p| p.ratio).sum::<f32>();
+
+        if self.config.pumps.is_empty() || total_ratio <= 0.0 {
+            return Err("no nutrient pumps with a positive ratio configured".into());
+        }
+
+        let mut steps = Vec::new();
+
+        for (pump, PumpConfig { ratio, .. }) in self.config.pumps.iter().enumerate() {
+            if *ratio <= 0.0 {
+                continue;
+            }
+
+            if !steps.is_empty() {
+                steps.push(Step::Mix(self.config.mixing_delay));
+            }
+
+            steps.push(self.plan_dose(pump, DoseAmount::Volume(volume * ratio / total_ratio))?);
+        }
+
+        Ok(steps)
+    }
+
+    fn scheduled_volume(&self) -> f32 {
+        self.active
+            .iter()
+            .map(|a| &a.step)
+            .chain(self.queue.iter().map(|(_, step)| step))
+            .filter_map(|step| match *step {
+                Step::Dose { volume, .. } => volume,
+                Step::Mix(_) => None,
+            })
+            .sum()
+    }
+
+    /// Checks the steps of one request or automatic set against the lockout and the
+    /// limits, counting them as a set without EC change only if they pass.
+    fn check_safety(&mut self, steps: &[Step]) -> Result<(), AtomicFixedString> {
+        let limits = self.config.limits;
+
+        if self.safety.lockout {
+            return Err("dosing is locked out, reset the lockout to resume".into());
+        }
+
+        if limits.has_volume_limit() {
+            let mut volume = 0.0;
+            for step in steps {
+                if let Step::Dose {
+                    pump,
+                    volume: dose_volume,
+                    ..
+                } = *step
+                {
+                    let Some(dose_volume) = dose_volume else {
+                        return Err(format!(
+                            "{} pump must be calibrated to enforce volume limits",
+                            self.config.pumps[pump].name
+                        )
+                        .into());
+                    };
+                    volume += dose_volume;
+                }
+            }
+
+            if let Some(max) = limits.max_set_volume {
+                if volume > max {
+                    return Err(
+                        format!("set of {volume:.2} ml exceeds the {max:.2} ml set limit").into(),
+                    );
+                }
+            }
+
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            let scheduled = self.scheduled_volume();
+
+            if let Some(max) = limits.max_hourly_volume {
+                let dispensed = self.safety.dispensed_since(now - Self::HOUR) + scheduled;
+                if dispensed + volume > max {
+                    return Err(format!(
+                        "set would exceed the hourly limit ({dispensed:.2}/{max:.2} ml)"
+                    )
+                    .into());
+                }
+            }
+
+            if let Some(max) = limits.max_daily_volume {
+                let dispensed = self.safety.dispensed_since(now - Self::DAY) + scheduled;
+                if dispensed + volume > max {
+                    return Err(format!(
+                        "set would exceed the daily limit ({dispensed:.2}/{max:.2} ml)"
+                    )
+                    .into());
+                }
+            }
+        }
+
+        if let Some(max) = limits.max_sets_without_change {
+            let ec_changed = match (self.safety.reference_ec, self.latest_ec) {
+                (Some(reference), Some(ec)) => (ec - reference).abs() >= limits.min_ec_change,
+                _ => true,
+            };
+
+            if ec_changed {
+                self.safety.reference_ec = self.latest_ec;
+                self.safety.consecutive_sets = 0;
+            }
+
+            if self.safety.consecutive_sets >= max {
+                self.safety.lockout = true;
+                log::warn!(
+                    "[nutrient_dosing] <APP> locked out after {max} sets without an EC change of {}",
+                    limits.min_ec_change
+                );
+                return Err(format!("locked out after {max} sets without EC change").into());
+            }
+
+            self.safety.consecutive_sets += 1;
+        }
+
+        Ok(())
+    }
+
+    fn enqueue(&mut self, steps: Vec<Step>) -> Vec<u32> {
+        steps
+            .into_iter()
+            .map(|step| {
+                self.next_id = self.next_id.wrapping_add(1);
+                self.queue.push_back((self.next_id, step));
+                self.next_id
+            })
+            .collect()
+    }
+
+    fn describe(&self, step: &Step) -> String {
+        match *step {
+            Step::Dose {
+                pump,
+                run_time,
+                volume: Some(volume),
+                ..
+            } => format!(
+                "{}: {volume:.2} ml ({:.2} s)",
+                self.config.pumps[pump].name,
+                run_time.as_secs_f32()
+            ),
+            Step::Dose {
+                pump,
+                run_time,
+                volume: None,
+                ..
+            } => format!(
+                "{}: {:.2} s",
+                self.config.pumps[pump].name,
+                run_time.as_secs_f32()
+            ),
+            Step::Mix(delay) => format!("mixing: {:.2} s", delay.as_secs_f32()),
+        }
+    }
+
+    fn cancel(&mut self) -> Result<AtomicFixedString, AtomicFixedString> {
+        let queued = self.queue.len();
+        self.queue.clear();
+
+        if let Some(active) = self.active.take() {
+            // the relay is de-energized by `handle`, `run_steps` accounts the dose
+            active.task.abort();
+            self.cancelled = Some(active);
+        } else if queued == 0 {
+            return Err("nothing to cancel".into());
+        }
+
+        Ok("cancelled nutrient dosing".into())
+    }
+
+    fn register_home_assistant(mut cmd: Commands) {
+        use mqtt::add_on::home_assistant::{self, Device};
+
+        #[derive(serde::Serialize)]
+        struct Switch {
+            name: &'static str,
+            command_topic: AtomicFixedString,
+            command_template: &'static str,
+            payload_on: bool,
+            payload_off: bool,
+            state_topic: AtomicFixedString,
+            value_template: &'static str,
+            state_on: bool,
+            state_off: bool,
+            device: Device,
+        }
+
+        #[derive(serde::Serialize)]
+        struct Button {
+            name: &'static str,
+            command_topic: AtomicFixedString,
+            command_template: &'static str,
+            payload_press: bool,
+            device: Device,
+        }
+
+        #[derive(serde::Serialize)]
+        struct State {
+            name: &'static str,
+            state_topic: AtomicFixedString,
+            value_template: &'static str,
+            icon: &'static str,
+            device: Device,
+        }
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("switch", "nutrient_dosing", "auto"),
+            payload: {
+                serde_json::to_value(Switch {
+                    name: "Automatic Nutrient Dosing",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"auto\" : {{value | lower}} }",
+                    payload_on: true,
+                    payload_off: false,
+                    state_topic: action::Status::topic(),
+                    value_template: "{{ value_json.auto }}",
+                    state_on: true,
+                    state_off: false,
+                    device: Device {
+                        identifiers: &["nutrient-dosing"],
+                        name: "Nutrient Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "nutrient_dosing", "dose"),
+            payload: {
+                serde_json::to_value(Button {
+                    name: "Dose Nutrients",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"dose_set\" : {{value | lower}} }",
+                    payload_press: true,
+                    device: Device {
+                        identifiers: &["nutrient-dosing"],
+                        name: "Nutrient Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "nutrient_dosing", "cancel"),
+            payload: {
+                serde_json::to_value(Button {
+                    name: "Cancel Nutrient Dosing",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"cancel\" : {{value | lower}} }",
+                    payload_press: true,
+                    device: Device {
+                        identifiers: &["nutrient-dosing"],
+                        name: "Nutrient Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "nutrient_dosing", "reset_lockout"),
+            payload: {
+                serde_json::to_value(Button {
+                    name: "Reset Nutrient Dosing Lockout",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"reset_lockout\" : {{value | lower}} }",
+                    payload_press: true,
+                    device: Device {
+                        identifiers: &["nutrient-dosing"],
+                        name: "Nutrient Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        #[derive(serde::Serialize)]
+        struct Problem {
+            name: &'static str,
+            state_topic: AtomicFixedString,
+            value_template: &'static str,
+            device_class: &'static str,
+            device: Device,
+        }
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("binary_sensor", "nutrient_dosing", "lockout"),
+            payload: {
+                serde_json::to_value(Problem {
+                    name: "Nutrient Dosing Lockout",
+                    state_topic: action::Status::topic(),
+                    value_template: "{{ \"ON\" if value_json.lockout else \"OFF\" }}",
+                    device_class: "problem",
+                    device: Device {
+                        identifiers: &["nutrient-dosing"],
+                        name: "Nutrient Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "nutrient_dosing", "active"),
+            payload: {
+                serde_json::to_value(State {
+                    name: "Nutrient Dosing",
+                    state_topic: action::Status::topic(),
+                    value_template: "{{ value_json.active if value_json.active else \"idle\" }}",
+                    icon: "mdi:flask-outline",
+                    device: Device {
+                        identifiers: &["nutrient-dosing"],
+                        name: "Nutrient Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+    }
+
+    fn update_state(
+        &mut self,
+        request: action::Update,
+    ) -> Result<AtomicFixedString, AtomicFixedString> {
+        let action::Update {
+            dose,
+            dose_set,
+            dose_set_ml,
+            auto,
+            cancel,
+            reset_lockout,
+        } = request;
+
+        let mut out = Vec::new();
+
+        if let Some(true) = cancel {
+            out.push(self.cancel()?.to_string());
+        }
+
+        if let Some(true) = reset_lockout {
+            self.safety.lockout = false;
+            self.safety.consecutive_sets = 0;
+            self.safety.reference_ec = self.latest_ec;
+            out.push("lockout reset".to_string());
+        }
+
+        if let Some(auto) = auto {
+            self.auto = auto;
+            out.push(format!(
+                "automatic dosing {}",
+                if auto { "on" } else { "off" }
+            ));
+        }
+
+        let mut steps = Vec::new();
+
+        if let Some(action::ManualDose { pump, volume, time }) = dose {
+            let pump = self.find_pump(pump.as_ref())?;
+            let amount = match (volume, time) {
+                (Some(volume), _) => DoseAmount::Volume(volume),
+                (None, Some(time)) if time > 0.0 => DoseAmount::Time(
+                    Duration::try_from_secs_f32(time)
+                        .map_err(|_| format!("dose time out of range: {time}"))?,
+                ),
+                _ => return Err("dose requires a volume or a positive time".into()),
+            };
+
+            steps.push(self.plan_dose(pump, amount)?);
+        }
+
+        if let Some(true) = dose_set {
+            steps.extend(self.plan_set(self.config.unit_volume_user)?);
+        }
+
+        if let Some(volume) = dose_set_ml {
+            steps.extend(self.plan_set(volume)?);
+        }
+
+        if !steps.is_empty() {
+            self.check_safety(&steps)?;
+
+            let description = steps
+                .iter()
+                .map(|step| self.describe(step))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            self.enqueue(steps);
+            out.push(format!("queued {description}"));
+        }
+
+        if out.is_empty() {
+            Ok("nothing to do".into())
+        } else {
+            Ok(out.join("; ").into())
+        }
+    }
+
+    fn auto_dose(
+        mut this: ResMut<Self>,
+        sensor: Option<Res<plugins::manager::water_quality_sensor::Manager>>,
+    ) {
+        let Some(sensor) = sensor else {
+            return;
+        };
+        // no reading yet
+        let Some(sampled_at) = sensor.sampled_at() else {
+            return;
+        };
+
+        let ec = sensor.get_data().ec();
+        // the latest reading is only reported in the status
+        this.bypass_change_detection().latest_ec = Some(ec);
+
+        if !this.auto || this.is_dosing() {
+            return;
+        }
+
+        let AutoConfig {
+            ec_setpoint,
+            ec_deadband,
+            set_volume,
+            settle_time,
+            max_reading_age,
+            ..
+        } = this.config.auto;
+
+        let now = time::OffsetDateTime::now_utc();
+
+        if now - sampled_at > max_reading_age {
+            log::warn!(
+                "[nutrient_dosing] automatic dosing disabled, reason: no EC reading for {}",
+                crate::helper::serde_time::format_duration(max_reading_age)
+            );
+            this.auto = false;
+            return;
+        }
+
+        if this
+            .last_set_time
+            .is_some_and(|last| now < last + settle_time)
+        {
+            return;
+        }
+
+        // a probe out of the solution reads 0
+        if ec <= 0.0 || ec >= ec_setpoint - ec_deadband {
+            return;
+        }
+
+        let steps = this.plan_set(set_volume);
+        match steps.and_then(|steps| this.check_safety(&steps).map(|()| steps)) {
+            Ok(steps) => {
+                log::info!(
+                    "[nutrient_dosing] <APP> EC {ec:.3} below setpoint {ec_setpoint:.3}, dosing {set_volume:.2} ml"
+                );
+                this.enqueue(steps);
+            }
+            Err(e) => {
+                log::warn!("[nutrient_dosing] automatic dosing disabled, reason: {e}");
+                this.auto = false;
+            }
+        }
+
+        this.last_set_time = Some(now);
+    }
+
+    fn run_steps(
+        mut cmd: Commands,
+        rt: Res<TokioTasksRuntime>,
+        mut this: ResMut<Self>,
+        mut relay_manager: ResMut<plugins::manager::RelayManager>,
+        mut lock: ResMut<plugins::manager::DosingLock>,
+    ) {
+        if this.cancelled.is_some() {
+            let ActiveStep {
+                id,
+                step,
+                start_time,
+                ..
+            } = this.cancelled.take().unwrap();
+
+            if let Step::Dose { pump, .. } = step {
+                let elapsed = (time::OffsetDateTime::now_utc() - start_time)
+                    .clamp(time::Duration::ZERO, step.duration().try_into().unwrap());
+                let volume = this.config.pumps[pump]
+                    .flow_rate
+                    .map(|rate| rate * elapsed.as_seconds_f32());
+
+                this.finish_dose(pump, volume);
+            }
+
+            log::info!(
+                "[nutrient_dosing] <USER> cancelled -> #{id} {}",
+                this.describe(&step)
+            );
+        }
+
+        if this.active.is_some() {
+            return;
+        }
+
+        if this.queue.is_empty() {
+            lock.release(action::GROUP);
+            return;
+        }
+
+        // wait for other dosing managers to finish
+        if !lock.try_acquire(action::GROUP) {
+            return;
+        }
+
+        let (id, step) = this.queue.pop_front().unwrap();
+
+        if let Step::Dose { relay, .. } = step {
+            if !Self::set_relay(&mut relay_manager, relay, true) {
+                // make sure a half switched relay does not keep running
+                Self::set_relay(&mut relay_manager, relay, false);
+
+                // the rest of the set would be dosed out of ratio
+                let dropped = this.queue.len();
+                this.queue.clear();
+                this.last_set_time = Some(time::OffsetDateTime::now_utc());
+
+                let description = this.describe(&step);
+                log::warn!(
+                    "[nutrient_dosing] <APP> failed -> #{id} {description}, dropped {dropped} queued steps"
+                );
+                cmd.spawn(
+                    action::Response(Err(format!("failed #{id} {description}").into()))
+                        .make_mqtt_msg(),
+                );
+                return;
+            }
+        }
+        log::info!(
+            "[nutrient_dosing] <APP> start -> #{id} {}",
+            this.describe(&step)
+        );
+
+        let task = rt.spawn_background_task(move |mut ctx| async move {
+            tokio::time::sleep(step.duration()).await;
+
+            ctx.run_on_main_thread(move |ctx| {
+                let world = ctx.world;
+
+                let mut this = world.get_resource_mut::<Self>().unwrap();
+                if this.active.as_ref().is_none_or(|a| a.id != id) {
+                    return;
+                }
+                this.active = None;
+
+                if this.queue.is_empty() {
+                    this.last_set_time = Some(time::OffsetDateTime::now_utc());
+                }
+
+                log::info!(
+                    "[nutrient_dosing] <APP> done -> #{id} {}",
+                    this.describe(&step)
+                );
+
+                if let Step::Dose {
+                    pump,
+                    relay,
+                    volume,
+                    ..
+                } = step
+                {
+                    this.finish_dose(pump, volume);
+
+                    let mut relay_manager = world.resource_mut::<plugins::manager::RelayManager>();
+                    Self::set_relay(&mut relay_manager, relay, false);
+                }
+            })
+            .await;
+        });
+
+        this.active = Some(ActiveStep {
+            id,
+            step,
+            start_time: time::OffsetDateTime::now_utc(),
+            task,
+        });
+    }
+
+    fn set_relay(
+        relay_manager: &mut plugins::manager::RelayManager,
+        relay: relay_module::Channel,
+        state: bool,
+    ) -> bool {
+        if let Err(e) = relay_manager.update_state(relay.update(state)) {
+            log::warn!(
+                "[nutrient_dosing] failed to update relay manager, reason:\n{}",
+                e.fmt_error()
+            );
+            return false;
+        }
+
+        true
+    }
+
+    fn finish_dose(&mut self, pump: usize, volume: Option<f32>) {
+        let state = &mut self.pumps[pump];
+        state.last_volume = volume;
+        state.total_volume += volume.unwrap_or_default();
+
+        if let Some(volume) = volume {
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            let history = &mut self.safety.history;
+
+            history.push_back(DoseRecord {
+                timestamp: now,
+                volume,
+            });
+            while history
+                .front()
+                .is_some_and(|record| record.timestamp <= now - Self::DAY)
+            {
+                history.pop_front();
+            }
+        }
+    }
+}
+impl mqtt::add_on::action_message::RequestHandler for Manager {
+    type Request = action::Update;
+    type Response = action::Response;
+
+    fn update_state(request: Self::Request, state: &mut Self) -> Option<Self::Response> {
+        log::info!("[nutrient_dosing] <USER> set -> {request:?}");
+
+        Some(action::Response(state.update_state(request).map_err(|e| {
+            log::warn!("[nutrient_dosing] request rejected, reason: {e}");
+            e
+        })))
+    }
+
+    fn handle(request: Self::Request, world: &mut World) -> Option<Self::Response> {
+        world.resource_scope(|world, mut this: Mut<Self>| {
+            let response = <Self as mqtt::add_on::action_message::RequestHandler>::update_state(
+                request, &mut this,
+            );
+
+            if let Some(Step::Dose { relay, .. }) = this.cancelled.as_ref().map(|c| c.step) {
+                let mut relay_manager = world.resource_mut::<plugins::manager::RelayManager>();
+                Self::set_relay(&mut relay_manager, relay, false);
+            }
+
+            response
+        })
+    }
+}
+impl state_file::SaveState for Manager {
+    type State<'de> = SafetyState;
+
+    const FILENAME: &str = "nutrient_dosing_manager";
+
+    fn build(state: Self::State<'_>, this: Option<Self>) -> Self {
+        let mut this = this.unwrap_or_else(|| Self::new(Config::default()));
+        this.safety = state;
+        this
+    }
+
+    fn save<'de>(&self) -> Self::State<'de> {
+        self.safety.clone()
+    }
+}
+impl mqtt::add_on::action_message::PublishStatus<action::Status> for Manager {
+    fn query_state() -> impl bevy_internal::prelude::System<In = (), Out = action::Status> {
+        fn func(this: Res<Manager>, lock: Res<plugins::manager::DosingLock>) -> action::Status {
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            let active_pump = match this.active.as_ref().map(|a| a.step) {
+                Some(Step::Dose { pump, .. }) => Some(pump),
+                _ => None,
+            };
+
+            action::Status {
+                auto: this.auto,
+                ec: this.latest_ec,
+                ec_setpoint: this.config.auto.ec_setpoint,
+                active: this.active.as_ref().map(|a| this.describe(&a.step).into()),
+                queued: this.queue.len(),
+                waiting_for: lock
+                    .owner()
+                    .filter(|owner| *owner != action::GROUP && !this.queue.is_empty())
+                    .map(Into::into),
+                pumps: this
+                    .config
+                    .pumps
+                    .iter()
+                    .zip(this.pumps.iter())
+                    .enumerate()
+                    .map(|(i, (config, state))| action::PumpStatus {
+                        name: config.name.clone(),
+                        state: active_pump == Some(i),
+                        flow_rate: config.flow_rate,
+                        last_volume: state.last_volume,
+                        total_volume: state.total_volume,
+                    })
+                    .collect(),
+                hourly_volume: this.safety.dispensed_since(now - Manager::HOUR),
+                daily_volume: this.safety.dispensed_since(now - Manager::DAY),
+                lockout: this.safety.lockout,
+                consecutive_sets: this.safety.consecutive_sets,
+            }
+        }
+
+        IntoSystem::into_system(func)
+    }
+}
+impl ConfigFile for Manager {
+    const FILENAME: &'static str = "nutrient_dosing";
+    type Config = Config;
+}
+impl HotReload for Manager {
+    fn apply_config(config: &Config, world: &mut World) -> Result<(), AtomicFixedString> {
+        if let Some(sprayer) = world.get_resource::<plugins::manager::AeroponicSprayManager>() {
+            config.check_sprayer_relays(sprayer.relays())?;
+        }
+
+        let mut this = world.resource_mut::<Manager>();
+
+        // queued steps refer to pumps by index
+        if this.is_dosing() && config.pumps.len() != this.config.pumps.len() {
+            return Err("pumps can not be added or removed while dosing".into());
+        }
+
+        if config.auto.enabled != this.config.auto.enabled {
+            this.auto = config.auto.enabled;
+        }
+        this.pumps.resize(config.pumps.len(), PumpState::default());
+        this.config = config.clone();
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DoseAmount {
+    Time(Duration),
+    /// volume in ml, requires a calibrated pump
+    Volume(f32),
+}
+
+pub mod action {
+    use crate::{constants, mqtt, AtomicFixedString};
+
+    pub const GROUP: &str = "nutrient_dosing";
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+    pub struct Update {
+        pub dose: Option<ManualDose>,
+        /// dose a set of `Config::unit_volume_user` over every pump
+        pub dose_set: Option<bool>,
+        pub dose_set_ml: Option<f32>,
+        pub auto: Option<bool>,
+        pub cancel: Option<bool>,
+        pub reset_lockout: Option<bool>,
+    }
+    impl mqtt::add_on::action_message::MessageImpl for Update {
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Request;
+        const GROUP: &'static str = GROUP;
+        const QOS: mqtt::Qos = mqtt::Qos::_1;
+    }
+
+    /// Doses a single pump, by `volume` (ml) if given, otherwise by `time` (s).
+    #[derive(Debug, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+    pub struct ManualDose {
+        pub pump: AtomicFixedString,
+        pub volume: Option<f32>,
+        pub time: Option<f32>,
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct Response(pub Result<AtomicFixedString, AtomicFixedString>);
+    impl mqtt::add_on::action_message::MessageImpl for Response {
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Response;
+        const GROUP: &'static str = GROUP;
+        const QOS: mqtt::Qos = mqtt::Qos::_1;
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct PumpStatus {
+        pub name: AtomicFixedString,
+        pub state: bool,
+        pub flow_rate: Option<f32>,
+        pub last_volume: Option<f32>,
+        pub total_volume: f32,
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct Status {
+        pub auto: bool,
+        pub ec: Option<f32>,
+        pub ec_setpoint: f32,
+        pub active: Option<AtomicFixedString>,
+        pub queued: usize,
+        /// dosing manager currently holding the pumps while steps are queued
+        pub waiting_for: Option<AtomicFixedString>,
+        pub pumps: Vec<PumpStatus>,
+        /// volume (ml) dispensed by every pump
+        pub hourly_volume: f32,
+        pub daily_volume: f32,
+        pub lockout: bool,
+        pub consecutive_sets: u32,
+    }
+    impl mqtt::add_on::action_message::MessageImpl for Status {
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Status;
+        const GROUP: &'static str = GROUP;
+        const QOS: mqtt::Qos = mqtt::Qos::_1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> action::Update {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn pump_run_time_is_capped() {
+        let mut manager = Manager::new(Config::default());
+
+        assert!(manager
+            .update_state(request(r#"{ "dose": { "pump": "A", "time": 1e6 } }"#))
+            .is_err());
+        assert!(manager.queue.is_empty());
+
+        manager.config.pumps[0].flow_rate = Some(1.0);
+        assert!(manager
+            .update_state(request(r#"{ "dose": { "pump": "A", "volume": 1e6 } }"#))
+            .is_err());
+        assert!(manager
+            .update_state(request(r#"{ "dose": { "pump": "A", "volume": 10.0 } }"#))
+            .is_ok());
+        assert_eq!(manager.queue.len(), 1);
+    }
+
+    #[test]
+    fn default_doses_a_and_b_by_ratio() {
+        let config = Config::default();
+        assert!(crate::config::validate(&config).is_ok());
+        assert!(config
+            .check_sprayer_relays(plugins::manager::aeroponic_spray::Config::default().relays())
+            .is_ok());
+
+        let mut manager = Manager::new(config);
+        for pump in manager.config.pumps.iter_mut() {
+            pump.flow_rate = Some(1.0);
+        }
+
+        // pump B is not wired by default
+        assert!(manager
+            .update_state(request(r#"{ "dose_set_ml": 10.0 }"#))
+            .is_err());
+        assert!(manager.queue.is_empty());
+
+        manager.config.pumps[1].relay = Some(relay_module::Channel::Relay2);
+        manager
+            .update_state(request(r#"{ "dose_set_ml": 10.0 }"#))
+            .unwrap();
+
+        let volumes = manager
+            .queue
+            .iter()
+            .filter_map(|(_, step)| match *step {
+                Step::Dose { pump, volume, .. } => Some((pump, volume)),
+                Step::Mix(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(volumes, [(0, Some(5.0)), (1, Some(5.0))]);
+    }
+}
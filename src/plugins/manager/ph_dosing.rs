@@ -1,95 +1,1191 @@
-use std::time::Duration;
+use std::{collections::VecDeque, time::Duration};
 
 use bevy_app::{Startup, Update};
-use bevy_ecs::system::{Commands, Res, Resource};
-use bevy_internal::prelude::DetectChanges;
+use bevy_ecs::{
+    change_detection::Mut,
+    system::{Commands, IntoSystem, Res, ResMut, Resource},
+    world::World,
+};
+use bevy_internal::{prelude::DetectChangesMut, time::common_conditions::on_timer};
 use bevy_tokio_tasks::TokioTasksRuntime;
 
-use crate::{config::ConfigFile, constants, helper::ToBytes, log, mqtt, plugins};
+use crate::{
+    config::{ConfigFile, Errors, HotReload, Validate},
+    constants,
+    helper::{ErrorLogFormat, ToBytes},
+    log,
+    mqtt::{self, add_on::home_assistant, message::MessageInfo},
+    plugins::{self, state_file},
+    AtomicFixedString,
+};
 
 pub struct Plugin {
     pub config: Config,
 }
 impl bevy_app::Plugin for Plugin {
     fn build(&self, app: &mut bevy_app::App) {
+        use mqtt::add_on::action_message::{ConfigMessage, RequestMessage, StatusMessage};
+
         app.init_resource::<plugins::manager::RelayManager>()
+            .init_resource::<plugins::manager::DosingLock>()
             .insert_resource(Manager::new(self.config))
             .add_plugins((
-                mqtt::add_on::action_message::RequestMessage::<Manager>::new(),
-                mqtt::add_on::action_message::ConfigMessage::<Manager, Config>::new(),
+                RequestMessage::<Manager>::new(),
+                ConfigMessage::<Manager, Config>::new(),
+                StatusMessage::<Manager, action::Status>::publish_condition(
+                    on_timer(Duration::from_secs(1)), //
+                ),
+                StatusMessage::<Manager, action::QueueStatus>::publish_condition(
+                    on_timer(Duration::from_secs(1)), //
+                ),
+                state_file::StateFile::<Manager>::new(),
             ))
             .add_systems(Startup, (Manager::register_home_assistant,))
-            .add_systems(Update, (Manager::update_ph_down, Manager::update_ph_up));
+            .add_systems(
+                Update,
+                (Manager::watch_ph, Manager::run_doses, Manager::settle_doses),
+            );
     }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, schemars::JsonSchema)]
 pub struct Config {
     #[serde(
         serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
         deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
     )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
     pub unit_time_user: Duration,
+    /// volume (ml) dispensed per user press, used instead of `unit_time_user` once
+    /// the pump is calibrated
+    #[serde(default)]
+    pub unit_volume_user: Option<f32>,
+    /// pump run time of a calibration run
+    #[serde(
+        default = "Config::default_calibration_time",
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    pub calibration_time: Duration,
+    /// wait after a dose before the pH is recorded in the dose journal
+    #[serde(
+        default = "Config::default_settle_time",
+        serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
+        deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
+    )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
+    pub settle_time: Duration,
+    #[serde(default)]
+    pub ph_down_pump: PumpConfig,
+    #[serde(default)]
+    pub ph_up_pump: PumpConfig,
+    #[serde(default)]
+    pub limits: Limits,
+}
+impl Config {
+    fn default_calibration_time() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    fn default_settle_time() -> Duration {
+        Duration::from_secs(5 * 60)
+    }
+
+    fn pump(&self, pump: Pump) -> &PumpConfig {
+        match pump {
+            Pump::PhDown => &self.ph_down_pump,
+            Pump::PhUp => &self.ph_up_pump,
+        }
+    }
+
+    fn pump_mut(&mut self, pump: Pump) -> &mut PumpConfig {
+        match pump {
+            Pump::PhDown => &mut self.ph_down_pump,
+            Pump::PhUp => &mut self.ph_up_pump,
+        }
+    }
 }
 impl Default for Config {
     fn default() -> Self {
         Self {
             unit_time_user: Duration::from_secs(3),
+            unit_volume_user: None,
+            calibration_time: Self::default_calibration_time(),
+            settle_time: Self::default_settle_time(),
+            ph_down_pump: PumpConfig::default(),
+            ph_up_pump: PumpConfig::default(),
+            limits: Limits::default(),
         }
     }
 }
+impl Validate for Config {
+    fn validate(&self, errors: &mut Errors) {
+        fn positive(errors: &mut Errors, path: &str, value: Option<f32>) {
+            errors.check(
+                value.is_none_or(|v| v.is_finite() && v > 0.0),
+                path,
+                "must be positive",
+            );
+        }
+
+        errors.check(
+            !self.unit_time_user.is_zero(),
+            "unit_time_user",
+            "must be positive",
+        );
+        errors.check_duration("unit_time_user", self.unit_time_user, Manager::MAX_RUN_TIME);
+        positive(errors, "unit_volume_user", self.unit_volume_user);
+        errors.check(
+            !self.calibration_time.is_zero(),
+            "calibration_time",
+            "must be positive",
+        );
+        errors.check_duration(
+            "calibration_time",
+            self.calibration_time,
+            Manager::MAX_RUN_TIME,
+        );
+        errors.check_duration(
+            "settle_time",
+            self.settle_time,
+            Duration::from_secs(24 * 60 * 60),
+        );
+
+        for (name, pump) in [
+            ("ph_down_pump", &self.ph_down_pump),
+            ("ph_up_pump", &self.ph_up_pump),
+        ] {
+            positive(errors, &format!("{name}.flow_rate"), pump.flow_rate);
+            positive(
+                errors,
+                &format!("{name}.container_volume"),
+                pump.container_volume,
+            );
+            errors.check(
+                pump.low_stock_volume.is_none_or(|v| v >= 0.0),
+                format_args!("{name}.low_stock_volume"),
+                "must not be negative",
+            );
+        }
+
+        positive(
+            errors,
+            "limits.max_dose_volume",
+            self.limits.max_dose_volume,
+        );
+        positive(
+            errors,
+            "limits.max_hourly_volume",
+            self.limits.max_hourly_volume,
+        );
+        positive(
+            errors,
+            "limits.max_daily_volume",
+            self.limits.max_daily_volume,
+        );
+        errors.check(
+            self.limits.min_ph_change >= 0.0,
+            "limits.min_ph_change",
+            "must not be negative",
+        );
+    }
+}
 impl mqtt::add_on::action_message::MessageImpl for Config {
-    const PREFIX: &'static str = constants::mqtt_prefix::CONFIG;
-    const PROJECT: &'static str = constants::project::NAME;
+    const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Config;
     const GROUP: &'static str = action::GROUP;
-    const DEVICE: &'static str = constants::project::DEVICE;
     const QOS: mqtt::Qos = mqtt::Qos::_1;
 }
 
+#[derive(
+    Debug, Default, serde::Deserialize, serde::Serialize, Clone, Copy, schemars::JsonSchema,
+)]
+#[serde(default)]
+pub struct PumpConfig {
+    /// calibrated flow rate in ml per second, `None` if the pump was never calibrated
+    pub flow_rate: Option<f32>,
+    /// container volume (ml) restored by a refill request
+    pub container_volume: Option<f32>,
+    /// remaining volume (ml) below which a low stock warning is raised
+    pub low_stock_volume: Option<f32>,
+}
+
+/// Safety limits applied to every dose, volume limits are per pump and require a
+/// calibrated pump.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, schemars::JsonSchema)]
+#[serde(default)]
+pub struct Limits {
+    pub max_dose_volume: Option<f32>,
+    /// rolling 1 hour window
+    pub max_hourly_volume: Option<f32>,
+    /// rolling 24 hour window
+    pub max_daily_volume: Option<f32>,
+    /// doses allowed before locking out when the pH does not move by `min_ph_change`
+    pub max_doses_without_change: Option<u32>,
+    pub min_ph_change: f32,
+}
+impl Limits {
+    fn has_volume_limit(&self) -> bool {
+        self.max_dose_volume.is_some()
+            || self.max_hourly_volume.is_some()
+            || self.max_daily_volume.is_some()
+    }
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_dose_volume: None,
+            max_hourly_volume: None,
+            max_daily_volume: None,
+            max_doses_without_change: Some(5),
+            min_ph_change: 0.05,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Pump {
+    PhDown,
+    PhUp,
+}
+impl Pump {
+    fn relay_update(&self, state: bool) -> plugins::manager::relay_module::action::Update {
+        use plugins::manager::relay_module::action::Update;
+
+        match self {
+            Pump::PhDown => Update {
+                relay_6: Some(state),
+                ..Update::empty()
+            },
+            Pump::PhUp => Update {
+                relay_7: Some(state),
+                ..Update::empty()
+            },
+        }
+    }
+}
+impl std::fmt::Display for Pump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pump::PhDown => write!(f, "ph_down"),
+            Pump::PhUp => write!(f, "ph_up"),
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Initiator {
+    User,
+    Calibration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DoseAmount {
+    Time(Duration),
+    /// volume in ml, requires a calibrated pump
+    Volume(f32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Dose {
+    pub pump: Pump,
+    pub run_time: Duration,
+    /// expected volume in ml, `None` if the pump is not calibrated
+    pub volume: Option<f32>,
+}
+impl std::fmt::Display for Dose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.volume {
+            Some(volume) => write!(
+                f,
+                "{}: {volume:.2} ml ({:.2} s)",
+                self.pump,
+                self.run_time.as_secs_f32()
+            ),
+            None => write!(f, "{}: {:.2} s", self.pump, self.run_time.as_secs_f32()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueuedDose {
+    pub id: u32,
+    pub dose: Dose,
+    pub initiator: Initiator,
+}
+impl std::fmt::Display for QueuedDose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{} {}", self.id, self.dose)
+    }
+}
+
+#[derive(Debug)]
+struct ActiveDose {
+    id: u32,
+    dose: Dose,
+    initiator: Initiator,
+    ph_before: Option<f32>,
+    start_time: time::OffsetDateTime,
+    /// `None` if the pump never started
+    task: Option<tokio::task::JoinHandle<()>>,
+}
+
+/// Finished dose waiting for the pH to settle before it is journaled.
+#[derive(Debug)]
+struct SettlingDose {
+    event: action::DoseEvent,
+    due: time::OffsetDateTime,
+}
+
+#[derive(Debug, Default)]
+struct PumpState {
+    last_volume: Option<f32>,
+    total_volume: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Calibration {
+    pump: Pump,
+    run_time: Duration,
+}
+
+/// Checked calibration request, applied once the rest of the request passed too.
+#[derive(Debug, Clone, Copy)]
+enum CalibrationStep {
+    Start(Dose),
+    Finish {
+        pump: Pump,
+        run_time: Duration,
+        volume: f32,
+    },
+    Abort(Pump),
+}
+
+/// Doses and safety state as they will be once the parts of a request checked so far
+/// are applied, so nothing changes unless the whole request passes.
+#[derive(Debug)]
+struct Preview {
+    /// active and queued doses that are not cancelled
+    pending: Vec<Dose>,
+    queue_len: usize,
+    calibration: Option<Calibration>,
+    safety: SafetyState,
+}
+impl Preview {
+    fn is_busy(&self, pump: Pump) -> bool {
+        self.pending.iter().any(|dose| dose.pump == pump)
+    }
+
+    /// volume of pending doses on `pump` that is not dispensed yet
+    fn scheduled_volume(&self, pump: Pump) -> f32 {
+        self.pending
+            .iter()
+            .filter(|dose| dose.pump == pump)
+            .filter_map(|dose| dose.volume)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+struct DoseRecord {
+    timestamp: i64,
+    volume: f32,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+struct Inventory {
+    /// remaining volume (ml) in the container, `None` until the first refill
+    remaining: Option<f32>,
+    /// doses of the last 24 hours
+    history: VecDeque<DoseRecord>,
+}
+impl Inventory {
+    fn dispensed_since(&self, timestamp: i64) -> f32 {
+        self.history
+            .iter()
+            .filter(|record| record.timestamp > timestamp)
+            .map(|record| record.volume)
+            .sum()
+    }
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SafetyState {
+    lockout: bool,
+    consecutive_doses: u32,
+    reference_ph: Option<f32>,
+    ph_down: Inventory,
+    ph_up: Inventory,
+}
+impl SafetyState {
+    fn reset_lockout(&mut self, ph: Option<f32>) {
+        self.lockout = false;
+        self.consecutive_doses = 0;
+        self.reference_ph = ph;
+    }
+
+    fn inventory(&self, pump: Pump) -> &Inventory {
+        match pump {
+            Pump::PhDown => &self.ph_down,
+            Pump::PhUp => &self.ph_up,
+        }
+    }
+
+    fn inventory_mut(&mut self, pump: Pump) -> &mut Inventory {
+        match pump {
+            Pump::PhDown => &mut self.ph_down,
+            Pump::PhUp => &mut self.ph_up,
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct State {
+    #[serde(flatten)]
+    safety: SafetyState,
+    #[serde(default)]
+    journal: VecDeque<action::DoseEvent>,
+}
+
 #[derive(Debug, Resource)]
 pub struct Manager {
-    ph_down_state: bool,
-    ph_up_state: bool,
+    ph_down: PumpState,
+    ph_up: PumpState,
+    queue: VecDeque<QueuedDose>,
+    active: Option<ActiveDose>,
+    cancelled: Option<ActiveDose>,
+    next_id: u32,
+    calibration: Option<Calibration>,
+    safety: SafetyState,
+    settling: VecDeque<SettlingDose>,
+    journal: VecDeque<action::DoseEvent>,
+    latest_ph: Option<f32>,
     config: Config,
 }
 impl Manager {
+    const HOUR: i64 = 60 * 60;
+    const DAY: i64 = 24 * Self::HOUR;
+    const MAX_QUEUE_LEN: usize = 10;
+    /// longest a pump may run for a single dose, whatever the volume limits
+    pub const MAX_RUN_TIME: Duration = Duration::from_secs(10 * 60);
+    const MAX_JOURNAL_LEN: usize = 200;
+
     fn new(config: Config) -> Self {
         Self {
             config,
-            ph_down_state: false,
-            ph_up_state: false,
+            ph_down: PumpState::default(),
+            ph_up: PumpState::default(),
+            queue: VecDeque::new(),
+            active: None,
+            cancelled: None,
+            next_id: 0,
+            calibration: None,
+            safety: SafetyState::default(),
+            settling: VecDeque::new(),
+            journal: VecDeque::new(),
+            latest_ph: None,
+        }
+    }
+
+    /// Plans `amounts` requested together on top of `preview`, converting volumes into
+    /// pump run time using the calibrated flow rate. Nothing is planned unless every dose
+    /// passes the safety checks.
+    fn plan_doses(
+        &self,
+        preview: &mut Preview,
+        amounts: &[(Pump, DoseAmount)],
+    ) -> Result<Vec<Dose>, AtomicFixedString> {
+        let doses = amounts
+            .iter()
+            .map(|&(pump, amount)| self.plan_dose(preview, pump, amount))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if preview.queue_len + doses.len() > Self::MAX_QUEUE_LEN {
+            return Err("dose queue is full".into());
+        }
+
+        self.check_safety(preview, &doses)?;
+
+        preview.pending.extend(doses.iter().copied());
+        preview.queue_len += doses.len();
+
+        Ok(doses)
+    }
+
+    fn enqueue(&mut self, dose: Dose, initiator: Initiator) -> QueuedDose {
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let queued = QueuedDose {
+            id: self.next_id,
+            dose,
+            initiator,
+        };
+        self.queue.push_back(queued);
+        queued
+    }
+
+    /// the active dose followed by the queued ones
+    fn scheduled(&self) -> impl Iterator<Item = QueuedDose> + '_ {
+        self.active
+            .iter()
+            .map(|active| QueuedDose {
+                id: active.id,
+                dose: active.dose,
+                initiator: active.initiator,
+            })
+            .chain(self.queue.iter().copied())
+    }
+
+    /// State the checks of a request start from, with the doses in `cancelled` gone.
+    fn preview(&self, cancelled: &[u32]) -> Preview {
+        let (cancelled, pending): (Vec<_>, Vec<_>) =
+            self.scheduled().partition(|q| cancelled.contains(&q.id));
+
+        Preview {
+            pending: pending.iter().map(|q| q.dose).collect(),
+            queue_len: self
+                .queue
+                .iter()
+                .filter(|q| !cancelled.iter().any(|c| c.id == q.id))
+                .count(),
+            // cancelling a calibration run aborts the calibration
+            calibration: self
+                .calibration
+                .filter(|c| !cancelled.iter().any(|q| q.dose.pump == c.pump)),
+            safety: self.safety.clone(),
+        }
+    }
+
+    fn plan_dose(
+        &self,
+        preview: &Preview,
+        pump: Pump,
+        amount: DoseAmount,
+    ) -> Result<Dose, AtomicFixedString> {
+        if preview.queue_len >= Self::MAX_QUEUE_LEN {
+            return Err("dose queue is full".into());
+        }
+
+        if matches!(preview.calibration, Some(c) if c.pump == pump) {
+            return Err(format!("{pump} pump is being calibrated").into());
+        }
+
+        let flow_rate = self.config.pump(pump).flow_rate;
+
+        let dose = match amount {
+            DoseAmount::Time(run_time) => Dose {
+                pump,
+                run_time,
+                volume: flow_rate.map(|rate| rate * run_time.as_secs_f32()),
+            },
+            DoseAmount::Volume(volume) => {
+                if !volume.is_finite() || volume <= 0.0 {
+                    return Err(format!("invalid {pump} dose volume: {volume}").into());
+                }
+
+                let Some(rate) = flow_rate.filter(|rate| *rate > 0.0) else {
+                    return Err(format!("{pump} pump is not calibrated").into());
+                };
+
+                let Ok(run_time) = Duration::try_from_secs_f32(volume / rate) else {
+                    return Err(format!("{pump} dose volume out of range: {volume}").into());
+                };
+
+                Dose {
+                    pump,
+                    run_time,
+                    volume: Some(volume),
+                }
+            }
+        };
+
+        if dose.run_time > Self::MAX_RUN_TIME {
+            return Err(format!(
+                "{pump} dose of {:.2} s exceeds the {} s pump run limit",
+                dose.run_time.as_secs_f32(),
+                Self::MAX_RUN_TIME.as_secs()
+            )
+            .into());
+        }
+
+        Ok(dose)
+    }
+
+    /// Checks `doses` requested together against the lockout and the limits, counting
+    /// them as doses without pH change only if all of them pass.
+    fn check_safety(&self, preview: &mut Preview, doses: &[Dose]) -> Result<(), AtomicFixedString> {
+        let limits = self.config.limits;
+
+        if preview.safety.lockout {
+            return Err("dosing is locked out, reset the lockout to resume".into());
+        }
+
+        for (i, dose) in doses.iter().enumerate() {
+            let pending = doses[..i]
+                .iter()
+                .filter(|other| other.pump == dose.pump)
+                .filter_map(|other| other.volume)
+                .sum::<f32>();
+            self.check_volume(preview, dose, pending)?;
+        }
+
+        if let Some(max) = limits.max_doses_without_change {
+            let safety = &mut preview.safety;
+            let ph_changed = match (safety.reference_ph, self.latest_ph) {
+                (Some(reference), Some(ph)) => (ph - reference).abs() >= limits.min_ph_change,
+                _ => true,
+            };
+
+            if ph_changed {
+                safety.reference_ph = self.latest_ph;
+                safety.consecutive_doses = 0;
+            }
+
+            if safety.consecutive_doses >= max {
+                safety.lockout = true;
+                log::warn!(
+                    "[ph_dosing] <APP> locked out after {max} doses without a pH change of {}",
+                    limits.min_ph_change
+                );
+                return Err(format!("locked out after {max} doses without pH change").into());
+            }
+
+            let count = doses.len() as u32;
+            if safety.consecutive_doses + count > max {
+                return Err(format!(
+                    "{count} doses would exceed the limit of {max} doses without pH change"
+                )
+                .into());
+            }
+
+            safety.consecutive_doses += count;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the volume limits of `dose`, `pending` is the volume of doses on the same
+    /// pump requested along with it.
+    fn check_volume(
+        &self,
+        preview: &Preview,
+        dose: &Dose,
+        pending: f32,
+    ) -> Result<(), AtomicFixedString> {
+        let Dose { pump, volume, .. } = *dose;
+        let limits = self.config.limits;
+
+        let inventory = preview.safety.inventory(pump);
+        let scheduled = preview.scheduled_volume(pump) + pending;
+        if limits.has_volume_limit() || inventory.remaining.is_some() {
+            let Some(volume) = volume else {
+                return Err(
+                    format!("{pump} pump must be calibrated to enforce volume limits").into(),
+                );
+            };
+
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+            if let Some(max) = limits.max_dose_volume {
+                if volume > max {
+                    return Err(format!(
+                        "{pump} dose of {volume:.2} ml exceeds the {max:.2} ml dose limit"
+                    )
+                    .into());
+                }
+            }
+
+            if let Some(max) = limits.max_hourly_volume {
+                let dispensed = inventory.dispensed_since(now - Self::HOUR) + scheduled;
+                if dispensed + volume > max {
+                    return Err(format!(
+                        "{pump} dose would exceed the hourly limit ({dispensed:.2}/{max:.2} ml)"
+                    )
+                    .into());
+                }
+            }
+
+            if let Some(max) = limits.max_daily_volume {
+                let dispensed = inventory.dispensed_since(now - Self::DAY) + scheduled;
+                if dispensed + volume > max {
+                    return Err(format!(
+                        "{pump} dose would exceed the daily limit ({dispensed:.2}/{max:.2} ml)"
+                    )
+                    .into());
+                }
+            }
+
+            if let Some(remaining) = inventory.remaining {
+                let remaining = remaining - scheduled;
+                if volume > remaining {
+                    return Err(
+                        format!("not enough {pump} left ({remaining:.2} ml remaining)").into(),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record_dose(&mut self, pump: Pump, volume: f32) {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let low_stock_volume = self.config.pump(pump).low_stock_volume;
+
+        let inventory = self.safety.inventory_mut(pump);
+        inventory.history.push_back(DoseRecord {
+            timestamp: now,
+            volume,
+        });
+        while inventory
+            .history
+            .front()
+            .is_some_and(|record| record.timestamp <= now - Self::DAY)
+        {
+            inventory.history.pop_front();
+        }
+
+        if let Some(remaining) = inventory.remaining.as_mut() {
+            let before = *remaining;
+            *remaining = (*remaining - volume).max(0.0);
+
+            if let Some(low) = low_stock_volume {
+                if before > low && *remaining <= low {
+                    log::warn!(
+                        "[ph_dosing] <APP> {pump} low stock -> {:.2} ml remaining",
+                        *remaining
+                    );
+                }
+            }
+        }
+    }
+
+    fn is_low_stock(&self, pump: Pump) -> bool {
+        match (
+            self.safety.inventory(pump).remaining,
+            self.config.pump(pump).low_stock_volume,
+        ) {
+            (Some(remaining), Some(low)) => remaining <= low,
+            _ => false,
+        }
+    }
+
+    fn check_refill(&self, request: action::Refill) -> Result<(Pump, f32), AtomicFixedString> {
+        let action::Refill { pump, volume } = request;
+
+        let Some(volume) = volume.or(self.config.pump(pump).container_volume) else {
+            return Err(format!("{pump} container volume is not configured").into());
+        };
+
+        if !volume.is_finite() || volume < 0.0 {
+            return Err(format!("invalid {pump} refill volume: {volume}").into());
+        }
+
+        Ok((pump, volume))
+    }
+
+    fn pump(&self, pump: Pump) -> &PumpState {
+        match pump {
+            Pump::PhDown => &self.ph_down,
+            Pump::PhUp => &self.ph_up,
+        }
+    }
+
+    fn pump_mut(&mut self, pump: Pump) -> &mut PumpState {
+        match pump {
+            Pump::PhDown => &mut self.ph_down,
+            Pump::PhUp => &mut self.ph_up,
+        }
+    }
+
+    fn user_dose_amount(&self, pump: Pump) -> DoseAmount {
+        match (
+            self.config.unit_volume_user,
+            self.config.pump(pump).flow_rate,
+        ) {
+            (Some(volume), Some(_)) => DoseAmount::Volume(volume),
+            _ => DoseAmount::Time(self.config.unit_time_user),
         }
     }
 
+    fn check_calibration(
+        &self,
+        preview: &Preview,
+        request: action::Calibrate,
+    ) -> Result<CalibrationStep, AtomicFixedString> {
+        match request {
+            action::Calibrate::Start { pump } => {
+                if let Some(calibration) = preview.calibration {
+                    return Err(format!("{} pump calibration in progress", calibration.pump).into());
+                }
+
+                if preview.is_busy(pump) {
+                    return Err(format!("{pump} pump is busy").into());
+                }
+
+                // the volume limits need the flow rate being calibrated, the lockout holds
+                if preview.safety.lockout {
+                    return Err("dosing is locked out, reset the lockout to resume".into());
+                }
+
+                let dose = self.plan_dose(
+                    preview,
+                    pump,
+                    DoseAmount::Time(self.config.calibration_time),
+                )?;
+                Ok(CalibrationStep::Start(dose))
+            }
+            action::Calibrate::Finish { volume } => {
+                let Some(Calibration { pump, run_time }) = preview.calibration else {
+                    return Err("no calibration in progress".into());
+                };
+
+                if preview.is_busy(pump) {
+                    return Err(format!("{pump} pump calibration run not finished").into());
+                }
+
+                if !volume.is_finite() || volume <= 0.0 {
+                    return Err(format!("invalid calibration volume: {volume}").into());
+                }
+
+                Ok(CalibrationStep::Finish {
+                    pump,
+                    run_time,
+                    volume,
+                })
+            }
+            action::Calibrate::Abort => match preview.calibration {
+                Some(Calibration { pump, .. }) => Ok(CalibrationStep::Abort(pump)),
+                None => Err("no calibration in progress".into()),
+            },
+        }
+    }
+
+    fn calibrate(&mut self, step: CalibrationStep) -> AtomicFixedString {
+        match step {
+            CalibrationStep::Start(dose) => {
+                let queued = self.enqueue(dose, Initiator::Calibration);
+                self.calibration = Some(Calibration {
+                    pump: dose.pump,
+                    run_time: dose.run_time,
+                });
+
+                format!(
+                    "calibrating {} pump (#{}) for {:.2} s, send the measured volume when done",
+                    dose.pump,
+                    queued.id,
+                    dose.run_time.as_secs_f32()
+                )
+                .into()
+            }
+            CalibrationStep::Finish {
+                pump,
+                run_time,
+                volume,
+            } => {
+                let flow_rate = volume / run_time.as_secs_f32();
+
+                self.calibration = None;
+                self.config.pump_mut(pump).flow_rate = Some(flow_rate);
+
+                let state = self.pump_mut(pump);
+                state.last_volume = Some(volume);
+                state.total_volume += volume;
+                self.record_dose(pump, volume);
+
+                if let Err(e) = Self::save_config(self.config, crate::config::history::Source::App)
+                {
+                    log::warn!("[ph_dosing] failed to save calibration, reason: {e}");
+                }
+
+                format!("{pump} pump calibrated: {flow_rate:.3} ml/s").into()
+            }
+            CalibrationStep::Abort(pump) => {
+                self.calibration = None;
+                format!("{pump} pump calibration aborted").into()
+            }
+        }
+    }
+
+    /// ids of the active and queued doses `request` cancels
+    fn cancel_targets(&self, request: action::Cancel) -> Result<Vec<u32>, AtomicFixedString> {
+        let ids = match request {
+            action::Cancel::All => self.scheduled().map(|q| q.id).collect::<Vec<_>>(),
+            action::Cancel::Active => self.active.iter().map(|a| a.id).collect(),
+            action::Cancel::Id(id) => self
+                .scheduled()
+                .map(|q| q.id)
+                .filter(|&q| q == id)
+                .collect(),
+        };
+
+        if ids.is_empty() {
+            return Err("no matching dose to cancel".into());
+        }
+
+        Ok(ids)
+    }
+
+    fn cancel(&mut self, ids: &[u32]) -> AtomicFixedString {
+        let (mut cancelled, queue): (Vec<_>, Vec<_>) =
+            self.queue.drain(..).partition(|q| ids.contains(&q.id));
+        self.queue = queue.into();
+
+        if let Some(active) = self.active.take_if(|a| ids.contains(&a.id)) {
+            // the relay is de-energized by `handle`, `run_doses` journals the dose
+            if let Some(task) = &active.task {
+                task.abort();
+            }
+            cancelled.push(QueuedDose {
+                id: active.id,
+                dose: active.dose,
+                initiator: active.initiator,
+            });
+            self.cancelled = Some(active);
+        }
+
+        if let Some(calibration) = self.calibration {
+            if cancelled.iter().any(|q| q.dose.pump == calibration.pump) {
+                self.calibration = None;
+            }
+        }
+
+        format!(
+            "cancelled {}",
+            cancelled
+                .iter()
+                .map(|q| q.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into()
+    }
+
     fn register_home_assistant(mut cmd: Commands) {
         #[derive(serde::Serialize)]
         struct Config {
             name: &'static str,
-            command_topic: &'static str,
+            command_topic: AtomicFixedString,
             command_template: &'static str,
             payload_press: bool,
-            device: mqtt::add_on::home_assistant::Device,
+            device: home_assistant::Device,
+        }
+
+        #[derive(serde::Serialize)]
+        struct State {
+            name: &'static str,
+            state_topic: AtomicFixedString,
+            value_template: &'static str,
+            icon: &'static str,
+            device: home_assistant::Device,
         }
 
         #[derive(serde::Serialize)]
-        struct State {
+        struct Volume {
+            name: &'static str,
+            state_topic: AtomicFixedString,
+            value_template: &'static str,
+            unit_of_measurement: &'static str,
+            icon: &'static str,
+            device: home_assistant::Device,
+        }
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "ph_dosing", "down"),
+            payload: {
+                serde_json::to_value(Config {
+                    name: "Dose pH Down",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"ph_down\" : {{value | lower}} }",
+                    payload_press: true,
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "ph_dosing", "up"),
+            payload: {
+                serde_json::to_value(Config {
+                    name: "Dose pH Up",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"ph_up\" : {{value | lower}} }",
+                    payload_press: true,
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "ph_dosing", "ph_down_pump"),
+            payload: {
+                serde_json::to_value(State {
+                    name: "Pump pH Down",
+                    state_topic: plugins::manager::relay_module::action::RelayStatus::topic(),
+                    value_template: "{{ \"ON\" if value_json.relay_6 else \"OFF\"}}",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                    icon: "mdi:arrow-down-bold-circle",
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "ph_dosing", "ph_up_pump"),
+            payload: {
+                serde_json::to_value(State {
+                    name: "Pump pH Up",
+                    state_topic: plugins::manager::relay_module::action::RelayStatus::topic(),
+                    value_template: "{{ \"ON\" if value_json.relay_7 else \"OFF\"}}",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                    icon: "mdi:arrow-up-bold-circle",
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "ph_dosing", "ph_down_last_volume"),
+            payload: {
+                serde_json::to_value(Volume {
+                    name: "Last Dose pH Down",
+                    state_topic: action::Status::topic(),
+                    value_template: "{{ value_json.ph_down.last_volume }}",
+                    unit_of_measurement: "mL",
+                    icon: "mdi:beaker-minus-outline",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "ph_dosing", "ph_up_last_volume"),
+            payload: {
+                serde_json::to_value(Volume {
+                    name: "Last Dose pH Up",
+                    state_topic: action::Status::topic(),
+                    value_template: "{{ value_json.ph_up.last_volume }}",
+                    unit_of_measurement: "mL",
+                    icon: "mdi:beaker-plus-outline",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        #[derive(serde::Serialize)]
+        struct Problem {
+            name: &'static str,
+            state_topic: AtomicFixedString,
+            value_template: &'static str,
+            device_class: &'static str,
+            device: home_assistant::Device,
+        }
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("binary_sensor", "ph_dosing", "lockout"),
+            payload: {
+                serde_json::to_value(Problem {
+                    name: "Dosing Lockout",
+                    state_topic: action::Status::topic(),
+                    value_template: "{{ \"ON\" if value_json.lockout else \"OFF\" }}",
+                    device_class: "problem",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "ph_dosing", "reset_lockout"),
+            payload: {
+                serde_json::to_value(Config {
+                    name: "Reset Dosing Lockout",
+                    command_topic: action::Update::topic(),
+                    command_template: "{ \"reset_lockout\" : {{value | lower}} }",
+                    payload_press: true,
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        #[derive(serde::Serialize)]
+        struct Press {
             name: &'static str,
-            state_topic: &'static str,
-            value_template: &'static str,
+            command_topic: AtomicFixedString,
+            payload_press: &'static str,
             icon: &'static str,
-            device: mqtt::add_on::home_assistant::Device,
+            device: home_assistant::Device,
         }
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/button/down/ph_dosing/config".into(),
+            topic: home_assistant::topic("button", "ph_dosing", "cancel"),
             payload: {
-                serde_json::to_value(Config {
-                    name: "Dose pH Down",
-                    command_topic: "request/triponics/ph_dosing/0",
-                    command_template: "{ \"ph_down\" : {{value | lower}} }",
-                    payload_press: true,
-                    device: mqtt::add_on::home_assistant::Device {
-                        identifiers: &["triponics-ph-dosing"],
+                serde_json::to_value(Press {
+                    name: "Cancel Dosing",
+                    command_topic: action::Update::topic(),
+                    payload_press: "{ \"cancel\" : \"all\" }",
+                    icon: "mdi:stop-circle-outline",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
                         name: "Dosing Pumps",
                     },
                 })
@@ -101,17 +1197,17 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/button/up/ph_dosing/config".into(),
+            topic: home_assistant::topic("sensor", "ph_dosing", "queue"),
             payload: {
-                serde_json::to_value(Config {
-                    name: "Dose pH Up",
-                    command_topic: "request/triponics/ph_dosing/0",
-                    command_template: "{ \"ph_up\" : {{value | lower}} }",
-                    payload_press: true,
-                    device: mqtt::add_on::home_assistant::Device {
-                        identifiers: &["triponics-ph-dosing"],
+                serde_json::to_value(State {
+                    name: "Queued Doses",
+                    state_topic: action::QueueStatus::topic(),
+                    value_template: "{{ value_json.queued | length }}",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
                         name: "Dosing Pumps",
                     },
+                    icon: "mdi:format-list-numbered",
                 })
                 .unwrap()
                 .to_bytes()
@@ -121,17 +1217,18 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/ph_down_pump/ph_dosing/config".into(),
+            topic: home_assistant::topic("sensor", "ph_dosing", "ph_down_remaining"),
             payload: {
-                serde_json::to_value(State {
-                    name: "Pump pH Down",
-                    state_topic: "status/triponics/relay_module/0",
-                    value_template: "{{ \"ON\" if value_json.relay_6 else \"OFF\"}}",
-                    device: mqtt::add_on::home_assistant::Device {
-                        identifiers: &["triponics-ph-dosing"],
+                serde_json::to_value(Volume {
+                    name: "Remaining pH Down",
+                    state_topic: action::Status::topic(),
+                    value_template: "{{ value_json.ph_down.remaining }}",
+                    unit_of_measurement: "mL",
+                    icon: "mdi:cup-water",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
                         name: "Dosing Pumps",
                     },
-                    icon: "mdi:arrow-down-bold-circle",
                 })
                 .unwrap()
                 .to_bytes()
@@ -141,17 +1238,98 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/ph_up_pump/ph_dosing/config".into(),
+            topic: home_assistant::topic("binary_sensor", "ph_dosing", "ph_down_low_stock"),
             payload: {
-                serde_json::to_value(State {
-                    name: "Pump pH Up",
-                    state_topic: "status/triponics/relay_module/0",
-                    value_template: "{{ \"ON\" if value_json.relay_7 else \"OFF\"}}",
-                    device: mqtt::add_on::home_assistant::Device {
-                        identifiers: &["triponics-ph-dosing"],
+                serde_json::to_value(Problem {
+                    name: "Low Stock pH Down",
+                    state_topic: action::Status::topic(),
+                    value_template: "{{ \"ON\" if value_json.ph_down.low_stock else \"OFF\" }}",
+                    device_class: "problem",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "ph_dosing", "ph_down_refill"),
+            payload: {
+                serde_json::to_value(Press {
+                    name: "Refilled pH Down",
+                    command_topic: action::Update::topic(),
+                    payload_press: "{ \"refill\" : { \"pump\" : \"ph_down\" } }",
+                    icon: "mdi:refresh",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("sensor", "ph_dosing", "ph_up_remaining"),
+            payload: {
+                serde_json::to_value(Volume {
+                    name: "Remaining pH Up",
+                    state_topic: action::Status::topic(),
+                    value_template: "{{ value_json.ph_up.remaining }}",
+                    unit_of_measurement: "mL",
+                    icon: "mdi:cup-water",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("binary_sensor", "ph_dosing", "ph_up_low_stock"),
+            payload: {
+                serde_json::to_value(Problem {
+                    name: "Low Stock pH Up",
+                    state_topic: action::Status::topic(),
+                    value_template: "{{ \"ON\" if value_json.ph_up.low_stock else \"OFF\" }}",
+                    device_class: "problem",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
+                        name: "Dosing Pumps",
+                    },
+                })
+                .unwrap()
+                .to_bytes()
+            },
+            qos: mqtt::Qos::_1,
+            retained: true,
+        });
+
+        cmd.spawn(mqtt::message::Message {
+            topic: home_assistant::topic("button", "ph_dosing", "ph_up_refill"),
+            payload: {
+                serde_json::to_value(Press {
+                    name: "Refilled pH Up",
+                    command_topic: action::Update::topic(),
+                    payload_press: "{ \"refill\" : { \"pump\" : \"ph_up\" } }",
+                    icon: "mdi:refresh",
+                    device: home_assistant::Device {
+                        identifiers: &["ph-dosing"],
                         name: "Dosing Pumps",
                     },
-                    icon: "mdi:arrow-up-bold-circle",
                 })
                 .unwrap()
                 .to_bytes()
@@ -161,130 +1339,326 @@ impl Manager {
         });
     }
 
-    fn update_state(&mut self, state: action::Update) {
-        log::trace!("{state:?}");
+    fn update_state(
+        &mut self,
+        request: action::Update,
+    ) -> Result<AtomicFixedString, AtomicFixedString> {
+        log::trace!("{request:?}");
+
+        let action::Update {
+            ph_down,
+            ph_up,
+            ph_down_ml,
+            ph_up_ml,
+            calibrate,
+            refill,
+            reset_lockout,
+            cancel,
+        } = request;
+
+        // everything is checked before the first change
+        let cancelled = cancel.map(|c| self.cancel_targets(c)).transpose()?;
+        let mut preview = self.preview(cancelled.as_deref().unwrap_or_default());
+
+        let reset_lockout = reset_lockout == Some(true);
+        if reset_lockout {
+            preview.safety.reset_lockout(self.latest_ph);
+        }
+
+        let refill = refill.map(|r| self.check_refill(r)).transpose()?;
+        if let Some((pump, volume)) = refill {
+            preview.safety.inventory_mut(pump).remaining = Some(volume);
+        }
+
+        let mut amounts = Vec::new();
+
+        if let Some(true) = ph_down {
+            amounts.push((Pump::PhDown, self.user_dose_amount(Pump::PhDown)));
+        }
+
+        if let Some(true) = ph_up {
+            amounts.push((Pump::PhUp, self.user_dose_amount(Pump::PhUp)));
+        }
+
+        if let Some(volume) = ph_down_ml {
+            amounts.push((Pump::PhDown, DoseAmount::Volume(volume)));
+        }
+
+        if let Some(volume) = ph_up_ml {
+            amounts.push((Pump::PhUp, DoseAmount::Volume(volume)));
+        }
+
+        let doses = if amounts.is_empty() {
+            Vec::new()
+        } else {
+            match self.plan_doses(&mut preview, &amounts) {
+                Ok(doses) => doses,
+                Err(e) => {
+                    // a tripped lockout latches even though the request is rejected
+                    self.safety.lockout |= preview.safety.lockout;
+                    return Err(e);
+                }
+            }
+        };
+
+        let calibration = calibrate
+            .map(|c| self.check_calibration(&preview, c))
+            .transpose()?;
+
+        let mut out = Vec::new();
+
+        if let Some(ids) = cancelled {
+            out.push(self.cancel(&ids).to_string());
+        }
+
+        if reset_lockout {
+            out.push("lockout reset".to_string());
+        }
+
+        if let Some((pump, volume)) = refill {
+            out.push(format!("{pump} container refilled: {volume:.2} ml"));
+        }
+
+        self.safety = preview.safety;
+
+        for dose in doses {
+            let queued = self.enqueue(dose, Initiator::User);
+            out.push(format!("queued {queued}"));
+        }
 
-        let action::Update { ph_down, ph_up } = state;
+        if let Some(step) = calibration {
+            out.push(self.calibrate(step).to_string());
+        }
 
-        if let Some(down_state) = ph_down {
-            self.ph_down_state = down_state;
+        if out.is_empty() {
+            Ok("nothing to do".into())
+        } else {
+            Ok(out.join("; ").into())
         }
+    }
 
-        if let Some(up_state) = ph_up {
-            self.ph_up_state = up_state;
+    fn watch_ph(
+        mut this: ResMut<Self>,
+        sensor: Option<Res<plugins::manager::water_quality_sensor::Manager>>,
+    ) {
+        // the sensor reports 0.0 until its first reading
+        if let Some(sensor) = sensor.filter(|sensor| sensor.sampled_at().is_some()) {
+            // the latest reading is not part of the saved state
+            this.bypass_change_detection().latest_ph = Some(sensor.get_data().ph());
         }
     }
 
-    fn update_ph_down(rt: Res<TokioTasksRuntime>, this: Res<Self>) {
-        if !this.is_changed() || this.is_added() {
+    fn run_doses(
+        mut cmd: Commands,
+        rt: Res<TokioTasksRuntime>,
+        mut this: ResMut<Self>,
+        mut relay_manager: ResMut<plugins::manager::RelayManager>,
+        mut lock: ResMut<plugins::manager::DosingLock>,
+    ) {
+        if this.cancelled.is_some() {
+            let active = this.cancelled.take().unwrap();
+
+            let event = this.end_dose(&active, action::Outcome::Cancelled);
+            log::info!(
+                "[ph_dosing] <USER> cancelled -> #{} {} after {:.2} s",
+                event.id,
+                event.pump,
+                event.actual_run_time
+            );
+            cmd.spawn(action::Response(Ok(event.to_string().into())).make_mqtt_msg());
+        }
+
+        if this.active.is_some() {
             return;
         }
 
-        if !this.ph_down_state {
+        if this.queue.is_empty() {
+            lock.release(action::GROUP);
             return;
         }
 
-        let dur = this.config.unit_time_user;
+        // wait for other dosing managers to finish
+        if !lock.try_acquire(action::GROUP) {
+            return;
+        }
 
-        rt.spawn_background_task(move |mut ctx| async move {
-            ctx.run_on_main_thread(|ctx| {
-                let mut relay_manager = ctx
-                    .world
-                    .get_resource_mut::<plugins::manager::RelayManager>()
-                    .unwrap();
+        let QueuedDose {
+            id,
+            dose,
+            initiator,
+        } = this.queue.pop_front().unwrap();
 
-                relay_manager
-                    .update_state(plugins::manager::relay_module::action::Update {
-                        relay_6: Some(true),
-                        ..plugins::manager::relay_module::action::Update::empty()
-                    })
-                    .unwrap();
-            })
-            .await;
+        let ph_before = this.latest_ph;
+        let start_time = time::OffsetDateTime::now_utc();
+
+        if !Self::set_pump(&mut relay_manager, dose.pump, true) {
+            // make sure a half switched relay does not keep running
+            Self::set_pump(&mut relay_manager, dose.pump, false);
+
+            let event = this.end_dose(
+                &ActiveDose {
+                    id,
+                    dose,
+                    initiator,
+                    ph_before,
+                    start_time,
+                    task: None,
+                },
+                action::Outcome::Failed,
+            );
+            log::warn!("[ph_dosing] <APP> failed -> #{id} {dose}");
+            cmd.spawn(action::Response(Err(event.to_string().into())).make_mqtt_msg());
+            return;
+        }
+        log::info!("[ph_dosing] <APP> start -> #{id} {dose}");
 
-            tokio::time::sleep(dur).await;
+        let task = rt.spawn_background_task(move |mut ctx| async move {
+            tokio::time::sleep(dose.run_time).await;
 
-            ctx.run_on_main_thread(|ctx| {
+            ctx.run_on_main_thread(move |ctx| {
                 let world = ctx.world;
 
+                let mut this = world.get_resource_mut::<Self>().unwrap();
+                if this.active.as_ref().is_none_or(|a| a.id != id) {
+                    return;
+                }
+                let active = this.active.take().unwrap();
+                let event = this.end_dose(&active, action::Outcome::Completed);
+
                 let mut relay_manager = world
                     .get_resource_mut::<plugins::manager::RelayManager>()
                     .unwrap();
+                Self::set_pump(&mut relay_manager, dose.pump, false);
 
-                relay_manager
-                    .update_state(plugins::manager::relay_module::action::Update {
-                        relay_6: Some(false),
-                        ..plugins::manager::relay_module::action::Update::empty()
-                    })
-                    .unwrap();
-
-                let mut this = world.get_resource_mut::<Self>().unwrap();
-                this.ph_down_state = false;
-
-                let request = action::Update {
-                    ph_down: Some(this.ph_down_state),
-                    ph_up: None,
-                };
-                log::info!("[ph_dosing] <APP> set -> {request}");
+                log::info!("[ph_dosing] <APP> done -> #{id} {dose}");
+                world.spawn(action::Response(Ok(event.to_string().into())).make_mqtt_msg());
             })
             .await;
         });
+
+        this.active = Some(ActiveDose {
+            id,
+            dose,
+            initiator,
+            ph_before,
+            start_time,
+            task: Some(task),
+        });
     }
 
-    fn update_ph_up(rt: Res<TokioTasksRuntime>, this: Res<Self>) {
-        if !this.is_changed() || this.is_added() {
-            return;
+    /// Journals doses once `Config::settle_time` passed, so the pH after the dose is
+    /// representative.
+    fn settle_doses(mut cmd: Commands, mut this: ResMut<Self>) {
+        let now = time::OffsetDateTime::now_utc();
+
+        while this.settling.front().is_some_and(|s| s.due <= now) {
+            let SettlingDose { mut event, .. } = this.settling.pop_front().unwrap();
+            event.ph_after = this.latest_ph;
+
+            log::info!("[ph_dosing] <APP> journal -> {event:?}");
+
+            this.journal.push_back(event.clone());
+            while this.journal.len() > Self::MAX_JOURNAL_LEN {
+                this.journal.pop_front();
+            }
+
+            cmd.spawn(action::Database(event).make_mqtt_msg());
         }
+    }
 
-        if !this.ph_up_state {
-            return;
+    fn set_pump(
+        relay_manager: &mut plugins::manager::RelayManager,
+        pump: Pump,
+        state: bool,
+    ) -> bool {
+        if let Err(e) = relay_manager.update_state(pump.relay_update(state)) {
+            log::warn!(
+                "[ph_dosing] failed to update relay manager, reason:\n{}",
+                e.fmt_error()
+            );
+            return false;
         }
 
-        let dur = this.config.unit_time_user;
+        true
+    }
 
-        rt.spawn_background_task(move |mut ctx| async move {
-            ctx.run_on_main_thread(|ctx| {
-                let mut relay_manager = ctx
-                    .world
-                    .get_resource_mut::<plugins::manager::RelayManager>()
-                    .unwrap();
+    /// Accounts the volume actually dispensed by `active` and queues its journal entry.
+    fn end_dose(&mut self, active: &ActiveDose, outcome: action::Outcome) -> action::DoseEvent {
+        let ActiveDose {
+            id,
+            dose,
+            initiator,
+            ph_before,
+            start_time,
+            ..
+        } = *active;
 
-                relay_manager
-                    .update_state(plugins::manager::relay_module::action::Update {
-                        relay_7: Some(true),
-                        ..plugins::manager::relay_module::action::Update::empty()
-                    })
-                    .unwrap();
-            })
-            .await;
+        let end_time = time::OffsetDateTime::now_utc();
+        let run_time = match outcome {
+            action::Outcome::Completed => dose.run_time,
+            action::Outcome::Cancelled => (end_time - start_time)
+                .clamp(time::Duration::ZERO, dose.run_time.try_into().unwrap())
+                .unsigned_abs(),
+            action::Outcome::Failed => Duration::ZERO,
+        };
+        let volume = match outcome {
+            action::Outcome::Completed => dose.volume,
+            _ => self
+                .config
+                .pump(dose.pump)
+                .flow_rate
+                .map(|rate| rate * run_time.as_secs_f32()),
+        };
 
-            tokio::time::sleep(dur).await;
+        if outcome != action::Outcome::Failed {
+            self.finish_dose(dose.pump, volume);
+        } else if matches!(self.calibration, Some(c) if c.pump == dose.pump) {
+            // a volume measured after a run that never happened would be taken as the flow rate
+            self.calibration = None;
+            log::warn!(
+                "[ph_dosing] <APP> {} calibration aborted, the calibration run failed",
+                dose.pump
+            );
+        }
 
-            ctx.run_on_main_thread(|ctx| {
-                let world = ctx.world;
+        let event = action::DoseEvent {
+            id,
+            pump: dose.pump,
+            initiator,
+            outcome,
+            start_time: start_time.unix_timestamp(),
+            end_time: end_time.unix_timestamp(),
+            intended_run_time: dose.run_time.as_secs_f32(),
+            actual_run_time: run_time.as_secs_f32(),
+            intended_volume: dose.volume,
+            actual_volume: volume,
+            ph_before,
+            ph_after: None,
+        };
 
-                let mut relay_manager = world
-                    .get_resource_mut::<plugins::manager::RelayManager>()
-                    .unwrap();
+        self.settling.push_back(SettlingDose {
+            event: event.clone(),
+            due: end_time + self.config.settle_time,
+        });
 
-                relay_manager
-                    .update_state(plugins::manager::relay_module::action::Update {
-                        relay_7: Some(false),
-                        ..plugins::manager::relay_module::action::Update::empty()
-                    })
-                    .unwrap();
+        event
+    }
 
-                let mut this = world.get_resource_mut::<Self>().unwrap();
-                this.ph_up_state = false;
+    fn finish_dose(&mut self, pump: Pump, volume: Option<f32>) {
+        if matches!(self.calibration, Some(c) if c.pump == pump) {
+            log::info!(
+                "[ph_dosing] <APP> {pump} calibration run done, waiting for measured volume"
+            );
+            return;
+        }
 
-                let request = action::Update {
-                    ph_down: None,
-                    ph_up: Some(this.ph_up_state),
-                };
-                log::info!("[ph_dosing] <APP> set -> {request}");
-            })
-            .await;
-        });
+        let state = self.pump_mut(pump);
+        state.last_volume = volume;
+        state.total_volume += volume.unwrap_or_default();
+
+        if let Some(volume) = volume {
+            self.record_dose(pump, volume);
+        }
     }
 }
 impl mqtt::add_on::action_message::RequestHandler for Manager {
@@ -294,30 +1668,142 @@ impl mqtt::add_on::action_message::RequestHandler for Manager {
     fn update_state(request: Self::Request, state: &mut Self) -> Option<Self::Response> {
         log::info!("[ph_dosing] <USER> set -> {request}");
 
-        state.update_state(request);
-        Some(action::Response(Ok("updated ph dosing state".into())))
+        Some(action::Response(state.update_state(request).map_err(|e| {
+            log::warn!("[ph_dosing] request rejected, reason: {e}");
+            e
+        })))
+    }
+
+    fn handle(request: Self::Request, world: &mut World) -> Option<Self::Response> {
+        world.resource_scope(|world, mut this: Mut<Self>| {
+            let response = <Self as mqtt::add_on::action_message::RequestHandler>::update_state(
+                request, &mut this,
+            );
+
+            if let Some(cancelled) = &this.cancelled {
+                let mut relay_manager = world.resource_mut::<plugins::manager::RelayManager>();
+                Self::set_pump(&mut relay_manager, cancelled.dose.pump, false);
+            }
+
+            response
+        })
+    }
+}
+impl state_file::SaveState for Manager {
+    type State<'de> = State;
+
+    const FILENAME: &str = "ph_dosing_manager";
+
+    fn build(state: Self::State<'_>, this: Option<Self>) -> Self {
+        let mut this = this.unwrap_or_else(|| Self::new(Config::default()));
+        this.safety = state.safety;
+        this.journal = state.journal;
+        this
+    }
+
+    fn save<'de>(&self) -> Self::State<'de> {
+        State {
+            safety: self.safety.clone(),
+            journal: self.journal.clone(),
+        }
+    }
+}
+impl mqtt::add_on::action_message::PublishStatus<action::Status> for Manager {
+    fn query_state() -> impl bevy_internal::prelude::System<In = (), Out = action::Status> {
+        fn func(this: Res<Manager>) -> action::Status {
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+            let pump_status = |pump: Pump| {
+                let PumpState {
+                    last_volume,
+                    total_volume,
+                } = this.pump(pump);
+                let inventory = this.safety.inventory(pump);
+
+                action::PumpStatus {
+                    state: this.active.as_ref().is_some_and(|a| a.dose.pump == pump),
+                    flow_rate: this.config.pump(pump).flow_rate,
+                    last_volume: *last_volume,
+                    total_volume: *total_volume,
+                    hourly_volume: inventory.dispensed_since(now - Manager::HOUR),
+                    daily_volume: inventory.dispensed_since(now - Manager::DAY),
+                    remaining: inventory.remaining,
+                    low_stock: this.is_low_stock(pump),
+                }
+            };
+
+            action::Status {
+                ph_down: pump_status(Pump::PhDown),
+                ph_up: pump_status(Pump::PhUp),
+                calibrating: this.calibration.map(|c| c.pump),
+                lockout: this.safety.lockout,
+                consecutive_doses: this.safety.consecutive_doses,
+            }
+        }
+
+        IntoSystem::into_system(func)
+    }
+}
+impl mqtt::add_on::action_message::PublishStatus<action::QueueStatus> for Manager {
+    fn query_state() -> impl bevy_internal::prelude::System<In = (), Out = action::QueueStatus> {
+        fn func(this: Res<Manager>) -> action::QueueStatus {
+            let dose_status = |id: u32, dose: &Dose, start_time: Option<i64>| action::DoseStatus {
+                id,
+                pump: dose.pump,
+                run_time: dose.run_time.as_secs_f32(),
+                volume: dose.volume,
+                start_time,
+            };
+
+            action::QueueStatus {
+                active: this
+                    .active
+                    .as_ref()
+                    .map(|a| dose_status(a.id, &a.dose, Some(a.start_time.unix_timestamp()))),
+                queued: this
+                    .queue
+                    .iter()
+                    .map(|q| dose_status(q.id, &q.dose, None))
+                    .collect(),
+            }
+        }
+
+        IntoSystem::into_system(func)
     }
 }
 impl ConfigFile for Manager {
     const FILENAME: &'static str = "ph_dosing";
     type Config = Config;
 }
+impl HotReload for Manager {
+    fn apply_config(config: &Config, world: &mut World) -> Result<(), AtomicFixedString> {
+        // queued doses keep the run time they were planned with
+        world.resource_mut::<Manager>().config = *config;
+        Ok(())
+    }
+}
 
-mod action {
+pub mod action {
     use crate::{constants, mqtt, AtomicFixedString};
 
+    use super::{Initiator, Pump};
+
     pub const GROUP: &str = "ph_dosing";
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
     pub struct Update {
         pub ph_down: Option<bool>,
         pub ph_up: Option<bool>,
+        pub ph_down_ml: Option<f32>,
+        pub ph_up_ml: Option<f32>,
+        pub calibrate: Option<Calibrate>,
+        pub refill: Option<Refill>,
+        pub reset_lockout: Option<bool>,
+        pub cancel: Option<Cancel>,
     }
     impl mqtt::add_on::action_message::MessageImpl for Update {
-        const PREFIX: &'static str = constants::mqtt_prefix::REQUEST;
-        const PROJECT: &'static str = constants::project::NAME;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Request;
         const GROUP: &'static str = GROUP;
-        const DEVICE: &'static str = constants::project::DEVICE;
         const QOS: mqtt::Qos = mqtt::Qos::_1;
     }
     impl std::fmt::Display for Update {
@@ -332,17 +1818,262 @@ mod action {
                 disp.entry(&"ph_down", &down);
             };
 
+            if let Some(up) = self.ph_up_ml {
+                disp.entry(&"ph_up_ml", &up);
+            }
+
+            if let Some(down) = self.ph_down_ml {
+                disp.entry(&"ph_down_ml", &down);
+            }
+
+            if let Some(calibrate) = &self.calibrate {
+                disp.entry(&"calibrate", calibrate);
+            }
+
+            if let Some(refill) = &self.refill {
+                disp.entry(&"refill", refill);
+            }
+
+            if let Some(reset) = self.reset_lockout {
+                disp.entry(&"reset_lockout", &reset);
+            }
+
+            if let Some(cancel) = &self.cancel {
+                disp.entry(&"cancel", cancel);
+            }
+
             disp.finish()
         }
     }
 
-    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    /// Guided flow calibration: `start` runs the pump for `Config::calibration_time`,
+    /// `finish` takes the volume (ml) measured from that run.
+    #[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Calibrate {
+        Start { pump: Pump },
+        Finish { volume: f32 },
+        Abort,
+    }
+
+    /// Resets the remaining volume of a container, `volume` defaults to
+    /// `PumpConfig::container_volume`.
+    #[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+    pub struct Refill {
+        pub pump: Pump,
+        pub volume: Option<f32>,
+    }
+
+    /// Cancels queued doses, an active dose is stopped immediately.
+    #[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Cancel {
+        All,
+        Active,
+        Id(u32),
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct Response(pub Result<AtomicFixedString, AtomicFixedString>);
     impl mqtt::add_on::action_message::MessageImpl for Response {
-        const PREFIX: &'static str = constants::mqtt_prefix::RESPONSE;
-        const PROJECT: &'static str = constants::project::NAME;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Response;
+        const GROUP: &'static str = GROUP;
+        const QOS: mqtt::Qos = mqtt::Qos::_1;
+    }
+
+    #[derive(
+        Debug,
+        Clone,
+        Copy,
+        PartialEq,
+        Eq,
+        serde::Serialize,
+        serde::Deserialize,
+        schemars::JsonSchema,
+    )]
+    #[serde(rename_all = "snake_case")]
+    pub enum Outcome {
+        Completed,
+        Cancelled,
+        /// the pump could not be switched on
+        Failed,
+    }
+
+    /// Journal entry of a single dose, times are unix timestamps, run times in
+    /// seconds and volumes in ml.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct DoseEvent {
+        pub id: u32,
+        pub pump: Pump,
+        pub initiator: Initiator,
+        pub outcome: Outcome,
+        pub start_time: i64,
+        pub end_time: i64,
+        pub intended_run_time: f32,
+        pub actual_run_time: f32,
+        pub intended_volume: Option<f32>,
+        pub actual_volume: Option<f32>,
+        pub ph_before: Option<f32>,
+        /// pH after `Config::settle_time`
+        pub ph_after: Option<f32>,
+    }
+    impl std::fmt::Display for DoseEvent {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let outcome = match self.outcome {
+                Outcome::Completed => "completed",
+                Outcome::Cancelled => "cancelled",
+                Outcome::Failed => "failed",
+            };
+
+            write!(
+                f,
+                "#{} {} {outcome}: {:.2}/{:.2} s",
+                self.id, self.pump, self.actual_run_time, self.intended_run_time
+            )?;
+
+            match (self.actual_volume, self.intended_volume) {
+                (Some(actual), Some(intended)) => {
+                    write!(f, ", {actual:.2}/{intended:.2} ml")
+                }
+                (Some(actual), None) => write!(f, ", {actual:.2} ml"),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct Database(pub DoseEvent);
+    impl mqtt::add_on::action_message::MessageImpl for Database {
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Database;
+        const GROUP: &'static str = GROUP;
+        const QOS: mqtt::Qos = mqtt::Qos::_1;
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct PumpStatus {
+        pub state: bool,
+        pub flow_rate: Option<f32>,
+        pub last_volume: Option<f32>,
+        pub total_volume: f32,
+        pub hourly_volume: f32,
+        pub daily_volume: f32,
+        pub remaining: Option<f32>,
+        pub low_stock: bool,
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct Status {
+        pub ph_down: PumpStatus,
+        pub ph_up: PumpStatus,
+        pub calibrating: Option<Pump>,
+        pub lockout: bool,
+        pub consecutive_doses: u32,
+    }
+    impl mqtt::add_on::action_message::MessageImpl for Status {
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Status;
         const GROUP: &'static str = GROUP;
-        const DEVICE: &'static str = constants::project::DEVICE;
         const QOS: mqtt::Qos = mqtt::Qos::_1;
     }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct DoseStatus {
+        pub id: u32,
+        pub pump: Pump,
+        pub run_time: f32,
+        pub volume: Option<f32>,
+        pub start_time: Option<i64>,
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    pub struct QueueStatus {
+        pub active: Option<DoseStatus>,
+        pub queued: Vec<DoseStatus>,
+    }
+    impl mqtt::add_on::action_message::MessageImpl for QueueStatus {
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Status;
+        const GROUP: &'static str = "ph_dosing_queue";
+        const QOS: mqtt::Qos = mqtt::Qos::_1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> action::Update {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn rejected_request_changes_nothing() {
+        let mut manager = Manager::new(Config::default());
+        manager.safety.lockout = true;
+
+        let result = manager.update_state(request(
+            r#"{ "reset_lockout": true, "ph_down": true, "calibrate": { "start": { "pump": "ph_down" } } }"#,
+        ));
+
+        assert!(result.is_err());
+        assert!(manager.queue.is_empty());
+        assert!(manager.calibration.is_none());
+        assert!(manager.safety.lockout);
+    }
+
+    #[test]
+    fn request_checks_see_earlier_parts() {
+        let mut manager = Manager::new(Config::default());
+        manager.safety.lockout = true;
+
+        manager
+            .update_state(request(r#"{ "reset_lockout": true, "ph_up": true }"#))
+            .unwrap();
+        assert!(!manager.safety.lockout);
+        assert_eq!(manager.queue.len(), 1);
+
+        // the queued dose is cancelled before the pump is checked for calibration
+        manager
+            .update_state(request(
+                r#"{ "cancel": "all", "calibrate": { "start": { "pump": "ph_up" } } }"#,
+            ))
+            .unwrap();
+        assert_eq!(manager.queue.len(), 1);
+        assert_eq!(manager.queue[0].initiator, Initiator::Calibration);
+        assert!(manager.calibration.is_some());
+    }
+
+    #[test]
+    fn failed_calibration_run_aborts_calibration() {
+        let mut manager = Manager::new(Config::default());
+
+        manager
+            .update_state(request(
+                r#"{ "calibrate": { "start": { "pump": "ph_down" } } }"#,
+            ))
+            .unwrap();
+        let QueuedDose {
+            id,
+            dose,
+            initiator,
+        } = manager.queue.pop_front().unwrap();
+
+        manager.end_dose(
+            &ActiveDose {
+                id,
+                dose,
+                initiator,
+                ph_before: None,
+                start_time: time::OffsetDateTime::now_utc(),
+                task: None,
+            },
+            action::Outcome::Failed,
+        );
+
+        assert!(manager.calibration.is_none());
+        assert!(manager
+            .update_state(request(
+                r#"{ "calibrate": { "finish": { "volume": 10.0 } } }"#
+            ))
+            .is_err());
+        assert!(manager.config.ph_down_pump.flow_rate.is_none());
+    }
 }
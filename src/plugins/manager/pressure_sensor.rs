@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use bevy_tokio_tasks::TokioTasksRuntime;
+
+use crate::{log, plugins::manager::modbus, AtomicFixedString};
+
+/// Line pressure transducer.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, schemars::JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[schemars(rename = "PressureSensorConfig")]
+pub enum Config {
+    /// analog input module (e.g. 4-20 mA ADC) holding the raw reading in a register,
+    /// mapped linearly from `raw_min..raw_max` to `pressure_min..pressure_max` (bar)
+    Modbus {
+        path: AtomicFixedString,
+        baud_rate: u32,
+        slave: u8,
+        register: u16,
+        raw_min: u16,
+        raw_max: u16,
+        #[serde(default)]
+        pressure_min: f32,
+        pressure_max: f32,
+    },
+    /// pressure rising with the pump and falling with every open zone, stands in for a
+    /// missing sensor
+    Simulated {
+        /// bar/s while the pump runs
+        pump_rate: f32,
+        /// bar/s per spraying zone
+        zone_rate: f32,
+    },
+}
+
+#[derive(Debug)]
+pub enum Sensor {
+    Modbus {
+        rx: tokio::sync::watch::Receiver<Option<f32>>,
+        _task: modbus::Task,
+    },
+    Simulated {
+        pump_rate: f32,
+        zone_rate: f32,
+        pressure: f32,
+    },
+}
+impl Sensor {
+    const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+    pub fn new(config: &Config, rt: &TokioTasksRuntime) -> Result<Self, AtomicFixedString> {
+        let (path, baud_rate, slave, register, raw_min, raw_max, pressure_min, pressure_max) =
+            match *config {
+                Config::Modbus {
+                    ref path,
+                    baud_rate,
+                    slave,
+                    register,
+                    raw_min,
+                    raw_max,
+                    pressure_min,
+                    pressure_max,
+                } => (
+                    path,
+                    baud_rate,
+                    slave,
+                    register,
+                    raw_min,
+                    raw_max,
+                    pressure_min,
+                    pressure_max,
+                ),
+                Config::Simulated {
+                    pump_rate,
+                    zone_rate,
+                } => {
+                    return Ok(Self::Simulated {
+                        pump_rate,
+                        zone_rate,
+                        pressure: 0.0,
+                    })
+                }
+            };
+
+        if raw_max <= raw_min {
+            return Err(format!("invalid pressure sensor range: {raw_min}..{raw_max}").into());
+        }
+
+        let (tx, rx) = tokio::sync::watch::channel(None);
+
+        let bus = modbus::Bus::open(path, baud_rate, rt)?;
+
+        let task = rt.spawn_background_task(move |_| async move {
+            loop {
+                tokio::time::sleep(Self::POLL_INTERVAL).await;
+
+                let raw = match bus.read_holding_registers(slave, register, 1).await {
+                    Ok(data) => data[0],
+                    Err(e) => {
+                        log::warn!("[pressure_sensor] {e}");
+                        tx.send_replace(None);
+                        continue;
+                    }
+                };
+
+                // a current loop below its live zero means a broken wire
+                if raw < raw_min {
+                    log::warn!("[pressure_sensor] reading below range: {raw}");
+                    tx.send_replace(None);
+                    continue;
+                }
+
+                let pressure = pressure_min
+                    + (pressure_max - pressure_min) * (raw - raw_min) as f32
+                        / (raw_max - raw_min) as f32;
+                log::trace!("[pressure_sensor] pressure -> {pressure:.2}");
+
+                if tx.send(Some(pressure)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self::Modbus {
+            rx,
+            _task: task.into(),
+        })
+    }
+
+    /// Latest pressure in bar.
+    pub fn pressure(&self) -> Option<f32> {
+        match self {
+            Sensor::Modbus { rx, .. } => *rx.borrow(),
+            Sensor::Simulated { pressure, .. } => Some(*pressure),
+        }
+    }
+
+    /// Advances the simulated pressure by `dt` seconds, real sensors ignore it.
+    pub fn simulate(&mut self, pump: bool, open_zones: usize, dt: f32) {
+        if let Sensor::Simulated {
+            pump_rate,
+            zone_rate,
+            pressure,
+        } = self
+        {
+            let rate = if pump { *pump_rate } else { 0.0 } - *zone_rate * open_zones as f32;
+            *pressure = (*pressure + rate * dt).max(0.0);
+        }
+    }
+}
@@ -6,7 +6,8 @@ use relay::Relay;
 use crate::{
     helper::{ErrorLogFormat, ToBytes},
     log,
-    plugins::mqtt,
+    plugins::mqtt::{self, message::MessageInfo},
+    AtomicFixedString,
 };
 
 pub struct Plugin;
@@ -32,6 +33,57 @@ mod gpio {
     pub const GROWLIGHT: u8 = 27;
 }
 
+/// Addressable relay channel, used by managers with configurable relay wiring.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+pub enum Channel {
+    #[serde(rename = "relay_1")]
+    Relay1,
+    #[serde(rename = "relay_2")]
+    Relay2,
+    #[serde(rename = "relay_3")]
+    Relay3,
+    #[serde(rename = "relay_6")]
+    Relay6,
+    #[serde(rename = "relay_7")]
+    Relay7,
+    #[serde(rename = "relay_8")]
+    Relay8,
+}
+impl Channel {
+    pub fn update(self, state: bool) -> action::Update {
+        let state = Some(state);
+
+        match self {
+            Channel::Relay1 => action::Update {
+                relay_1: state,
+                ..action::Update::empty()
+            },
+            Channel::Relay2 => action::Update {
+                relay_2: state,
+                ..action::Update::empty()
+            },
+            Channel::Relay3 => action::Update {
+                relay_3: state,
+                ..action::Update::empty()
+            },
+            Channel::Relay6 => action::Update {
+                relay_6: state,
+                ..action::Update::empty()
+            },
+            Channel::Relay7 => action::Update {
+                relay_7: state,
+                ..action::Update::empty()
+            },
+            Channel::Relay8 => action::Update {
+                relay_8: state,
+                ..action::Update::empty()
+            },
+        }
+    }
+}
+
 #[derive(Debug, Resource)]
 pub struct Manager {
     relay_1: relay::NO<rppal::gpio::OutputPin>,
@@ -96,17 +148,17 @@ impl Default for Manager {
 }
 impl Manager {
     pub fn start(mut cmd: Commands) {
-        use mqtt::add_on::home_assistant::Device;
+        use mqtt::add_on::home_assistant::{self, Device};
 
         #[derive(serde::Serialize)]
         struct HAConfig {
             name: &'static str,
-            unique_id: &'static str,
-            command_topic: &'static str,
+            unique_id: AtomicFixedString,
+            command_topic: AtomicFixedString,
             command_template: &'static str,
             payload_on: bool,
             payload_off: bool,
-            state_topic: &'static str,
+            state_topic: AtomicFixedString,
             value_template: &'static str,
             state_on: bool,
             state_off: bool,
@@ -114,21 +166,21 @@ impl Manager {
         }
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/switch/relay_1/relay_module/config".into(),
+            topic: home_assistant::topic("switch", "relay_module", "relay_1"),
             payload: {
                 serde_json::to_value(HAConfig {
                     name: "Relay 1 (Switch 1)",
-                    unique_id: "triponics-relay-module_1",
-                    command_topic: "request/triponics/relay_module/0",
+                    unique_id: home_assistant::unique_id("relay-module_1"),
+                    command_topic: action::Update::topic(),
                     command_template: "{ \"relay_1\" : {{value | lower}} }",
                     payload_on: true,
                     payload_off: false,
-                    state_topic: "status/triponics/relay_module/0",
+                    state_topic: action::RelayStatus::topic(),
                     value_template: "{{ value_json.relay_1 }}",
                     state_on: true,
                     state_off: false,
                     device: Device {
-                        identifiers: &["triponics-relay-module"],
+                        identifiers: &["relay-module"],
                         name: "Relay Module",
                     },
                 })
@@ -140,21 +192,21 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/switch/relay_2/relay_module/config".into(),
+            topic: home_assistant::topic("switch", "relay_module", "relay_2"),
             payload: {
                 serde_json::to_value(HAConfig {
                     name: "Relay 2 (Switch 2)",
-                    unique_id: "triponics-relay-module_2",
-                    command_topic: "request/triponics/relay_module/0",
+                    unique_id: home_assistant::unique_id("relay-module_2"),
+                    command_topic: action::Update::topic(),
                     command_template: "{ \"relay_2\" : {{value | lower}} }",
                     payload_on: true,
                     payload_off: false,
-                    state_topic: "status/triponics/relay_module/0",
+                    state_topic: action::RelayStatus::topic(),
                     value_template: "{{ value_json.relay_2 }}",
                     state_on: true,
                     state_off: false,
                     device: Device {
-                        identifiers: &["triponics-relay-module"],
+                        identifiers: &["relay-module"],
                         name: "Relay Module",
                     },
                 })
@@ -166,21 +218,21 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/switch/relay_3/relay_module/config".into(),
+            topic: home_assistant::topic("switch", "relay_module", "relay_3"),
             payload: {
                 serde_json::to_value(HAConfig {
                     name: "Relay 3 (Switch 3)",
-                    unique_id: "triponics-relay-module_3",
-                    command_topic: "request/triponics/relay_module/0",
+                    unique_id: home_assistant::unique_id("relay-module_3"),
+                    command_topic: action::Update::topic(),
                     command_template: "{ \"relay_3\" : {{value | lower}} }",
                     payload_on: true,
                     payload_off: false,
-                    state_topic: "status/triponics/relay_module/0",
+                    state_topic: action::RelayStatus::topic(),
                     value_template: "{{ value_json.relay_3 }}",
                     state_on: true,
                     state_off: false,
                     device: Device {
-                        identifiers: &["triponics-relay-module"],
+                        identifiers: &["relay-module"],
                         name: "Relay Module",
                     },
                 })
@@ -192,21 +244,21 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/switch/relay_6/relay_module/config".into(),
+            topic: home_assistant::topic("switch", "relay_module", "relay_6"),
             payload: {
                 serde_json::to_value(HAConfig {
                     name: "Relay 6 (Pump pH Down)",
-                    unique_id: "triponics-relay-module_6",
-                    command_topic: "request/triponics/relay_module/0",
+                    unique_id: home_assistant::unique_id("relay-module_6"),
+                    command_topic: action::Update::topic(),
                     command_template: "{ \"relay_6\" : {{value | lower}} }",
                     payload_on: true,
                     payload_off: false,
-                    state_topic: "status/triponics/relay_module/0",
+                    state_topic: action::RelayStatus::topic(),
                     value_template: "{{ value_json.relay_6 }}",
                     state_on: true,
                     state_off: false,
                     device: Device {
-                        identifiers: &["triponics-relay-module"],
+                        identifiers: &["relay-module"],
                         name: "Relay Module",
                     },
                 })
@@ -218,21 +270,21 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/switch/relay_7/relay_module/config".into(),
+            topic: home_assistant::topic("switch", "relay_module", "relay_7"),
             payload: {
                 serde_json::to_value(HAConfig {
                     name: "Relay 7 (Pump pH Up)",
-                    unique_id: "triponics-relay-module_7",
-                    command_topic: "request/triponics/relay_module/0",
+                    unique_id: home_assistant::unique_id("relay-module_7"),
+                    command_topic: action::Update::topic(),
                     command_template: "{ \"relay_7\" : {{value | lower}} }",
                     payload_on: true,
                     payload_off: false,
-                    state_topic: "status/triponics/relay_module/0",
+                    state_topic: action::RelayStatus::topic(),
                     value_template: "{{ value_json.relay_7 }}",
                     state_on: true,
                     state_off: false,
                     device: Device {
-                        identifiers: &["triponics-relay-module"],
+                        identifiers: &["relay-module"],
                         name: "Relay Module",
                     },
                 })
@@ -244,21 +296,21 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/switch/relay_8/relay_module/config".into(),
+            topic: home_assistant::topic("switch", "relay_module", "relay_8"),
             payload: {
                 serde_json::to_value(HAConfig {
                     name: "Relay 8 (Growlight)",
-                    unique_id: "triponics-relay-module_8",
-                    command_topic: "request/triponics/relay_module/0",
+                    unique_id: home_assistant::unique_id("relay-module_8"),
+                    command_topic: action::Update::topic(),
                     command_template: "{ \"relay_8\" : {{value | lower}} }",
                     payload_on: true,
                     payload_off: false,
-                    state_topic: "status/triponics/relay_module/0",
+                    state_topic: action::RelayStatus::topic(),
                     value_template: "{{ value_json.relay_8 }}",
                     state_on: true,
                     state_off: false,
                     device: Device {
-                        identifiers: &["triponics-relay-module"],
+                        identifiers: &["relay-module"],
                         name: "Relay Module",
                     },
                 })
@@ -345,7 +397,7 @@ pub mod action {
     const GROUP: &str = "relay_module";
     const QOS: mqtt::Qos = mqtt::Qos::_1;
 
-    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct Update {
         pub relay_1: Option<bool>,
         pub relay_2: Option<bool>,
@@ -367,10 +419,8 @@ pub mod action {
         }
     }
     impl mqtt::add_on::action_message::MessageImpl for Update {
-        const PREFIX: &'static str = constants::mqtt_prefix::REQUEST;
-        const PROJECT: &'static str = constants::project::NAME;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Request;
         const GROUP: &'static str = GROUP;
-        const DEVICE: &'static str = constants::project::DEVICE;
         const QOS: mqtt::Qos = QOS;
     }
     impl Default for Update {
@@ -425,7 +475,7 @@ pub mod action {
         }
     }
 
-    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct RelayStatus {
         pub relay_1: bool,
         pub relay_2: bool,
@@ -435,20 +485,16 @@ pub mod action {
         pub relay_8: bool,
     }
     impl mqtt::add_on::action_message::MessageImpl for RelayStatus {
-        const PREFIX: &'static str = constants::mqtt_prefix::STATUS;
-        const PROJECT: &'static str = constants::project::NAME;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Status;
         const GROUP: &'static str = GROUP;
-        const DEVICE: &'static str = constants::project::DEVICE;
         const QOS: mqtt::Qos = QOS;
     }
 
-    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct MqttResponse(pub Result<AtomicFixedString, AtomicFixedString>);
     impl mqtt::add_on::action_message::MessageImpl for MqttResponse {
-        const PREFIX: &'static str = constants::mqtt_prefix::RESPONSE;
-        const PROJECT: &'static str = constants::project::NAME;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Response;
         const GROUP: &'static str = GROUP;
-        const DEVICE: &'static str = constants::project::DEVICE;
         const QOS: mqtt::Qos = QOS;
     }
 }
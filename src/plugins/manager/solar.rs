@@ -0,0 +1,66 @@
+//! Offline sunrise and sunset, following the sunrise equation used by NOAA, accurate to
+//! about a minute.
+
+const J2000: f64 = 2451545.0;
+const UNIX_EPOCH_JULIAN: f64 = 2440587.5;
+const EARTH_TILT: f64 = 23.4397;
+/// sun center below the horizon at sunrise, includes refraction and the solar disc
+const SUNRISE_ALTITUDE: f64 = -0.833;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SunTimes {
+    pub noon: time::OffsetDateTime,
+    /// `None` during polar day and polar night
+    pub sunrise: Option<time::OffsetDateTime>,
+    pub sunset: Option<time::OffsetDateTime>,
+    /// the sun stays above the horizon for the whole day
+    pub polar_day: bool,
+}
+impl SunTimes {
+    /// `latitude` is north positive, `longitude` is east positive, times are returned in
+    /// `offset`.
+    pub fn on(date: time::Date, latitude: f64, longitude: f64, offset: time::UtcOffset) -> Self {
+        let days = date.to_julian_day() as f64 - J2000 + 0.0008;
+        let mean_noon = days - longitude / 360.0;
+
+        let anomaly = (357.5291 + 0.98560028 * mean_noon).rem_euclid(360.0);
+        let m = anomaly.to_radians();
+        let center = 1.9148 * m.sin() + 0.02 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+        let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372)
+            .rem_euclid(360.0)
+            .to_radians();
+
+        let transit =
+            J2000 + mean_noon + 0.0053 * m.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+        let declination = (ecliptic_longitude.sin() * EARTH_TILT.to_radians().sin()).asin();
+        let latitude = latitude.to_radians();
+        let cos_hour_angle = (SUNRISE_ALTITUDE.to_radians().sin()
+            - latitude.sin() * declination.sin())
+            / (latitude.cos() * declination.cos());
+
+        let to_datetime = |julian: f64| {
+            let unix = ((julian - UNIX_EPOCH_JULIAN) * 86400.0).round() as i64;
+            time::OffsetDateTime::from_unix_timestamp(unix)
+                .unwrap()
+                .to_offset(offset)
+        };
+
+        let (sunrise, sunset) = if (-1.0..=1.0).contains(&cos_hour_angle) {
+            let hour_angle = cos_hour_angle.acos().to_degrees() / 360.0;
+            (
+                Some(to_datetime(transit - hour_angle)),
+                Some(to_datetime(transit + hour_angle)),
+            )
+        } else {
+            (None, None)
+        };
+
+        Self {
+            noon: to_datetime(transit),
+            sunrise,
+            sunset,
+            polar_day: cos_hour_angle < -1.0,
+        }
+    }
+}
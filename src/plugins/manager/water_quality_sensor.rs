@@ -4,9 +4,14 @@ use bevy_app::{Startup, Update};
 use bevy_ecs::system::{Commands, IntoSystem, Res, ResMut, Resource};
 use bevy_internal::time::common_conditions::on_timer;
 use bevy_tokio_tasks::TokioTasksRuntime;
-use tokio_modbus::prelude::*;
 
-use crate::{helper::ToBytes, log, mqtt};
+use crate::{
+    helper::ToBytes,
+    log,
+    mqtt::{self, message::MessageInfo},
+    plugins::manager::modbus,
+    AtomicFixedString,
+};
 
 pub struct Plugin;
 impl bevy_app::Plugin for Plugin {
@@ -33,6 +38,8 @@ pub struct Manager {
     data_sender: Option<tokio::sync::watch::Sender<SensorData>>,
     sensor_data_rx: tokio::sync::watch::Receiver<SensorData>,
     latest_data: SensorData,
+    /// time of the latest reading, `None` before the first one
+    sampled_at: Option<time::OffsetDateTime>,
 }
 impl Default for Manager {
     fn default() -> Self {
@@ -42,10 +49,13 @@ impl Default for Manager {
             data_sender: Some(tx),
             sensor_data_rx,
             latest_data: Default::default(),
+            sampled_at: None,
         }
     }
 }
 impl Manager {
+    const PORT: &str = "/dev/serial0";
+    const BAUD_RATE: u32 = 9600;
     const SENSOR_ADDR: u8 = 0x1;
     const SENSOR_DATA_ADDR: u16 = 0x0;
     const SENSOR_DATA_LEN: u16 = 3;
@@ -54,33 +64,34 @@ impl Manager {
         self.latest_data
     }
 
+    /// Time of the latest reading, failed reads keep the previous data.
+    pub fn sampled_at(&self) -> Option<time::OffsetDateTime> {
+        self.sampled_at
+    }
+
     fn start(rt: ResMut<TokioTasksRuntime>, mut manager: ResMut<Manager>) {
         let tx = manager.data_sender.take().unwrap();
 
-        rt.spawn_background_task(move |_| async move {
-            let mut modbus_ctx = rtu::attach_slave(
-                tokio_serial::SerialStream::open(
-                    &tokio_serial::new("/dev/serial0", 9600), //
-                )
-                .unwrap(),
-                Slave(Manager::SENSOR_ADDR),
-            );
+        let bus = match modbus::Bus::open(&Self::PORT.into(), Self::BAUD_RATE, &rt) {
+            Ok(bus) => bus,
+            Err(e) => {
+                log::error!("[water_quality_sensor] sensor disabled, reason: {e}");
+                return;
+            }
+        };
 
+        rt.spawn_background_task(move |_| async move {
             loop {
                 tokio::time::sleep(Duration::from_secs(1)).await;
 
-                let modbus_result = match modbus_ctx
-                    .read_holding_registers(Manager::SENSOR_DATA_ADDR, Manager::SENSOR_DATA_LEN)
+                match bus
+                    .read_holding_registers(
+                        Manager::SENSOR_ADDR,
+                        Manager::SENSOR_DATA_ADDR,
+                        Manager::SENSOR_DATA_LEN,
+                    )
                     .await
                 {
-                    Ok(o) => o,
-                    Err(e) => {
-                        log::warn!("{e}");
-                        continue;
-                    }
-                };
-
-                match modbus_result {
                     Ok(data) => {
                         let new_data = SensorData {
                             ph: SensorData::ph_from_raw(data[0]),
@@ -100,25 +111,25 @@ impl Manager {
     }
 
     fn register_home_assistant(mut cmd: Commands) {
-        use mqtt::add_on::home_assistant::Device;
+        use mqtt::add_on::home_assistant::{self, Device};
 
         #[derive(serde::Serialize)]
         struct Config {
             name: &'static str,
             icon: &'static str,
-            state_topic: &'static str,
+            state_topic: AtomicFixedString,
             value_template: &'static str,
             unit_of_measurement: Option<&'static str>,
             device: Device,
         }
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/time/water_quality_sensor/config".into(),
+            topic: home_assistant::topic("sensor", "water_quality_sensor", "time"),
             payload: {
                 serde_json::to_value(Config {
                     name: "Sampled Time",
                     icon: "mdi:clock",
-                    state_topic: "status/triponics/water_quality_sensor/0",
+                    state_topic: action::MqttStatus::topic(),
                     value_template:
                         "{{ (as_datetime(value_json.timestamp) | as_local | string )[:19] }}",
                     unit_of_measurement: None,
@@ -135,12 +146,12 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/ph/water_quality_sensor/config".into(),
+            topic: home_assistant::topic("sensor", "water_quality_sensor", "ph"),
             payload: {
                 serde_json::to_value(Config {
                     name: "Water pH",
                     icon: "mdi:flask-round-bottom",
-                    state_topic: "status/triponics/water_quality_sensor/0",
+                    state_topic: action::MqttStatus::topic(),
                     value_template: "{{ value_json.ph }}",
                     unit_of_measurement: Some("pH"),
                     device: Device {
@@ -156,12 +167,12 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/ec/water_quality_sensor/config".into(),
+            topic: home_assistant::topic("sensor", "water_quality_sensor", "ec"),
             payload: {
                 serde_json::to_value(Config {
                     name: "Water EC",
                     icon: "mdi:lightning-bolt-outline",
-                    state_topic: "status/triponics/water_quality_sensor/0",
+                    state_topic: action::MqttStatus::topic(),
                     value_template: "{{ value_json.ec }}",
                     unit_of_measurement: Some("mS/cm"),
                     device: Device {
@@ -177,12 +188,12 @@ impl Manager {
         });
 
         cmd.spawn(mqtt::message::Message {
-            topic: "homeassistant/sensor/temperature/water_quality_sensor/config".into(),
+            topic: home_assistant::topic("sensor", "water_quality_sensor", "temperature"),
             payload: {
                 serde_json::to_value(Config {
                     name: "Water Temperature",
                     icon: "mdi:water-thermometer",
-                    state_topic: "status/triponics/water_quality_sensor/0",
+                    state_topic: action::MqttStatus::topic(),
                     value_template: "{{ value_json.temp }}",
                     unit_of_measurement: Some("°C"),
                     device: Device {
@@ -199,8 +210,11 @@ impl Manager {
     }
 
     fn update(mut manager: ResMut<Manager>) {
-        let data = *manager.sensor_data_rx.borrow_and_update();
-        manager.latest_data = data;
+        if manager.sensor_data_rx.has_changed().unwrap_or(false) {
+            let data = *manager.sensor_data_rx.borrow_and_update();
+            manager.latest_data = data;
+            manager.sampled_at = Some(time::OffsetDateTime::now_utc());
+        }
     }
 }
 impl mqtt::add_on::action_message::PublishStatus<action::Database> for Manager {
@@ -233,6 +247,14 @@ pub struct SensorData {
     temp: f32,
 }
 impl SensorData {
+    pub fn ph(&self) -> f32 {
+        self.ph
+    }
+
+    pub fn ec(&self) -> f32 {
+        self.ec
+    }
+
     fn ph_from_raw(raw_data: u16) -> f32 {
         raw_data as f32 / 100.0
     }
@@ -276,13 +298,13 @@ impl SensorDataAwg {
     }
 }
 
-mod action {
+pub mod action {
     use crate::{constants, mqtt};
 
     pub const GROUP: &str = "water_quality_sensor";
     pub const QOS: mqtt::Qos = mqtt::Qos::_1;
 
-    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct State {
         timestamp: i64,
         ph: f32,
@@ -302,23 +324,19 @@ mod action {
         }
     }
 
-    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct Database(pub State);
     impl mqtt::add_on::action_message::MessageImpl for Database {
-        const PREFIX: &'static str = constants::mqtt_prefix::DATABASE;
-        const PROJECT: &'static str = constants::project::NAME;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Database;
         const GROUP: &'static str = GROUP;
-        const DEVICE: &'static str = constants::project::DEVICE;
         const QOS: mqtt::Qos = QOS;
     }
 
-    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
     pub struct MqttStatus(pub State);
     impl mqtt::add_on::action_message::MessageImpl for MqttStatus {
-        const PREFIX: &'static str = constants::mqtt_prefix::STATUS;
-        const PROJECT: &'static str = constants::project::NAME;
+        const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Status;
         const GROUP: &'static str = GROUP;
-        const DEVICE: &'static str = constants::project::DEVICE;
         const QOS: mqtt::Qos = QOS;
     }
 }
@@ -2,15 +2,18 @@ use std::{marker::PhantomData, sync::RwLock};
 
 use bevy_app::{Plugin, Startup, Update};
 use bevy_ecs::{
+    change_detection::Mut,
     event::{Event, EventReader},
     schedule::{Condition, IntoSystemConfigs, SystemConfigs},
-    system::{Commands, In, IntoSystem, ResMut, Resource, RunSystemOnce, System},
+    system::{Commands, In, IntoSystem, ResMut, Resource, RunSystemOnce, System, SystemState},
     world::World,
 };
+use bevy_tokio_tasks::TokioTasksRuntime;
 
 #[allow(unused_imports)]
 use crate::log;
 use crate::{
+    config::{history, ValidationError},
     plugins::mqtt::{
         self,
         message::{self, MessageInfo},
@@ -21,12 +24,10 @@ use crate::{
 
 pub trait MessageImpl
 where
-    Self: MessageInfo,
+    Self: MessageInfo + schemars::JsonSchema,
 {
-    const PREFIX: &'static str;
-    const PROJECT: &'static str;
+    const PREFIX: crate::constants::MqttPrefix;
     const GROUP: &'static str;
-    const DEVICE: &'static str;
     const QOS: Qos;
 }
 impl<M> MessageInfo for M
@@ -34,7 +35,7 @@ where
     M: MessageImpl,
 {
     fn topic() -> AtomicFixedString {
-        format!("{}/{}/{}/{}", M::PREFIX, M::PROJECT, M::GROUP, M::DEVICE).into()
+        crate::namespace().topic(M::PREFIX, M::GROUP)
     }
 
     fn qos() -> Qos {
@@ -65,11 +66,17 @@ where
 
         None
     }
+
+    /// Handles a request with access to the whole world, for requests that must act on
+    /// other resources right away. Defaults to [`RequestHandler::update_state`].
+    fn handle(request: Self::Request, world: &mut World) -> Option<Self::Response> {
+        world.resource_scope(|_, mut state: Mut<Self>| Self::update_state(request, &mut state))
+    }
 }
 
 pub struct ConfigMessage<T, Cfg>
 where
-    T: crate::config::ConfigFile + Send + Sync + 'static,
+    T: crate::config::HotReload + Send + Sync + 'static,
     Cfg: MessageImpl + Send + Sync + 'static,
 {
     _t: PhantomData<T>,
@@ -77,8 +84,8 @@ where
 }
 impl<T, Cfg> ConfigMessage<T, Cfg>
 where
-    T: crate::config::ConfigFile<Config = Cfg> + Send + Sync + 'static,
-    Cfg: MessageImpl + std::fmt::Debug + Send + Sync + 'static,
+    T: crate::config::HotReload<Config = Cfg> + Send + Sync + 'static,
+    Cfg: MessageImpl + crate::config::Validate + Clone + std::fmt::Debug + Send + Sync + 'static,
 {
     pub fn new() -> Self {
         Self {
@@ -92,6 +99,8 @@ where
             mqtt::message::Subscriptions::new()
                 .with_msg::<local::LoadCfgMsg<Cfg>>()
                 .with_msg::<local::SaveCfgMsg<Cfg>>()
+                .with_msg::<local::HistoryCfgMsg<Cfg>>()
+                .with_msg::<local::RollbackCfgMsg<Cfg>>()
                 .finalize(),
         );
     }
@@ -110,16 +119,91 @@ where
         ev.clear();
     }
 
-    fn on_save_request(mut ev: EventReader<mqtt::event::IncomingMessage>) {
+    fn on_save_request(
+        mut cmd: Commands,
+        mut reload: ResMut<local::Reload<Cfg>>,
+        mut ev: EventReader<mqtt::event::IncomingMessage>,
+    ) {
         while let Some(incoming) = ev.read().next() {
-            if let Some(local::SaveCfgMsg(cfg)) = incoming.get::<local::SaveCfgMsg<Cfg>>() {
-                log::info!(
-                    "mqtt received new config:\npath: {}\nconfig: {}\n",
-                    T::config_filepath().to_str().unwrap(),
-                    serde_json::to_string_pretty(&cfg).unwrap()
+            if let Some(payload) = incoming.payload::<local::SaveCfgMsg<Cfg>>() {
+                // published configs carry no version, they are always current
+                let saved = T::parse_config(payload, T::config_version()).and_then(|(cfg, _)| {
+                    log::info!(
+                        "mqtt received new config:\npath: {}\nconfig: {}\n",
+                        T::config_filepath().to_str().unwrap(),
+                        serde_json::to_string_pretty(&cfg).unwrap()
+                    );
+
+                    T::save_config(cfg.clone(), history::Source::Mqtt)
+                        .map(|()| cfg)
+                        .map_err(local::save_error)
+                });
+
+                match saved {
+                    Ok(cfg) => reload.pending = Some(cfg),
+                    Err(errors) => {
+                        for e in errors.iter() {
+                            log::warn!("[config] rejected {} -> {e}", Cfg::GROUP);
+                        }
+                        cmd.spawn(local::ConfigResponse::<Cfg>::new(Err(errors)).make_mqtt_msg());
+                    }
+                }
+                break;
+            }
+        }
+
+        ev.clear();
+    }
+
+    fn on_history_request(mut cmd: Commands, mut ev: EventReader<mqtt::event::IncomingMessage>) {
+        while let Some(incoming) = ev.read().next() {
+            if incoming.payload::<local::HistoryCfgMsg<Cfg>>().is_some() {
+                let entries = history::load(T::FILENAME);
+                log::debug!(
+                    "mqtt send {} config history ({} entries)",
+                    Cfg::GROUP,
+                    entries.len()
                 );
-                if let Err(e) = T::save_config(cfg) {
-                    log::warn!("failed to save new config, reason: {e}");
+                cmd.spawn(local::ConfigHistory::<Cfg>::new(entries).make_mqtt_msg());
+                break;
+            }
+        }
+
+        ev.clear();
+    }
+
+    fn on_rollback_request(
+        mut cmd: Commands,
+        mut reload: ResMut<local::Reload<Cfg>>,
+        mut ev: EventReader<mqtt::event::IncomingMessage>,
+    ) {
+        while let Some(incoming) = ev.read().next() {
+            if let Some(local::RollbackCfgMsg { id, .. }) =
+                incoming.get::<local::RollbackCfgMsg<Cfg>>()
+            {
+                // entries keep their version, older ones are migrated again
+                let restored = history::find(T::FILENAME, id)
+                    .ok_or_else(|| vec![ValidationError::new("id", format!("no entry {id}"))])
+                    .and_then(|entry| {
+                        T::parse_config(&serde_json::to_vec(&entry.config).unwrap(), 0)
+                    })
+                    .and_then(|(cfg, _)| {
+                        T::save_config(cfg.clone(), history::Source::Rollback { id })
+                            .map(|()| cfg)
+                            .map_err(local::save_error)
+                    });
+
+                match restored {
+                    Ok(cfg) => {
+                        log::info!("[config] <USER> {} rolled back -> {id}", Cfg::GROUP);
+                        reload.pending = Some(cfg);
+                    }
+                    Err(errors) => {
+                        for e in errors.iter() {
+                            log::warn!("[config] rollback of {} rejected -> {e}", Cfg::GROUP);
+                        }
+                        cmd.spawn(local::ConfigResponse::<Cfg>::new(Err(errors)).make_mqtt_msg());
+                    }
                 }
                 break;
             }
@@ -127,12 +211,96 @@ where
 
         ev.clear();
     }
+
+    fn setup_reload(world: &mut World) {
+        let watch = match crate::config::watch::<T>(world.resource::<TokioTasksRuntime>()) {
+            Ok(rx) => Some(rx),
+            Err(e) => {
+                log::warn!(
+                    "[config] edits of {} need a restart, reason: {e}",
+                    T::config_filepath().to_str().unwrap()
+                );
+                None
+            }
+        };
+        let applied = T::load_config()
+            .map(|cfg| serde_json::to_string(&cfg).unwrap())
+            .unwrap_or_default();
+
+        world.insert_resource(local::Reload::<Cfg> {
+            pending: None,
+            applied,
+            watch,
+        });
+    }
+
+    /// Applies a config saved over MQTT or edited on disk to the running app and
+    /// publishes the effective config.
+    fn reload(world: &mut World) {
+        let (pending, edited) = {
+            let mut reload = world.resource_mut::<local::Reload<Cfg>>();
+            let edited = reload
+                .watch
+                .as_ref()
+                .is_some_and(|rx| rx.try_iter().count() > 0);
+            (reload.pending.take(), edited)
+        };
+
+        let requested = pending.is_some();
+        let respond = |world: &mut World, result| {
+            if requested {
+                world.spawn(local::ConfigResponse::<Cfg>::new(result).make_mqtt_msg());
+            }
+        };
+
+        let cfg = match pending {
+            Some(cfg) => cfg,
+            None if edited => match T::load_config() {
+                Ok(cfg) => cfg,
+                Err(e) => {
+                    log::warn!("[config] ignored edited config, reason: {e}");
+                    return;
+                }
+            },
+            None => return,
+        };
+
+        // saving over MQTT also shows up as a file edit
+        let json = serde_json::to_string(&cfg).unwrap();
+        if world.resource::<local::Reload<Cfg>>().applied == json {
+            respond(world, Ok("config unchanged".into()));
+            return;
+        }
+
+        let path = T::config_filepath();
+        match T::apply_config(&cfg, world) {
+            Ok(()) => {
+                log::info!("[config] applied {} -> {json}", path.to_str().unwrap());
+                world.resource_mut::<local::Reload<Cfg>>().applied = json;
+                world.spawn(cfg.make_mqtt_msg());
+                respond(world, Ok("config applied".into()));
+            }
+            Err(e) => {
+                log::warn!(
+                    "[config] failed to apply {}, reason: {e}",
+                    path.to_str().unwrap()
+                );
+                respond(
+                    world,
+                    Err(vec![ValidationError::new(
+                        ".",
+                        format!("saved but not applied, reason: {e}"),
+                    )]),
+                );
+            }
+        }
+    }
 }
 
 impl<T, Cfg> Default for ConfigMessage<T, Cfg>
 where
-    T: crate::config::ConfigFile<Config = Cfg> + Send + Sync + 'static,
-    Cfg: MessageImpl + Send + Sync + 'static,
+    T: crate::config::HotReload<Config = Cfg> + Send + Sync + 'static,
+    Cfg: MessageImpl + crate::config::Validate + Clone + std::fmt::Debug + Send + Sync + 'static,
 {
     fn default() -> Self {
         Self::new()
@@ -140,18 +308,28 @@ where
 }
 impl<T, Cfg> Plugin for ConfigMessage<T, Cfg>
 where
-    T: crate::config::ConfigFile<Config = Cfg> + Send + Sync + 'static,
-    Cfg: MessageImpl + Send + Sync + 'static,
+    T: crate::config::HotReload<Config = Cfg> + Send + Sync + 'static,
+    Cfg: MessageImpl + crate::config::Validate + Clone + std::fmt::Debug + Send + Sync + 'static,
 {
     fn build(&self, app: &mut bevy_app::App) {
-        app.add_systems(Startup, (ConfigMessage::<T, Cfg>::setup,))
-            .add_systems(
-                Update,
-                (
-                    ConfigMessage::<T, Cfg>::on_load_request,
-                    ConfigMessage::<T, Cfg>::on_save_request,
-                ),
-            );
+        app.add_systems(
+            Startup,
+            (
+                ConfigMessage::<T, Cfg>::setup,
+                ConfigMessage::<T, Cfg>::setup_reload,
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                ConfigMessage::<T, Cfg>::on_load_request,
+                ConfigMessage::<T, Cfg>::on_save_request,
+                ConfigMessage::<T, Cfg>::on_history_request,
+                ConfigMessage::<T, Cfg>::on_rollback_request,
+                ConfigMessage::<T, Cfg>::reload,
+            )
+                .chain(),
+        );
     }
 }
 
@@ -234,17 +412,22 @@ where
     }
 
     fn state_update(
-        mut cmd: Commands,
-        mut ev_reader: EventReader<mqtt::event::IncomingMessage>,
-        mut maybe_state: Option<ResMut<T>>,
+        world: &mut World,
+        ev_reader: &mut SystemState<EventReader<mqtt::event::IncomingMessage>>,
     ) {
-        while let Some(incoming_msg) = ev_reader.read().next() {
-            if let Some(request) = incoming_msg.get::<T::Request>() {
-                if let Some(ref mut state) = maybe_state {
-                    if let Some(res) = T::update_state(request, state) {
-                        cmd.spawn(res.make_mqtt_msg());
-                    }
-                }
+        let requests = ev_reader
+            .get_mut(world)
+            .read()
+            .filter_map(|incoming_msg| incoming_msg.get::<T::Request>())
+            .collect::<Vec<_>>();
+
+        for request in requests {
+            if !world.contains_resource::<T>() {
+                continue;
+            }
+
+            if let Some(res) = T::handle(request, world) {
+                world.spawn(res.make_mqtt_msg());
             }
         }
     }
@@ -262,9 +445,99 @@ where
 mod local {
     use super::*;
 
+    #[derive(Resource)]
+    pub struct Reload<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        /// saved over MQTT, waiting to be applied
+        pub pending: Option<Cfg>,
+        /// JSON of the running config
+        pub applied: String,
+        pub watch: Option<crossbeam_channel::Receiver<()>>,
+    }
+
     #[derive(Debug, Event)]
     pub struct StatusUpdate<Msg: MessageImpl>(Msg);
 
+    pub fn save_error(e: std::io::Error) -> Vec<ValidationError> {
+        vec![ValidationError::new(
+            ".",
+            format!("failed to save config, reason: {e}"),
+        )]
+    }
+
+    /// Outcome of a config save request, published on the response topic of the
+    /// config group as `{"config": <result>}` to tell it apart from the responses of
+    /// group actions.
+    #[derive(Debug)]
+    pub struct ConfigResponse<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        result: Result<AtomicFixedString, Vec<ValidationError>>,
+        _c: PhantomData<Cfg>,
+    }
+    impl<Cfg> ConfigResponse<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        pub fn new(result: Result<AtomicFixedString, Vec<ValidationError>>) -> Self {
+            Self {
+                result,
+                _c: PhantomData::<Cfg>,
+            }
+        }
+    }
+    impl<Cfg> serde::Serialize for ConfigResponse<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            #[derive(serde::Serialize)]
+            struct Wrapped<'a> {
+                config: &'a Result<AtomicFixedString, Vec<ValidationError>>,
+            }
+
+            Wrapped {
+                config: &self.result,
+            }
+            .serialize(serializer)
+        }
+    }
+    impl<'de, Cfg> serde::Deserialize<'de> for ConfigResponse<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            #[derive(serde::Deserialize)]
+            struct Wrapped {
+                config: Result<AtomicFixedString, Vec<ValidationError>>,
+            }
+
+            let Wrapped { config } = serde::Deserialize::deserialize(deserializer)?;
+            Ok(Self::new(config))
+        }
+    }
+    impl<Cfg> MessageInfo for ConfigResponse<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        fn topic() -> AtomicFixedString {
+            crate::namespace().topic(crate::constants::MqttPrefix::Response, Cfg::GROUP)
+        }
+
+        fn qos() -> Qos {
+            Cfg::QOS
+        }
+    }
+
     #[derive(Debug)]
     pub struct SaveCfgMsg<Cfg>(pub Cfg)
     where
@@ -342,4 +615,121 @@ mod local {
             Cfg::QOS
         }
     }
+
+    #[derive(Debug)]
+    pub struct HistoryCfgMsg<Cfg>(PhantomData<Cfg>)
+    where
+        Cfg: MessageImpl + Send + Sync + 'static;
+    impl<Cfg> serde::Serialize for HistoryCfgMsg<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            serializer.serialize_unit()
+        }
+    }
+    impl<'de, Cfg> serde::Deserialize<'de> for HistoryCfgMsg<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        fn deserialize<D>(_: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            Ok(Self(PhantomData))
+        }
+    }
+    impl<Cfg> MessageInfo for HistoryCfgMsg<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        fn topic() -> AtomicFixedString {
+            format!("history_{}", Cfg::topic()).into()
+        }
+
+        fn qos() -> Qos {
+            Cfg::QOS
+        }
+    }
+
+    /// Saved configs of a group, oldest first.
+    #[derive(Debug)]
+    pub struct ConfigHistory<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        entries: Vec<history::Entry>,
+        _c: PhantomData<Cfg>,
+    }
+    impl<Cfg> ConfigHistory<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        pub fn new(entries: Vec<history::Entry>) -> Self {
+            Self {
+                entries,
+                _c: PhantomData::<Cfg>,
+            }
+        }
+    }
+    impl<Cfg> serde::Serialize for ConfigHistory<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            self.entries.serialize(serializer)
+        }
+    }
+    impl<'de, Cfg> serde::Deserialize<'de> for ConfigHistory<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            Ok(Self::new(serde::Deserialize::deserialize(deserializer)?))
+        }
+    }
+    impl<Cfg> MessageInfo for ConfigHistory<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        fn topic() -> AtomicFixedString {
+            format!("{}/history", Cfg::topic()).into()
+        }
+
+        fn qos() -> Qos {
+            Cfg::QOS
+        }
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    pub struct RollbackCfgMsg<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        /// history entry to restore
+        pub id: u32,
+        #[serde(skip)]
+        pub _c: PhantomData<Cfg>,
+    }
+    impl<Cfg> MessageInfo for RollbackCfgMsg<Cfg>
+    where
+        Cfg: MessageImpl + Send + Sync + 'static,
+    {
+        fn topic() -> AtomicFixedString {
+            format!("rollback_{}", Cfg::topic()).into()
+        }
+
+        fn qos() -> Qos {
+            Cfg::QOS
+        }
+    }
 }
@@ -1,5 +1,148 @@
-#[derive(serde::Serialize)]
+use crate::{constants, namespace, AtomicFixedString};
+
+/// Device identifiers published before namespaces that carried the project name.
+const LEGACY_DEVICES: [(&str, &str); 2] = [
+    ("ph-dosing", "triponics-ph-dosing"),
+    ("relay-module", "triponics-relay-module"),
+];
+
+/// Device grouping entities, its identifiers are namespaced like [`unique_id`].
 pub struct Device {
     pub identifiers: &'static [&'static str],
     pub name: &'static str,
 }
+impl serde::Serialize for Device {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        #[derive(serde::Serialize)]
+        struct Namespaced {
+            identifiers: Vec<AtomicFixedString>,
+            name: &'static str,
+        }
+
+        let namespace = crate::namespace();
+        Namespaced {
+            identifiers: self
+                .identifiers
+                .iter()
+                .map(|id| local::device_id(namespace, id))
+                .collect(),
+            name: self.name,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Discovery topic of an entity of `group`,
+/// `<discovery prefix>/<component>/<project>_<device>/<group>_<entity>/config`. The default
+/// namespace keeps `<discovery prefix>/<component>/<entity>/<group>/config` from before
+/// namespaces, so existing installs keep their entities and history.
+pub fn topic(component: &str, group: &str, entity: &str) -> AtomicFixedString {
+    local::topic(crate::namespace(), component, group, entity)
+}
+
+/// `<project>-<device>-<id>`, unique across towers sharing a Home Assistant. The default
+/// namespace keeps `<project>-<id>` from before namespaces.
+pub fn unique_id(id: &str) -> AtomicFixedString {
+    local::unique_id(crate::namespace(), id)
+}
+
+mod local {
+    use super::*;
+
+    fn is_legacy(namespace: &namespace::Config) -> bool {
+        namespace.project.as_ref() == constants::project::NAME
+            && namespace.device.as_ref() == constants::project::DEVICE
+    }
+
+    pub fn topic(
+        namespace: &namespace::Config,
+        component: &str,
+        group: &str,
+        entity: &str,
+    ) -> AtomicFixedString {
+        let prefix = &namespace.prefixes.home_assistant;
+
+        if is_legacy(namespace) {
+            format!("{prefix}/{component}/{entity}/{group}/config").into()
+        } else {
+            format!(
+                "{prefix}/{component}/{}_{}/{group}_{entity}/config",
+                namespace.project, namespace.device
+            )
+            .into()
+        }
+    }
+
+    pub fn unique_id(namespace: &namespace::Config, id: &str) -> AtomicFixedString {
+        if is_legacy(namespace) {
+            format!("{}-{id}", namespace.project).into()
+        } else {
+            format!("{}-{}-{id}", namespace.project, namespace.device).into()
+        }
+    }
+
+    pub fn device_id(namespace: &namespace::Config, id: &str) -> AtomicFixedString {
+        if is_legacy(namespace) {
+            LEGACY_DEVICES
+                .iter()
+                .find(|(current, _)| *current == id)
+                .map_or(id, |(_, legacy)| legacy)
+                .to_string()
+                .into()
+        } else {
+            unique_id(namespace, id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::local;
+    use crate::namespace;
+
+    #[test]
+    fn default_namespace_keeps_legacy_discovery() {
+        let namespace = namespace::Config::default();
+
+        assert_eq!(
+            local::topic(&namespace, "switch", "relay_module", "relay_1").as_ref(),
+            "homeassistant/switch/relay_1/relay_module/config"
+        );
+        assert_eq!(
+            local::unique_id(&namespace, "relay-module_1").as_ref(),
+            "triponics-relay-module_1"
+        );
+        assert_eq!(
+            local::device_id(&namespace, "relay-module").as_ref(),
+            "triponics-relay-module"
+        );
+        assert_eq!(
+            local::device_id(&namespace, "growlight").as_ref(),
+            "growlight"
+        );
+    }
+
+    #[test]
+    fn other_namespaces_are_unique() {
+        let namespace = namespace::Config {
+            device: "1".into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            local::topic(&namespace, "switch", "relay_module", "relay_1").as_ref(),
+            "homeassistant/switch/triponics_1/relay_module_relay_1/config"
+        );
+        assert_eq!(
+            local::unique_id(&namespace, "relay-module_1").as_ref(),
+            "triponics-1-relay-module_1"
+        );
+        assert_eq!(
+            local::device_id(&namespace, "growlight").as_ref(),
+            "triponics-1-growlight"
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Offline access to the cache of messages published while disconnected, for the
+//! command line tool. The running app owns the cache through its own connection.
+
+use std::path::{Path, PathBuf};
+
+use super::{message, ClientCreateOptions};
+
+/// `<cache_dir_path>/<client_id>.db3`
+pub fn filepath(options: &ClientCreateOptions) -> PathBuf {
+    let mut path = options.cache_dir_path.clone();
+    path.push(format!("{}.db3", options.client_id.as_ref()));
+    path
+}
+
+#[derive(Debug)]
+pub struct Stats {
+    pub messages: u64,
+    /// unix timestamps of the oldest and newest cached message
+    pub oldest: Option<i64>,
+    pub newest: Option<i64>,
+    /// total size of the encoded messages
+    pub bytes: u64,
+}
+
+pub fn stats(path: &Path) -> anyhow::Result<Stats> {
+    Ok(
+        open(path)?.query_row(include_str!("../../sql/stats.sql"), (), |row| {
+            Ok(Stats {
+                messages: row.get(0)?,
+                oldest: row.get(1)?,
+                newest: row.get(2)?,
+                bytes: row.get(3)?,
+            })
+        })?,
+    )
+}
+
+/// Discards every cached message, returns how many there were.
+pub fn flush(path: &Path) -> anyhow::Result<usize> {
+    Ok(open(path)?.execute(include_str!("../../sql/clear.sql"), ())?)
+}
+
+/// Every cached message with the unix timestamp it was cached at, oldest first.
+pub fn read_all(path: &Path) -> anyhow::Result<Vec<(i64, message::Message)>> {
+    let conn = open(path)?;
+    let mut stmt = conn.prepare(include_str!("../../sql/read_all.sql"))?;
+    let rows = stmt.query_map((), |row| {
+        Ok((row.get::<usize, i64>(0)?, row.get::<usize, Vec<u8>>(1)?))
+    })?;
+
+    rows.map(|row| {
+        let (time, data) = row?;
+        Ok((time, postcard::from_bytes::<message::Message>(&data)?))
+    })
+    .collect()
+}
+
+fn open(path: &Path) -> anyhow::Result<rusqlite::Connection> {
+    anyhow::ensure!(path.exists(), "no cache at \"{}\"", path.display());
+    Ok(rusqlite::Connection::open_with_flags(
+        path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE,
+    )?)
+}
@@ -24,4 +24,9 @@ impl IncomingMessage {
             None
         }
     }
+
+    /// Undecoded payload of a message on the topic of `T`.
+    pub fn payload<T: MessageInfo>(&self) -> Option<&[u8]> {
+        (self.0.topic() == T::topic().as_ref()).then(|| self.0.payload())
+    }
 }
@@ -1,4 +1,5 @@
 pub mod add_on;
+pub mod cache;
 pub mod event;
 
 mod options;
@@ -25,9 +26,14 @@ use bevy_tokio_tasks::TokioTasksRuntime;
 use futures::StreamExt;
 use tokio::sync::Mutex;
 
-use crate::{config::ConfigFile, constants, helper::AsyncEventExt, log, AtomicFixedString};
+use crate::{
+    config::{ConfigFile, Errors, Validate},
+    constants,
+    helper::AsyncEventExt,
+    log, AtomicFixedString,
+};
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, schemars::JsonSchema)]
 pub struct Config {
     pub create_options: ClientCreateOptions,
     pub connect_options: ClientConnectOptions,
@@ -58,6 +64,37 @@ impl Default for Config {
         }
     }
 }
+impl Validate for Config {
+    fn validate(&self, errors: &mut Errors) {
+        let create = &self.create_options;
+
+        errors.check(
+            !create.server_uri.as_ref().is_empty(),
+            "create_options.server_uri",
+            "must not be empty",
+        );
+        errors.check(
+            !create.client_id.as_ref().is_empty(),
+            "create_options.client_id",
+            "must not be empty",
+        );
+        errors.check(
+            create.incoming_msg_buffer_size > 0,
+            "create_options.incoming_msg_buffer_size",
+            "must be at least 1",
+        );
+        errors.check(
+            !create.restart_interval.is_zero(),
+            "create_options.restart_interval",
+            "must be positive",
+        );
+        errors.check_duration(
+            "create_options.restart_interval",
+            create.restart_interval,
+            Duration::from_secs(60 * 60),
+        );
+    }
+}
 
 pub struct Plugin {
     pub config: Config,
@@ -79,10 +116,7 @@ impl bevy_app::Plugin for Plugin {
             .insert_resource(client_connect_options.clone())
             .insert_resource(local::MqttSubscriptions(Vec::new()))
             .insert_resource(local::MqttIncommingMsgTx(mqtt_incoming_msg_queue))
-            .insert_resource(local::MqttCacheManager::new(
-                client_create_options.client_id.clone(),
-                client_create_options.cache_dir_path.as_path(),
-            ))
+            .insert_resource(local::MqttCacheManager::new(client_create_options))
             .add_event::<event::RestartClient>()
             .add_async_event_receiver(mqtt_incoming_msg_rx)
             .add_systems(
@@ -102,6 +136,25 @@ impl bevy_app::Plugin for Plugin {
             .send_event(event::RestartClient("initial restart"));
     }
 }
+
+/// Heartbeat published while the client is connected.
+#[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct Ping {
+    timestamp: String,
+}
+impl Ping {
+    fn new() -> Self {
+        Self {
+            timestamp: time::OffsetDateTime::now_utc().unix_timestamp().to_string(),
+        }
+    }
+}
+impl add_on::action_message::MessageImpl for Ping {
+    const PREFIX: constants::MqttPrefix = constants::MqttPrefix::Status;
+    const GROUP: &'static str = "ping";
+    const QOS: Qos = Qos::_1;
+}
+
 impl Plugin {
     fn restart_client(
         mut cmd: Commands,
@@ -140,25 +193,6 @@ impl Plugin {
             let ping_interval = Duration::from_secs_f32(keep_alive_interval.as_secs_f32() * 0.6);
             log::debug!("[mqtt] ping interval: {}", ping_interval.as_secs_f32());
 
-            #[derive(Debug, serde::Serialize, serde::Deserialize)]
-            struct Ping {
-                timestamp: String,
-            }
-            impl Ping {
-                fn new() -> Self {
-                    Self {
-                        timestamp: time::OffsetDateTime::now_utc().unix_timestamp().to_string(),
-                    }
-                }
-            }
-            impl add_on::action_message::MessageImpl for Ping {
-                const PREFIX: &'static str = constants::mqtt_prefix::STATUS;
-                const PROJECT: &'static str = constants::project::NAME;
-                const GROUP: &'static str = "ping";
-                const DEVICE: &'static str = constants::project::DEVICE;
-                const QOS: Qos = Qos::_1;
-            }
-
             loop {
                 log::trace!("[mqtt] ping!");
                 client.publish(Ping::new().make_mqtt_msg().into());
@@ -599,26 +633,22 @@ pub mod message {
 }
 
 mod local {
-    use std::{
-        path::{Path, PathBuf},
-        sync::OnceLock,
-    };
+    use std::sync::OnceLock;
 
     use bevy_ecs::system::Resource;
     use tokio::sync::Mutex;
 
     use crate::AtomicFixedString;
 
-    use super::{event, log, message, Qos};
+    use super::{event, log, message, ClientCreateOptions, Qos};
 
     #[derive(Debug, Resource)]
     pub struct MqttCacheManager {
         pub connection: &'static Mutex<rusqlite::Connection>,
     }
     impl MqttCacheManager {
-        pub fn new(client_id: AtomicFixedString, path: &Path) -> Self {
-            let mut path = PathBuf::from(path);
-            path.push(format!("{}.db3", client_id.as_ref()));
+        pub fn new(options: &ClientCreateOptions) -> Self {
+            let path = super::cache::filepath(options);
             std::fs::create_dir_all(path.parent().unwrap()).unwrap();
 
             let conn = rusqlite::Connection::open(path).unwrap();
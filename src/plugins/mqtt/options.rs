@@ -6,7 +6,7 @@ use crate::AtomicFixedString;
 
 use super::PersistenceType;
 
-#[derive(Clone, Resource, serde::Deserialize, serde::Serialize, Debug)]
+#[derive(Clone, Resource, serde::Deserialize, serde::Serialize, Debug, schemars::JsonSchema)]
 pub struct ClientCreateOptions {
     pub server_uri: AtomicFixedString,
     pub client_id: AtomicFixedString,
@@ -16,6 +16,7 @@ pub struct ClientCreateOptions {
         serialize_with = "crate::helper::serde_time::serialize_duration_formatted",
         deserialize_with = "crate::helper::serde_time::deserialize_duration_formatted"
     )]
+    #[schemars(with = "crate::helper::serde_time::schema::Duration")]
     pub restart_interval: Duration,
 
     pub max_buffered_messages: Option<i32>,
@@ -28,8 +29,7 @@ pub struct ClientCreateOptions {
 }
 impl ClientCreateOptions {
     pub(super) fn default_cache_path() -> PathBuf {
-        let mut cache_dir_path = std::env::current_dir().unwrap();
-        cache_dir_path.push("data");
+        let mut cache_dir_path = crate::data_directory().to_path_buf();
         cache_dir_path.push("cache");
         cache_dir_path
     }
@@ -107,13 +107,15 @@ impl From<&ClientCreateOptions> for paho_mqtt::CreateOptions {
 }
 
 #[serde_with::serde_as]
-#[derive(Clone, Resource, serde::Serialize, serde::Deserialize, Debug)]
+#[derive(Clone, Resource, serde::Serialize, serde::Deserialize, Debug, schemars::JsonSchema)]
 pub struct ClientConnectOptions {
     pub clean_start: Option<bool>,
     pub max_inflight: Option<i32>,
     #[serde_as(as = "Option<AsDuration>")]
+    #[schemars(with = "Option<crate::helper::serde_time::schema::Duration>")]
     pub connect_timeout: Option<Duration>,
     #[serde_as(as = "Option<AsDuration>")]
+    #[schemars(with = "Option<crate::helper::serde_time::schema::Duration>")]
     pub keep_alive_interval: Option<Duration>,
 }
 impl From<&ClientConnectOptions> for paho_mqtt::ConnectOptions {
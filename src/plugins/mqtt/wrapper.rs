@@ -6,7 +6,7 @@ pub enum Qos {
     _2 = paho_mqtt::QOS_2,
 }
 
-#[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug, schemars::JsonSchema)]
 pub enum PersistenceType {
     /// Messages are persisted to files in a local directory (default).
     File,
@@ -152,6 +152,13 @@ impl Plugin {
     pub fn disable(world: &mut World) {
         world.remove_resource::<UseStateFile>();
     }
+
+    /// Directory holding a `<FILENAME>.json` file per [`SaveState`] resource.
+    pub fn directory(&self) -> PathBuf {
+        let mut path = crate::data_directory().to_path_buf();
+        path.push(self.dirname);
+        path
+    }
 }
 impl Default for Plugin {
     fn default() -> Self {
@@ -160,8 +167,7 @@ impl Default for Plugin {
 }
 impl bevy_app::Plugin for Plugin {
     fn build(&self, app: &mut bevy_app::App) {
-        let mut path = crate::data_directory().to_path_buf();
-        path.push(self.dirname);
+        let path = self.directory();
 
         std::fs::create_dir_all(&path).unwrap();
         app.init_resource::<UseStateFile>()
@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use bevy_app::{App, Startup};
+use bevy_ecs::system::Commands;
+use schemars::schema::RootSchema;
+
+use crate::{
+    config::ConfigFile,
+    constants::MqttPrefix,
+    log, namespace,
+    plugins::{
+        manager,
+        mqtt::{self, add_on::action_message::MessageImpl},
+    },
+    AtomicFixedString,
+};
+
+/// JSON Schema of a config file or an MQTT message.
+pub struct Entry {
+    /// `file/<FILENAME>` for config files, `<PREFIX>/<GROUP>` for messages
+    pub name: AtomicFixedString,
+    pub schema: RootSchema,
+}
+impl Entry {
+    fn config<T: ConfigFile>() -> Self {
+        Self::new(format!("file/{}", T::FILENAME), T::config_schema())
+    }
+
+    fn message<M: MessageImpl>() -> Self {
+        Self::new(
+            format!("{}/{}", M::PREFIX.name(), M::GROUP),
+            schemars::schema_for!(M),
+        )
+    }
+
+    fn new(name: String, mut schema: RootSchema) -> Self {
+        // type names like `Config` or `Update` mean little outside of their module
+        schema.schema.metadata().title = Some(name.clone());
+
+        Self {
+            name: name.into(),
+            schema,
+        }
+    }
+
+    pub fn topic(&self) -> AtomicFixedString {
+        let namespace = crate::namespace();
+        format!(
+            "{}/{}/schema/{}",
+            namespace.prefixes.get(MqttPrefix::Config),
+            namespace.project,
+            self.name
+        )
+        .into()
+    }
+}
+
+/// Every config file and MQTT message of the app.
+pub fn all() -> Vec<Entry> {
+    use manager::{
+        aeroponic_spray, growlight, nutrient_dosing, ph_dosing, relay_module, water_quality_sensor,
+    };
+
+    vec![
+        Entry::config::<namespace::Namespace>(),
+        Entry::config::<mqtt::Plugin>(),
+        Entry::config::<manager::AeroponicSprayManager>(),
+        Entry::config::<manager::PhDosingManager>(),
+        Entry::config::<manager::GrowlightManager>(),
+        Entry::config::<manager::NutrientDosingManager>(),
+        Entry::message::<crate::local::ExitMsg>(),
+        Entry::message::<mqtt::Ping>(),
+        Entry::message::<aeroponic_spray::Config>(),
+        Entry::message::<aeroponic_spray::action::Update>(),
+        Entry::message::<aeroponic_spray::action::Response>(),
+        Entry::message::<aeroponic_spray::action::AeroponicSprayerStatus>(),
+        Entry::message::<growlight::Config>(),
+        Entry::message::<growlight::action::Update>(),
+        Entry::message::<growlight::action::MqttResponse>(),
+        Entry::message::<growlight::action::StatusMqtt>(),
+        Entry::message::<ph_dosing::Config>(),
+        Entry::message::<ph_dosing::action::Update>(),
+        Entry::message::<ph_dosing::action::Response>(),
+        Entry::message::<ph_dosing::action::Status>(),
+        Entry::message::<ph_dosing::action::QueueStatus>(),
+        Entry::message::<ph_dosing::action::Database>(),
+        Entry::message::<nutrient_dosing::Config>(),
+        Entry::message::<nutrient_dosing::action::Update>(),
+        Entry::message::<nutrient_dosing::action::Response>(),
+        Entry::message::<nutrient_dosing::action::Status>(),
+        Entry::message::<relay_module::action::Update>(),
+        Entry::message::<relay_module::action::MqttResponse>(),
+        Entry::message::<relay_module::action::RelayStatus>(),
+        Entry::message::<water_quality_sensor::action::MqttStatus>(),
+        Entry::message::<water_quality_sensor::action::Database>(),
+    ]
+}
+
+/// Prints every schema as one JSON object keyed by name, or writes them to
+/// `<dir>/<name>.json`.
+pub fn export(dir: Option<&Path>) -> anyhow::Result<()> {
+    let entries = all();
+
+    match dir {
+        Some(dir) => {
+            for Entry { name, schema } in entries {
+                let mut filepath = dir.join(name.as_ref());
+                filepath.set_extension("json");
+                std::fs::create_dir_all(filepath.parent().unwrap())?;
+                std::fs::write(&filepath, serde_json::to_string_pretty(&schema)?)?;
+                println!("{}", filepath.display());
+            }
+        }
+        None => {
+            let schemas = entries
+                .into_iter()
+                .map(|Entry { name, schema }| (name.to_string(), schema))
+                .collect::<std::collections::BTreeMap<_, _>>();
+            println!("{}", serde_json::to_string_pretty(&schemas)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Publishes every schema retained on `<config prefix>/<project>/schema/<name>`.
+pub struct Plugin;
+impl bevy_app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, publish);
+    }
+}
+
+fn publish(mut cmd: Commands) {
+    let entries = all();
+    log::debug!("[schema] publish {} schemas", entries.len());
+
+    cmd.spawn_batch(
+        entries
+            .into_iter()
+            .map(|entry| mqtt::message::Message {
+                topic: entry.topic(),
+                payload: serde_json::to_vec(&entry.schema).unwrap().into(),
+                qos: mqtt::Qos::_1,
+                retained: true,
+            })
+            .collect::<Vec<_>>(),
+    );
+}
@@ -0,0 +1 @@
+DELETE FROM cache
\ No newline at end of file
@@ -0,0 +1 @@
+SELECT time, data FROM cache ORDER BY id ASC
\ No newline at end of file
@@ -0,0 +1 @@
+SELECT COUNT(id), MIN(time), MAX(time), COALESCE(SUM(LENGTH(data)), 0) FROM cache
\ No newline at end of file